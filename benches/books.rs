@@ -0,0 +1,99 @@
+//! Benchmark of data access for the books handlers.
+//!
+//! This compares two ways to read all books from many concurrent
+//! tokio tasks, which is what happens when many requests arrive:
+//!
+//! * `thread_spawn_std_mutex`: the prior way, which spawns a new OS
+//!   thread per request, then locks a `std::sync::Mutex`, then joins.
+//!
+//! * `in_memory_book_store`: the current way, which awaits a tokio
+//!   read-write lock in our `InMemoryBookStore`, without any new thread.
+//!
+//! Run:
+//!
+//! ```sh
+//! cargo bench --bench books
+//! ```
+
+/// Use criterion for statistics-driven benchmarks.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+/// Use std capabilities for the prior way of data access.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// See file book.rs, which defines the `Book` struct.
+#[path = "../src/book.rs"]
+mod book;
+use crate::book::Book;
+
+/// See file data.rs, which defines the `BookStore` data store.
+/// This benchmark uses only part of the module, so allow the rest.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/data.rs"]
+mod data;
+use crate::data::{demo_books, BookStore, InMemoryBookStore};
+
+/// How many concurrent requests each benchmark iteration simulates.
+const REQUESTS: u64 = 64;
+
+/// The prior way: spawn an OS thread, lock a std mutex, then join.
+async fn get_books_with_thread_spawn(books: Arc<Mutex<HashMap<u32, Book>>>) -> Vec<Book> {
+    std::thread::spawn(move || {
+        let data = books.lock().unwrap();
+        let mut books = data.values().cloned().collect::<Vec<_>>();
+        books.sort_by(|a, b| a.title.cmp(&b.title));
+        books
+    })
+    .join()
+    .unwrap()
+}
+
+/// The current way: await our data store, which uses a tokio lock.
+async fn get_books_with_store(store: Arc<dyn BookStore>) -> Vec<Book> {
+    let mut books = store.list().await.unwrap();
+    books.sort_by(|a, b| a.title.cmp(&b.title));
+    books
+}
+
+fn bench_get_books(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mutex = Arc::new(Mutex::new(
+        demo_books().into_iter().map(|book| (book.id, book)).collect::<HashMap<_, _>>(),
+    ));
+    let store: Arc<dyn BookStore> = Arc::new(InMemoryBookStore::new(demo_books()));
+
+    let mut group = c.benchmark_group("get_books");
+    group.throughput(Throughput::Elements(REQUESTS));
+    group.bench_function("thread_spawn_std_mutex", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let tasks = (0..REQUESTS)
+                    .map(|_| tokio::spawn(get_books_with_thread_spawn(mutex.clone())))
+                    .collect::<Vec<_>>();
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            })
+        })
+    });
+    group.bench_function("in_memory_book_store", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                let tasks = (0..REQUESTS)
+                    .map(|_| tokio::spawn(get_books_with_store(store.clone())))
+                    .collect::<Vec<_>>();
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_get_books);
+criterion_main!(benches);
@@ -11,5 +11,5 @@ async fn main() {
 /// Create our application with one route that prints "Hello, World!"
 pub fn app() -> axum::Router {
     axum::Router::new()
-        .route("/", axum::routing::get(|| async { "Hello, World!" }))
+        .route("/", get(|| async { "Hello, World!" }))
 }
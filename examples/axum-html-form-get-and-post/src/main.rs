@@ -23,70 +23,93 @@ pub fn app() -> axum::Router {
         )
 }
 
-/////
-/// Demo HTML form GET and POST
-/////
+////
+// Demo HTML form GET and POST
+////
 
 /// See file book.rs, which defines the `Book` struct.
 mod book;
 use crate::book::Book;
 
+/// Use askama templates, which askama checks when we compile, and which
+/// escape each value by default, such as "<" as "&#60;".
+use askama::Template;
+
+/// Template for the base layout of each page, with the shared head and
+/// headline, then the page's content, which is a partial template, such
+/// as `BookFormPartial`. The partial escapes its own values, so the layout
+/// shows it as is, by using the filter `safe`.
+#[derive(Template)]
+#[template(ext = "html", source = r#"<!doctype html>
+<html>
+    <head>
+        <title>{{ title }}</title>
+    </head>
+    <body>
+        <h1>{{ title }}</h1>
+{{ content|safe }}
+    </body>
+</html>
+"#)]
+pub struct Layout<'a, T: Template> {
+    pub title: &'a str,
+    pub content: T,
+}
+
+/// Template for the book form, which is a partial of a page.
+#[derive(Template)]
+#[template(ext = "html", source = r#"        <form method="post" action="/demo-form">
+            <p>
+                <label for="title">
+                    Title:
+                    <br>
+                    <input id="title" name="title">
+                </label>
+            </p>
+            <p>
+                <label for="author">
+                    Author:
+                    <br>
+                    <input id="author" name="author">
+                </label>
+            </p>
+            <p>
+                <input type="submit">
+            </p>
+        </form>"#)]
+pub struct BookFormPartial;
+
+/// Template for a book that a form submitted, which is a partial of a page.
+#[derive(Template)]
+#[template(ext = "html", source = r#"        <p>Title: {{ book.title }}</p>
+        <p>Author: {{ book.author }}</p>"#)]
+pub struct BookPartial {
+    pub book: Book,
+}
+
+/// Render a page, which is a partial in our layout. If a template fails,
+/// then respond with 500 Internal Server Error, rather than panic.
+fn page(title: &str, content: impl Template) -> Result<axum::response::Html<String>, axum::http::StatusCode> {
+    Layout { title, content }
+        .render()
+        .map(axum::response::Html)
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
 /// axum handler for "GET /demo-form" which responds with a form.
 /// This demo shows how to write a typical HTML form with input fields.
-pub async fn get_demo_form() ->  axum::response::Html<&'static str> {
-    r#"
-    <!doctype html>
-    <html>
-        <head>
-            <title>Book Form</title>
-        </head>
-        <body>
-            <h1>Book Form</h1>
-            <form method="post" action="/demo-form">
-                <p>
-                    <label for="title">
-                        Title:
-                        <br>
-                        <input name="title">
-                    </label>
-                </p>
-                <p>
-                    <label for="author">
-                        Author:
-                        <br>
-                        <input name="author">
-                    </label>
-                </p>
-                <p>
-                    <input type="submit">
-                </p?
-            </form>
-        </body>
-    </html>
-    "#.into()
+pub async fn get_demo_form() -> Result<axum::response::Html<String>, axum::http::StatusCode> {
+    page("Book Form", BookFormPartial)
 }
 
 /// axum handler for "POST /demo-form" which submits an HTML form.
-/// This demo shows how extract a form submission to a struct.
+/// This demo shows how extract a form submission to a struct,
+/// then show it by using a template, which escapes each value.
 pub async fn post_demo_form(
     form: axum::extract::Form<Book>
-) -> axum::response::Html<String> {
+) -> Result<axum::response::Html<String>, axum::http::StatusCode> {
     let book: Book = form.0;
-    format!(
-        r#"
-        <!doctype html>
-        <html>
-            <head>
-                <title>Book</title>
-            </head>
-            <body>
-                <h1>Book</h1>
-                {:?}
-            </body>
-        </html>
-        "#,
-        &book
-    ).into()
+    page("Book", BookPartial { book })
 }
 
 #[cfg(test)]
@@ -98,9 +121,21 @@ mod tests {
     async fn get_demo_form() {
         let server = TestServer::new(app()).unwrap();
         let response_text = server.get("/demo-form").await.text();
+        assert!(response_text.contains("<title>Book Form</title>"));
         assert!(response_text.contains("<form method=\"post\" action=\"/demo-form\">"));
     }
 
+    #[tokio::test]
+    async fn post_demo_form_escapes_values() {
+        let server = TestServer::new(app()).unwrap();
+        let data = [
+            ["title", "<script>"],
+            ["author", "bravo"],
+        ];
+        let response_text = server.post("/demo-form").form(&data).await.text();
+        assert!(response_text.contains("<p>Title: &#60;script&#62;</p>"));
+    }
+
     #[tokio::test]
     async fn post_demo_form() {
         let server = TestServer::new(app()).unwrap();
@@ -109,6 +144,7 @@ mod tests {
             ["author", "bravo"],
         ];
         let response_text = server.post("/demo-form").form(&data).await.text();
-        assert!(response_text.contains("Book { title: \"alfa\", author: \"bravo\" }"));
+        assert!(response_text.contains("<p>Title: alfa</p>"));
+        assert!(response_text.contains("<p>Author: bravo</p>"));
     }
 }
@@ -18,7 +18,7 @@ pub fn app() -> axum::Router {
 /// axum handler for "GET /string.html" which responds with a string.
 /// The `Html` type sets an HTTP header content-type of `text/html`.
 pub async fn string_html() -> axum::response::Html<&'static str> {
-    "<html><body><h1>Headline</h1><p>Paragraph</b></body></html>".into()
+    "<html><body><h1>Headline</h1><p>Paragraph</p></body></html>".into()
 }
 
 #[cfg(test)]
@@ -29,6 +29,6 @@ mod tests {
     #[tokio::test]
     async fn test() {
         let server = TestServer::new(app()).unwrap();
-        server.get("/string.html").await.assert_text("<html><body><h1>Headline</h1><p>Paragraph</b></body></html>")
+        server.get("/string.html").await.assert_text("<html><body><h1>Headline</h1><p>Paragraph</p></body></html>")
     }
 }
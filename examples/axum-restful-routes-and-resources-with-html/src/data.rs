@@ -20,8 +20,7 @@ use crate::book::Book;
 // ```
 // async fn example() {
 //     thread::spawn(move || {
-//         match DATA.lock() {
-            Ok(data) => {
+//         let data = DATA.lock().unwrap();
 //         …
 // }).join().unwrap()
 // ```
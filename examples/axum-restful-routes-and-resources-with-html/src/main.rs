@@ -52,6 +52,75 @@ use crate::data::DATA;
 /// Use Thread for spawning a thread e.g. to acquire our crate::DATA mutex lock.
 use std::thread;
 
+/// Use askama templates, which askama checks when we compile, and which
+/// escape each value by default, such as "<" as "&#60;".
+use askama::Template;
+
+/// Template for the base layout of each page, with the shared head and
+/// headline, then the page's content, which is a partial template, such
+/// as `BooksPartial`. The partial escapes its own values, so the layout
+/// shows it as is, by using the filter `safe`.
+#[derive(Template)]
+#[template(ext = "html", source = r#"<!doctype html>
+<html>
+    <head>
+        <title>{{ title }}</title>
+    </head>
+    <body>
+        <h1>{{ title }}</h1>
+{{ content|safe }}
+    </body>
+</html>
+"#)]
+pub struct Layout<'a, T: Template> {
+    pub title: &'a str,
+    pub content: T,
+}
+
+/// Template for books, one paragraph per book, which is a partial of a page.
+#[derive(Template)]
+#[template(ext = "html", source = "{% for book in books %}<p>{{ book }}</p>\n{% endfor %}")]
+pub struct BooksPartial<'a> {
+    pub books: Vec<&'a Book>,
+}
+
+/// Template for a message, such as what a change did, which is a partial of a page.
+#[derive(Template)]
+#[template(ext = "html", source = "<p>{{ message }}</p>")]
+pub struct MessagePartial {
+    pub message: String,
+}
+
+/// Template for a form to edit a book, which is a partial of a page.
+#[derive(Template)]
+#[template(ext = "html", source = r#"<form method="patch" action="/books/{{ book.id }}/edit">
+<input type="hidden" name="id" value="{{ book.id }}">
+<p><input name="title" value="{{ book.title }}"></p>
+<p><input name="author" value="{{ book.author }}"></p>
+<input type="submit" value="Save">
+</form>
+"#)]
+pub struct BookFormPartial<'a> {
+    pub book: &'a Book,
+}
+
+/// An HTML page, or 500 Internal Server Error if its template fails.
+type Page = Result<axum::response::Html<String>, axum::http::StatusCode>;
+
+/// Render a page, which is a partial in our layout. If a template fails,
+/// then respond with 500 Internal Server Error, rather than panic.
+fn page(title: &str, content: impl Template) -> Page {
+    Layout { title, content }
+        .render()
+        .map(axum::response::Html)
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Render a page with a message, such as what a change did.
+fn message_page(title: &str, message: String) -> Page {
+    page(title, MessagePartial { message })
+}
+
 /// To access data, create a thread, spawn it, then get the lock.
 /// When you're done, then join the thread with its parent thread.
 #[allow(dead_code)]
@@ -64,16 +133,13 @@ async fn print_data() {
 /// axum handler for "GET /books" which responds with a resource page.
 /// This demo uses our DATA; a production app could use a database.
 /// This demo must clone the DATA in order to sort items by title.
-pub async fn get_books() -> axum::response::Html<String> {
+pub async fn get_books() -> Page {
     thread::spawn(move || {
-        match DATA.lock() {
-            Ok(data) => {
+        let data = DATA.lock().unwrap();
         let mut books = data.values().collect::<Vec<_>>().clone();
         books.sort_by(|a, b| a.title.cmp(&b.title));
-        books.iter().map(|&book|
-            format!("<p>{}</p>\n", &book)
-        ).collect::<String>()
-    }).join().unwrap().into()
+        page("Books", BooksPartial { books })
+    }).join().unwrap()
 }
 
 /// axum handler for "POST /books" which creates a new book resource.
@@ -81,71 +147,66 @@ pub async fn get_books() -> axum::response::Html<String> {
 /// 
 pub async fn post_books(
     axum::extract::Form(book_change): axum::extract::Form<Book>
-) -> axum::response::Html<String> {
+) -> Page {
     thread::spawn(move || {
-        match DATA.lock() {
-            Ok(mut data) => {
+        let mut data = DATA.lock().unwrap();
         let id = data.keys().max().unwrap() + 1;
         let book = Book { id, ..book_change };
         data.insert(id, book.clone());
-        format!("Post a new book with new id {}: {}", &id, &book)
-    }).join().unwrap().into()
+        message_page("Post book", format!("Post a new book with new id {}: {}", &id, &book))
+    }).join().unwrap()
 }
 
 /// axum handler for "GET /books/{id}" which responds with one resource HTML page.
 /// This demo app uses our crate::DATA variable, and iterates on it to find the id.
 pub async fn get_books_id(
     axum::extract::Path(id): axum::extract::Path<u32>
-) -> axum::response::Html<String> {
+) -> Page {
     thread::spawn(move || {
-        match DATA.lock() {
-            Ok(data) => {
+        let data = DATA.lock().unwrap();
         match data.get(&id) {
-            Some(book) => format!("<p>{}</p>\n", &book),
-            None => format!("<p>Book id {} not found</p>", id),
+            Some(book) => page("Book", BooksPartial { books: vec![book] }),
+            None => message_page("Not found", format!("Book id {} not found", id)),
         }
-    }).join().unwrap().into()
+    }).join().unwrap()
 }
 
 /// axum handler for "PUT /books/{id}" which sets a specific book resource.
 /// This demo shows how axum can extract JSON data into a Book struct.
 pub async fn put_books_id(
     axum::extract::Form(book): axum::extract::Form<Book>
-) -> axum::response::Html<String> {
+) -> Page {
     thread::spawn(move || {
-        match DATA.lock() {
-            Ok(mut data) => {
+        let mut data = DATA.lock().unwrap();
         data.insert(book.id, book.clone());
-        format!("Put book: {}", &book)
-    }).join().unwrap().into()
+        message_page("Put book", format!("Put book: {}", &book))
+    }).join().unwrap()
 }
 
 /// axum handler for "DELETE /books/{id}" which destroys a resource.
 /// This demo extracts an id, then deletes the book in the DATA store.
 pub async fn delete_books_id(
     axum::extract::Path(id): axum::extract::Path<u32>
-) -> axum::response::Html<String> {
+) -> Page {
     thread::spawn(move || {
-        match DATA.lock() {
-            Ok(mut data) => {
+        let mut data = DATA.lock().unwrap();
         if data.contains_key(&id) {
             data.remove(&id);
-            format!("Delete book id: {}", &id)
+            message_page("Delete book", format!("Delete book id: {}", &id))
         } else {
-            format!("Book id not found: {}", &id)
+            message_page("Not found", format!("Book id not found: {}", &id))
         }
-    }).join().unwrap().into()
+    }).join().unwrap()
 }
 
 /// axum handler for "PATCH /books/{id}" which updates attributes.
 /// This demo shows how to mutate the book attributes in the DATA store.
 pub async fn patch_books_id(
     axum::extract::Form(book_change): axum::extract::Form<BookChange>
-) -> axum::response::Html<String> {
+) -> Page {
     thread::spawn(move || {
         let id = book_change.id;
-        match DATA.lock() {
-            Ok(mut data) => {
+        let mut data = DATA.lock().unwrap();
         if let Some(resource) = data.get_mut(&id) {
             if let Some(title) = book_change.title {
                 resource.title = title;
@@ -153,51 +214,36 @@ pub async fn patch_books_id(
             if let Some(author) = book_change.author {
                 resource.author = author;
             }
-            format!("Patch book id: {}", &id)
+            message_page("Patch book", format!("Patch book id: {}", &id))
         } else {
-            format!("Book id not found: {}", &id)
+            message_page("Not found", format!("Book id not found: {}", &id))
         }
-    }).join().unwrap().into()
+    }).join().unwrap()
 }
 
 /// axum handler for "GET /books/{id}/edit" which responds with a form.
 /// This demo shows how to write a typical HTML form with input fields.
 pub async fn get_books_id_with_edit_form(
     axum::extract::Path(id): axum::extract::Path<u32>
-) -> axum::response::Html<String> {
+) -> Page {
     thread::spawn(move || {
-        match DATA.lock() {
-            Ok(data) => {
+        let data = DATA.lock().unwrap();
         match data.get(&id) {
-            Some(book) => format!(
-                concat!(
-                    "<form method=\"patch\" action=\"/books/{}/edit\">\n",
-                    "<input type=\"hidden\" name=\"id\" value=\"{}\">\n",
-                    "<p><input name=\"title\" value=\"{}\"></p>\n",
-                    "<p><input name=\"author\" value=\"{}\"></p>\n",
-                    "<input type=\"submit\" value=\"Save\">\n",
-                    "</form>\n"
-                ),
-                &book.id,
-                &book.id,
-                &book.title,
-                &book.author
-            ),
-            None => format!("<p>Book id {} not found</p>", id),
+            Some(book) => page("Edit book", BookFormPartial { book }),
+            None => message_page("Not found", format!("Book id {} not found", id)),
         }
-    }).join().unwrap().into()
+    }).join().unwrap()
 }
 
 /// axum handler for "PATCH /books/{id}/edit" which updates attributes.
 /// This demo shows how to do HTML form submission then update attributes.
 pub async fn patch_books_id_with_edit_form(
     form: axum::extract::Form<BookChange>
-) -> axum::response::Html<String> {
+) -> Page {
     let book_change: BookChange = form.0;
     thread::spawn(move || {
         let id = book_change.id;
-        match DATA.lock() {
-            Ok(mut data) => {
+        let mut data = DATA.lock().unwrap();
         if data.contains_key(&id) {
             if let Some(title) = book_change.title {
                 data.get_mut(&id).unwrap().title = title.clone();
@@ -205,11 +251,11 @@ pub async fn patch_books_id_with_edit_form(
             if let Some(author) = book_change.author {
                 data.get_mut(&id).unwrap().title = author.clone();
             }
-            format!("Patch book id: {}", &id)
+            message_page("Patch book", format!("Patch book id: {}", &id))
         } else {
-            format!("Book id not found: {}", &book_change.id)
+            message_page("Not found", format!("Book id not found: {}", &book_change.id))
         }
-    }).join().unwrap().into()
+    }).join().unwrap()
 }
 
 #[cfg(test)]
@@ -220,7 +266,9 @@ mod tests {
     #[tokio::test]
     async fn get_books() {
         let server = TestServer::new(app()).unwrap();
-        server.get("/books").await.assert_text("<p>Antigone by Sophocles</p>\n<p>Beloved by Toni Morrison</p>\n<p>Candide by Voltaire</p>\n");
+        let response_text = server.get("/books").await.text();
+        assert!(response_text.contains("<title>Books</title>"));
+        assert!(response_text.contains("<p>Antigone by Sophocles</p>\n<p>Beloved by Toni Morrison</p>\n<p>Candide by Voltaire</p>\n"));
     }
 
     // #[tokio::test]
@@ -10,6 +10,14 @@ pub struct Book {
     pub author: String,
 }
 
+// Demo new book structure, for a request that creates a book.
+// The server assigns the id, so any id in the request is ignored.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, Hash, PartialEq)]
+pub struct NewBook {
+    pub title: String,
+    pub author: String,
+}
+
 // Display the book using the format "{title} by {author}".
 // This is a typical Rust trait and is not axum-specific.
 impl std::fmt::Display for Book {
@@ -1,11 +1,16 @@
-// Use Deserialize to convert e.g. from request JSON into Book struct.
+// Use Deserialize to convert e.g. from request JSON into BookChange struct.
 use serde::{Serialize, Deserialize};
 
-// Demo book patch structure with some example fields for id, title, author.
+// Demo book change structure, which is a JSON Merge Patch (RFC 7396)
+// for a book, in typed form, such as `{"title": "Elektra"}`.
+//
+// Each field is optional; a missing field means no change. The id comes
+// from the request path, so the id here is optional, and must match.
 // A production app could prefer an id to be type u32, UUID, etc.
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, Hash, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct BookChange {
-    pub id: u32,
+    pub id: Option<u32>,
     pub title: Option<String>,
     pub author: Option<String>,
 }
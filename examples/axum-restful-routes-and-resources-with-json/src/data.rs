@@ -1,33 +1,72 @@
-// Use Lazy for creating a global variable e.g. our DATA.
-use std::sync::LazyLock;
+// Use Arc for sharing our data store with each handler as axum state.
+use std::sync::Arc;
 
-// Use Mutex for thread-safe access to a variable e.g. our DATA.
-use std::sync::Mutex;
+// Use RwLock for async access to a variable e.g. our books.
+// A tokio lock lets a handler await the lock without blocking
+// a tokio worker thread, and without spawning a new OS thread.
+use tokio::sync::RwLock;
 
-// Use HashMap for storing data as key-value pairs e.g. our DATA.
+// Use HashMap for storing data as key-value pairs e.g. our books.
 use std::collections::HashMap;
 
+// Use an atomic counter for allocating book ids e.g. our next_id.
+use std::sync::atomic::{AtomicU32, Ordering};
+
 // Use the Book struct.
 use crate::book::Book;
 
-// Create a data store as a global variable with `Lazy` and `Mutex`.
+// The application state that axum gives to any handler that asks for it.
+// Each app has its own data store, so each test has its own books.
+pub type AppState = Arc<Data>;
+
+// A data store of books.
 //
 // This demo implementation uses a `HashMap` for ease and speed.
 // The map key is a primary key for lookup; the map value is a Book.
 //
-// To access data, create a thread, spawn it, and acquire the lock:
+// To access data, await the lock, for reading or for writing:
 //
 // ```
-// async fn example() {
-//     thread::spawn(move || {
-//         if let data = Ok(DATA.lock());
-//         …
-// }).join().unwrap()
+// async fn example(axum::extract::State(data): axum::extract::State<AppState>) {
+//     let books = data.books.read().await;
+//     …
+// }
 // ```
-pub static DATA: LazyLock<Mutex<HashMap<u32, Book>>> = LazyLock::new(|| Mutex::new(
-    HashMap::from([
-        (1, Book { id: 1, title: "Antigone".into(), author: "Sophocles".into()}),
-        (2, Book { id: 2, title: "Beloved".into(), author: "Toni Morrison".into()}),
-        (3, Book { id: 3, title: "Candide".into(), author: "Voltaire".into()}),
-    ])
-));
+#[derive(Debug)]
+pub struct Data {
+    pub books: RwLock<HashMap<u32, Book>>,
+
+    // The next book id, which only ever increases, so we never reuse an id,
+    // even after a delete. The seed books use ids 1 to 3.
+    //
+    // Allocate an id while holding the books write lock, so the new book and
+    // its id appear together.
+    next_id: AtomicU32,
+}
+
+impl Data {
+    // Allocate a new book id.
+    pub fn next_id(&self) -> u32 {
+        self.next_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    // Record that a book id is in use, such as by "PUT /books/{id}",
+    // so that we never allocate that id for a new book.
+    pub fn reserve_id(&self, id: u32) {
+        self.next_id.fetch_max(id.saturating_add(1), Ordering::SeqCst);
+    }
+}
+
+// Create a data store with our demo books.
+impl Default for Data {
+    fn default() -> Self {
+        Data {
+            books: RwLock::new(HashMap::from([
+                (1, Book { id: 1, title: "Antigone".into(), author: "Sophocles".into()}),
+                (2, Book { id: 2, title: "Beloved".into(), author: "Toni Morrison".into()}),
+                (3, Book { id: 3, title: "Candide".into(), author: "Voltaire".into()}),
+            ])),
+            next_id: AtomicU32::new(4),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+// Use Hash to derive an entity tag from the contents of a book.
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// Use the Book struct.
+use crate::book::Book;
+
+// A strong entity tag for a book, such as `"5f1c0e3a9b2d4c68"`.
+//
+// This example's data store has no version numbers, so we derive the tag
+// from the contents of the book, which changes whenever the book does.
+pub fn etag(book: &Book) -> String {
+    let mut hasher = DefaultHasher::new();
+    book.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+// Does a request's `If-Match` header match the current book, if any?
+//
+// A request without `If-Match` always matches. The value "*" matches
+// any current book. Otherwise the value is a list of entity tags, and
+// one must be the book's tag; a weak tag such as `W/"…"` never matches.
+pub fn if_match(headers: &axum::http::HeaderMap, book: Option<&Book>) -> bool {
+    let Some(value) = headers.get(axum::http::header::IF_MATCH) else {
+        return true;
+    };
+    let (Ok(value), Some(book)) = (value.to_str(), book) else {
+        return false;
+    };
+    value.trim() == "*" || value.split(',').any(|tag| tag.trim() == etag(book))
+}
@@ -0,0 +1,188 @@
+// Use Lazy for creating a global variable e.g. our KEYS.
+use std::sync::LazyLock;
+
+// Use Mutex for access to our KEYS. We never hold the lock across an
+// await, so a std lock is fine.
+use std::sync::Mutex;
+
+// Use HashMap for storing first responses keyed by client and key.
+use std::collections::HashMap;
+
+// Use Hash to fingerprint a request, so a retry must be identical.
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// Use Instant and Duration for each first response's time to live.
+use std::time::{Duration, Instant};
+
+// Use axum capabilities for responses.
+use axum::response::{IntoResponse, Response};
+
+// Use the Problem struct for errors.
+use crate::problem::Problem;
+
+// The request header that a client sends with a unique key per operation.
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+// How long we keep a first response, so a client can retry within that time.
+const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// The maximum size of a request body that we fingerprint.
+const MAX_BODY_LEN: usize = 2 * 1024 * 1024;
+
+// The request headers that change what a request means, so we fingerprint
+// them too. A retry with another `If-Match` has another precondition, and
+// a retry with another `Accept` asks for another response format.
+const FINGERPRINT_HEADERS: [axum::http::HeaderName; 4] = [
+    axum::http::header::IF_MATCH,
+    axum::http::header::IF_NONE_MATCH,
+    axum::http::header::CONTENT_TYPE,
+    axum::http::header::ACCEPT,
+];
+
+// A record of a request: its fingerprint, and its first response,
+// or `None` while the first request is still in progress.
+#[derive(Clone)]
+struct Record {
+    fingerprint: u64,
+    response: Option<Saved>,
+    expires: Instant,
+}
+
+// A first response, which we replay for a retry.
+#[derive(Clone)]
+struct Saved {
+    status: axum::http::StatusCode,
+    headers: axum::http::HeaderMap,
+    body: axum::body::Bytes,
+}
+
+// Create the records as a global variable with `Lazy` and `Mutex`.
+//
+// The map key is the client, such as its `Authorization` header, and the
+// `Idempotency-Key` header; the map value is the record. We record a
+// request before we run it, so a concurrent retry can't run it twice.
+static KEYS: LazyLock<Mutex<HashMap<(String, String), Record>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// A request that we run with a key. If the request never finishes, such as
+// when the client disconnects, then dropping this drops its record, so a
+// retry can run, rather than get 409 until the record expires.
+struct Pending {
+    id: (String, String),
+    finished: bool,
+}
+
+impl Pending {
+    // Keep the first response, or drop the record if there's no response
+    // to keep, such as for a server error, so the client can retry.
+    fn finish(mut self, response: Option<Saved>) {
+        self.finished = true;
+        let mut keys = KEYS.lock().unwrap_or_else(|err| err.into_inner());
+        match response {
+            Some(response) => {
+                if let Some(record) = keys.get_mut(&self.id) {
+                    record.response = Some(response);
+                }
+            }
+            None => {
+                keys.remove(&self.id);
+            }
+        }
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if !self.finished {
+            KEYS.lock().unwrap_or_else(|err| err.into_inner()).remove(&self.id);
+        }
+    }
+}
+
+// axum middleware that honors an `Idempotency-Key` header on an unsafe
+// request, such as "POST /books", which a client may retry after a network
+// failure. A retry with the same method, path, headers, and body replays
+// the first response; a retry while the first request is still in progress
+// gets 409; a reuse of the key with a different request gets 422.
+pub async fn idempotency(request: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    let key = match request.headers().get(IDEMPOTENCY_KEY).and_then(|key| key.to_str().ok()) {
+        Some(key) if !request.method().is_safe() => key.to_string(),
+        _ => return next.run(request).await,
+    };
+    let client = request
+        .headers()
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let uri = request.uri().clone();
+    let (parts, body) = request.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, MAX_BODY_LEN).await else {
+        return Problem::new(
+            "unreadable-body",
+            "Unreadable request body",
+            axum::http::StatusCode::BAD_REQUEST,
+            "The request body is unreadable or too large",
+            &uri,
+        )
+        .into_response();
+    };
+    let mut hasher = DefaultHasher::new();
+    (parts.method.as_str(), parts.uri.to_string(), &body[..]).hash(&mut hasher);
+    for name in &FINGERPRINT_HEADERS {
+        let values = parts.headers.get_all(name).iter().map(|value| value.as_bytes()).collect::<Vec<_>>();
+        values.hash(&mut hasher);
+    }
+    let fingerprint = hasher.finish();
+    let id = (client, key);
+    let record = {
+        let mut keys = KEYS.lock().unwrap_or_else(|err| err.into_inner());
+        let now = Instant::now();
+        keys.retain(|_, record| record.expires > now);
+        let record = keys.get(&id).cloned();
+        if record.is_none() {
+            keys.insert(id.clone(), Record { fingerprint, response: None, expires: now + TTL });
+        }
+        record
+    };
+    match record {
+        None => {}
+        Some(record) if record.fingerprint != fingerprint => {
+            return Problem::new(
+                "idempotency-key-reused",
+                "Idempotency key reused",
+                axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Idempotency-Key {} was already used for a different request", id.1),
+                &uri,
+            )
+            .into_response();
+        }
+        Some(Record { response: None, .. }) => {
+            return Problem::new(
+                "idempotency-key-in-progress",
+                "Idempotency key in progress",
+                axum::http::StatusCode::CONFLICT,
+                format!("A request with Idempotency-Key {} is still in progress", id.1),
+                &uri,
+            )
+            .into_response();
+        }
+        Some(Record { response: Some(saved), .. }) => {
+            let mut response = (saved.status, saved.headers, saved.body).into_response();
+            response.headers_mut().insert("idempotent-replayed", axum::http::HeaderValue::from_static("true"));
+            return response;
+        }
+    }
+    let pending = Pending { id, finished: false };
+    let response = next.run(axum::extract::Request::from_parts(parts, axum::body::Body::from(body))).await;
+    if response.status().is_server_error() {
+        pending.finish(None);
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, usize::MAX).await else {
+        pending.finish(None);
+        return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    pending.finish(Some(Saved { status: parts.status, headers: parts.headers.clone(), body: body.clone() }));
+    Response::from_parts(parts, axum::body::Body::from(body))
+}
@@ -4,9 +4,9 @@
 //!
 //! For more see the file `README.md` in the project root.
 //!
-//! This example uses a `Book` struct, a `DATA` variable
-//! that is a lazy mutex global variable, and handlers
-//! that process the routes for HTTP verbs GET, PUT, etc.
+//! This example uses a `Book` struct, a `Data` store that axum
+//! gives to each handler as state, with an async read-write lock,
+//! and handlers that process the routes for HTTP verbs GET, PUT, etc.
 
 /// Use axum capabilities.
 use axum::{
@@ -21,8 +21,13 @@ async fn main() {
     axum::serve(listener, app()).await.unwrap();
 }
 
-/// Create our application.
+/// Create our application, which has a new data store with our demo books.
 pub fn app() -> axum::Router {
+    app_with_state(AppState::default())
+}
+
+/// Create our application, using the given data store.
+pub fn app_with_state(state: AppState) -> axum::Router {
     axum::Router::new()
         .route("/books",
             get(get_books)
@@ -34,147 +39,179 @@ pub fn app() -> axum::Router {
             .patch(patch_books_id)
             .delete(delete_books_id)
         )
+        .fallback(problem::fallback)
+        .method_not_allowed_fallback(problem::method_not_allowed_fallback)
+        .layer(axum::middleware::from_fn(idempotency::idempotency))
+        .with_state(state)
 }
 
 /// See file book.rs, which defines the `Book` struct.
 mod book;
-use crate::book::Book;
+use crate::book::{Book, NewBook};
 
 /// See file book_change.rs, which defines the `BookChange` struct.
 mod book_change;
-use crate::book_change::BookChange;
 
-/// See file data.rs, which defines the DATA global variable.
+/// See file idempotency.rs, which replays a response for a retry that
+/// has the same `Idempotency-Key` header.
+mod idempotency;
+
+/// See file patch.rs, which applies a JSON Merge Patch or a JSON Patch.
+mod patch;
+
+/// See file data.rs, which defines the `Data` store and our app state.
 mod data;
-use crate::data::DATA;
+use crate::data::AppState;
 
-/// Use Thread for spawning a thread e.g. to acquire our crate::DATA mutex lock.
-use std::thread;
+/// See file etag.rs, which defines the `ETag` and `If-Match` helpers.
+mod etag;
+use crate::etag::{etag, if_match};
+
+/// See file problem.rs, which defines the `Problem` struct for errors,
+/// and the `Json` and `Path` extractors that reject with a `Problem`.
+mod problem;
+use crate::problem::Problem;
 
 /// axum handler for "GET /books" which responds with a resource page.
-/// This demo uses our DATA; a production app could use a database.
-/// This demo must clone the DATA in order to sort items by title.
-pub async fn get_books() -> axum::response::Response {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(data) => {
-                let mut books = data.values().collect::<Vec<_>>().to_owned();
-                books.sort_by(|a, b| a.title.cmp(&b.title));
-                (axum::http::StatusCode::OK, axum::response::Json(books)).into_response()
-            },
-            Err(_) => {
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response()
-            }
-        }
-    }).join().unwrap().into()
+/// This demo uses our data store; a production app could use a database.
+/// This demo must clone the books in order to sort them by title.
+pub async fn get_books(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> axum::response::Response {
+    let data = state.books.read().await;
+    let mut books = data.values().collect::<Vec<_>>().to_owned();
+    books.sort_by(|a, b| a.title.cmp(&b.title));
+    (axum::http::StatusCode::OK, axum::response::Json(books)).into_response()
 }
 
 /// axum handler for "POST /books" which creates a new book resource.
-/// This demo shows how axum can extract JSON data into a Book struct.
+/// This demo shows how axum can extract JSON data into a NewBook struct.
+/// The server assigns the id, then responds with 201 Created, the book,
+/// and a `Location` header with the book's path.
 pub async fn post_books(
-    axum::extract::Json(book): axum::extract::Json<Book>
-) -> axum::http::StatusCode {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(mut data) => {
-                let id = data.keys().max().unwrap() + 1;
-                let book = Book { id, ..book };
-                data.insert(id, book.clone());
-                axum::http::StatusCode::CREATED
-            },
-            Err(_) => {
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR
-            }
-        }
-    }).join().unwrap().into()
+    axum::extract::State(state): axum::extract::State<AppState>,
+    problem::Json(new_book): problem::Json<NewBook>
+) -> axum::response::Response {
+    let mut data = state.books.write().await;
+    let id = state.next_id();
+    let book = Book { id, title: new_book.title, author: new_book.author };
+    data.insert(id, book.clone());
+    (
+        axum::http::StatusCode::CREATED,
+        [
+            (axum::http::header::LOCATION, format!("/books/{}", id)),
+            (axum::http::header::ETAG, etag(&book)),
+        ],
+        axum::response::Json(book)
+    ).into_response()
 }
 
 /// axum handler for "GET /books/{id}" which responds with one resource HTML page.
-/// This demo app uses our crate::DATA variable, and iterates on it to find the id.
+/// This demo app uses our data store, and looks up the id in it.
+/// The `ETag` header lets a client make a later write conditional via `If-Match`.
 pub async fn get_books_id(
-    axum::extract::Path(id): axum::extract::Path<u32>
+    axum::extract::State(state): axum::extract::State<AppState>,
+    uri: axum::http::Uri,
+    problem::Path(id): problem::Path<u32>
 ) -> axum::response::Response {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(data) => {
-                match data.get(&id) {
-                    Some(book) => (axum::http::StatusCode::OK, axum::response::Json(book)).into_response(),
-                    None => http::StatusCode::NOT_FOUND.into_response()
-                }
-            },
-            Err(_) => {
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response()
-            }
-        }
-    }).join().unwrap().into()
+    let data = state.books.read().await;
+    match data.get(&id) {
+        Some(book) => (
+            axum::http::StatusCode::OK,
+            [(axum::http::header::ETAG, etag(book))],
+            axum::response::Json(book)
+        ).into_response(),
+        None => Problem::book_not_found(id, &uri).into_response()
+    }
 }
 
 /// axum handler for "PUT /books/{id}" which sets a specific book resource.
 /// This demo shows how axum can extract JSON data into a Book struct.
+/// The book id must match the path id. A replace responds with 204 No
+/// Content; a create responds with 201 Created, the book, and a `Location`
+/// header. With an `If-Match` header, the current book must still match it.
 pub async fn put_books_id(
-    axum::extract::Json(book): axum::extract::Json<Book>
-) -> axum::http::StatusCode {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(mut data) => {
-                data.insert(book.id, book.clone());
-                axum::http::StatusCode::CREATED
-            },
-            Err(_) => {
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR
-            }
-        }
-    }).join().unwrap().into()
+    axum::extract::State(state): axum::extract::State<AppState>,
+    uri: axum::http::Uri,
+    headers: axum::http::HeaderMap,
+    problem::Path(id): problem::Path<u32>,
+    problem::Json(book): problem::Json<Book>
+) -> Result<axum::response::Response, Problem> {
+    if book.id != id {
+        return Err(Problem::id_mismatch(id, book.id, &uri));
+    }
+    let mut data = state.books.write().await;
+    if !if_match(&headers, data.get(&id)) {
+        return Err(Problem::precondition_failed(id, &uri));
+    }
+    state.reserve_id(id);
+    let etag = etag(&book);
+    if data.insert(id, book.clone()).is_some() {
+        return Ok((axum::http::StatusCode::NO_CONTENT, [(axum::http::header::ETAG, etag)]).into_response());
+    }
+    Ok((
+        axum::http::StatusCode::CREATED,
+        [
+            (axum::http::header::LOCATION, format!("/books/{}", id)),
+            (axum::http::header::ETAG, etag),
+        ],
+        axum::response::Json(book)
+    ).into_response())
 }
 
 /// axum handler for "DELETE /books/{id}" which destroys a resource.
-/// This demo extracts an id, then deletes the book in the DATA store.
+/// This demo extracts an id, then deletes the book in the data store.
+/// With an `If-Match` header, the current book must still match it.
 pub async fn delete_books_id(
-    axum::extract::Path(id): axum::extract::Path<u32>
-) -> axum::http::StatusCode {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(mut data) => {
-                if data.contains_key(&id) {
-                    data.remove(&id);
-                    axum::http::StatusCode::NO_CONTENT
-                } else {
-                    axum::http::StatusCode::NOT_FOUND
-                }
-            },
-            Err(_) => {
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR
-            }
-        }
-    }).join().unwrap().into()
+    axum::extract::State(state): axum::extract::State<AppState>,
+    uri: axum::http::Uri,
+    headers: axum::http::HeaderMap,
+    problem::Path(id): problem::Path<u32>
+) -> Result<axum::http::StatusCode, Problem> {
+    let mut data = state.books.write().await;
+    if !if_match(&headers, data.get(&id)) {
+        return Err(Problem::precondition_failed(id, &uri));
+    }
+    if data.contains_key(&id) {
+        data.remove(&id);
+        Ok(axum::http::StatusCode::NO_CONTENT)
+    } else {
+        Err(Problem::book_not_found(id, &uri))
+    }
 }
 
 /// axum handler for "PATCH /books/{id}" which updates attributes.
-/// This demo shows how to mutate the book attributes in the DATA store.
+/// This demo shows how to patch a book in the data store, by using
+/// a JSON Merge Patch or a JSON Patch; see file patch.rs.
+/// With an `If-Match` header, the current book must still match it.
 pub async fn patch_books_id(
-    axum::extract::Json(book_change): axum::extract::Json<BookChange>
-) -> axum::http::StatusCode {
-    thread::spawn(move || {
-        let id = book_change.id;
-        match DATA.lock() {
-            Ok(mut data) => {
-                if data.contains_key(&id) {
-                    if let Some(title) = book_change.title {
-                        data.get_mut(&id).unwrap().title = title.clone();
-                    }
-                    if let Some(author) = book_change.author {
-                        data.get_mut(&id).unwrap().title = author.clone();
-                    }
-                    axum::http::StatusCode::NO_CONTENT
-                } else {
-                    axum::http::StatusCode::NOT_FOUND
-                }
-            },
-            Err(_) => {
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR
-            }
+    axum::extract::State(state): axum::extract::State<AppState>,
+    uri: axum::http::Uri,
+    headers: axum::http::HeaderMap,
+    problem::Path(id): problem::Path<u32>,
+    body: axum::body::Bytes,
+) -> Result<axum::response::Response, Problem> {
+    let mut data = state.books.write().await;
+    if !if_match(&headers, data.get(&id)) {
+        return Err(Problem::precondition_failed(id, &uri));
+    }
+    let Some(book) = data.get(&id) else {
+        return Err(Problem::book_not_found(id, &uri));
+    };
+    let content_type = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    match patch::apply(book, content_type, &body, &uri) {
+        Ok(book) => {
+            let etag = etag(&book);
+            data.insert(id, book);
+            Ok((axum::http::StatusCode::NO_CONTENT, [(axum::http::header::ETAG, etag)]).into_response())
         }
-    }).join().unwrap().into()
+        Err(problem) => Ok((
+            [(axum::http::HeaderName::from_static("accept-patch"), patch::ACCEPT_PATCH)],
+            problem,
+        ).into_response()),
+    }
 }
 
 #[cfg(test)]
@@ -216,7 +253,12 @@ mod tests {
                 "author": "Giovanni Boccaccio"
             }
         );
-        server.post("/books").json(&j).await.assert_status(axum::http::StatusCode::CREATED);
+        let response = server.post("/books").json(&j).await;
+        response.assert_status(axum::http::StatusCode::CREATED);
+        let book = response.json::<Book>();
+        assert_eq!(book.title, "Decameron");
+        response.assert_header(axum::http::header::LOCATION, format!("/books/{}", book.id));
+        server.get(&format!("/books/{}", book.id)).await.assert_json(&book);
     }
 
     #[tokio::test]
@@ -236,11 +278,24 @@ mod tests {
         let server = TestServer::new(app()).unwrap();
         let j = json!(
             {
+                "id": 4,
                 "title": "Decameron",
                 "author": "Giovanni Boccaccio"
             }
         );
-        server.put("/books/4").json(&j).await.assert_status(axum::http::StatusCode::CREATED);
+        let response = server.put("/books/4").json(&j).await;
+        response.assert_status(axum::http::StatusCode::CREATED);
+        response.assert_header(axum::http::header::LOCATION, "/books/4");
+        response.assert_json(&j);
+        // A put to an existing book replaces it.
+        let j = json!({"id": 4, "title": "Decameron", "author": "Boccaccio"});
+        server.put("/books/4").json(&j).await.assert_status(axum::http::StatusCode::NO_CONTENT);
+        server.get("/books/4").await.assert_json(&j);
+        // The book id must match the path id.
+        let response = server.put("/books/1").json(&j).await;
+        response.assert_status(axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.json::<serde_json::Value>()["type"], "urn:demo-rust-axum:problem:id-mismatch");
+        server.get("/books/1").await.assert_json(&json!({"id": 1, "title": "Antigone", "author": "Sophocles"}));
     }
 
     #[tokio::test]
@@ -261,4 +316,132 @@ mod tests {
         server.delete("/books/1").await.assert_status(axum::http::StatusCode::NO_CONTENT);
     }
 
+    #[tokio::test]
+    async fn get_books_id_not_found() {
+        let server = TestServer::new(app()).unwrap();
+        let response = server.get("/books/9").await;
+        response.assert_status_not_found();
+        response.assert_header(axum::http::header::CONTENT_TYPE, "application/problem+json");
+        response.assert_json(&json!(
+            {
+                "type": "urn:demo-rust-axum:problem:book-not-found",
+                "title": "Book not found",
+                "status": 404,
+                "detail": "Book id 9 not found",
+                "instance": "/books/9"
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn put_books_id_with_missing_field() {
+        let server = TestServer::new(app()).unwrap();
+        let j = json!(
+            {
+                "id": 4,
+                "title": "Decameron"
+            }
+        );
+        let response = server.put("/books/4").json(&j).await;
+        response.assert_status(axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+        let problem = response.json::<serde_json::Value>();
+        assert_eq!(problem["type"], "urn:demo-rust-axum:problem:invalid-json-data");
+        assert_eq!(problem["instance"], "/books/4");
+        assert!(problem["detail"].as_str().unwrap().contains("missing field `author`"));
+    }
+
+    #[tokio::test]
+    async fn put_books_id_with_missing_content_type() {
+        let server = TestServer::new(app()).unwrap();
+        let response = server.put("/books/4").text("{}").await;
+        response.assert_status(axum::http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(response.json::<serde_json::Value>()["type"], "urn:demo-rust-axum:problem:missing-json-content-type");
+    }
+
+    #[tokio::test]
+    async fn put_books_id_with_if_match() {
+        let server = TestServer::new(app()).unwrap();
+        let tag = server.get("/books/1").await.header(axum::http::header::ETAG);
+        let j = json!({"id": 1, "title": "Antigone", "author": "Sophocles of Colonus"});
+        let response = server.put("/books/1").add_header(axum::http::header::IF_MATCH, tag.clone()).json(&j).await;
+        response.assert_status(axum::http::StatusCode::NO_CONTENT);
+        // The first write changed the book, so the old tag no longer matches.
+        let response = server.put("/books/1").add_header(axum::http::header::IF_MATCH, tag.clone()).json(&j).await;
+        response.assert_status(axum::http::StatusCode::PRECONDITION_FAILED);
+        assert_eq!(response.json::<serde_json::Value>()["type"], "urn:demo-rust-axum:problem:precondition-failed");
+        server.delete("/books/1").add_header(axum::http::header::IF_MATCH, tag).await.assert_status(axum::http::StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn patch_books_id_with_merge_patch_and_json_patch() {
+        let server = TestServer::new(app()).unwrap();
+        let merge = json!({"author": "Sophocles of Colonus"});
+        let response = server.patch("/books/1").bytes(merge.to_string().into())
+            .content_type(patch::APPLICATION_MERGE_PATCH_JSON).await;
+        response.assert_status(axum::http::StatusCode::NO_CONTENT);
+        // The author changes, and the title stays the same.
+        server.get("/books/1").await.assert_json(&json!({"id": 1, "title": "Antigone", "author": "Sophocles of Colonus"}));
+        // A failed test operation leaves the whole book unchanged.
+        let ops = json!([
+            {"op": "replace", "path": "/author", "value": "Anonymous"},
+            {"op": "test", "path": "/title", "value": "Elektra"}
+        ]);
+        let response = server.patch("/books/1").bytes(ops.to_string().into())
+            .content_type(patch::APPLICATION_JSON_PATCH_JSON).await;
+        response.assert_status(axum::http::StatusCode::CONFLICT);
+        server.get("/books/1").await.assert_json(&json!({"id": 1, "title": "Antigone", "author": "Sophocles of Colonus"}));
+        // A patch must leave a valid book with the same id.
+        let merge = json!({"title": null});
+        let response = server.patch("/books/1").bytes(merge.to_string().into())
+            .content_type(patch::APPLICATION_MERGE_PATCH_JSON).await;
+        response.assert_status(axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+        let ops = json!([{"op": "replace", "path": "/id", "value": 9}]);
+        let response = server.patch("/books/1").bytes(ops.to_string().into())
+            .content_type(patch::APPLICATION_JSON_PATCH_JSON).await;
+        response.assert_status(axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+        let response = server.patch("/books/1").text("title=Elektra").await;
+        response.assert_status(axum::http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        response.assert_header("accept-patch", patch::ACCEPT_PATCH);
+    }
+
+    #[tokio::test]
+    async fn post_books_with_idempotency_key() {
+        let server = TestServer::new(app()).unwrap();
+        let key = (axum::http::HeaderName::from_static("idempotency-key"), "b0a4d1f7");
+        let j = json!({"title": "Ficciones", "author": "Jorge Luis Borges"});
+        let first = server.post("/books").add_header(key.0.clone(), key.1).json(&j).await;
+        let retry = server.post("/books").add_header(key.0.clone(), key.1).json(&j).await;
+        retry.assert_status(axum::http::StatusCode::CREATED);
+        retry.assert_header("idempotent-replayed", "true");
+        assert_eq!(retry.json::<Book>(), first.json::<Book>());
+        let j = json!({"title": "Labyrinths", "author": "Jorge Luis Borges"});
+        let response = server.post("/books").add_header(key.0.clone(), key.1).json(&j).await;
+        response.assert_status(axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+        // A retry with another precondition or format is a different request.
+        let j = json!({"title": "Ficciones", "author": "Jorge Luis Borges"});
+        let response = server.post("/books").add_header(key.0, key.1)
+            .add_header(axum::http::header::ACCEPT, "text/html").json(&j).await;
+        response.assert_status(axum::http::StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn post_books_with_idempotency_key_in_progress() {
+        let state = AppState::default();
+        let server = TestServer::new(app_with_state(state.clone())).unwrap();
+        let key = (axum::http::HeaderName::from_static("idempotency-key"), "6c2e9a15");
+        let j = json!({"title": "Ficciones", "author": "Jorge Luis Borges"});
+        // Hold the data lock, so the first request stays in progress.
+        let data = state.books.write().await;
+        let first = server.post("/books").add_header(key.0.clone(), key.1).json(&j).into_future();
+        tokio::pin!(first);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), &mut first).await.is_err());
+        // A concurrent retry must not run the write a second time.
+        let retry = server.post("/books").add_header(key.0.clone(), key.1).json(&j).await;
+        retry.assert_status(axum::http::StatusCode::CONFLICT);
+        drop(data);
+        first.await.assert_status(axum::http::StatusCode::CREATED);
+        let retry = server.post("/books").add_header(key.0, key.1).json(&j).await;
+        retry.assert_header("idempotent-replayed", "true");
+    }
+
 }
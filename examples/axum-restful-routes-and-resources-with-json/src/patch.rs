@@ -0,0 +1,121 @@
+// Use the Book and BookChange structs, and the Problem struct for errors.
+use crate::book::Book;
+use crate::book_change::BookChange;
+use crate::problem::Problem;
+
+// The media type for a JSON Merge Patch body, per RFC 7396.
+pub const APPLICATION_MERGE_PATCH_JSON: &str = "application/merge-patch+json";
+
+// The media type for a JSON Patch body, per RFC 6902.
+pub const APPLICATION_JSON_PATCH_JSON: &str = "application/json-patch+json";
+
+// The patch media types that we accept, for the `Accept-Patch` header.
+pub const ACCEPT_PATCH: &str = "application/merge-patch+json, application/json-patch+json";
+
+// Apply a patch request body to a book, and return the patched book.
+//
+// The request `Content-Type` chooses the kind of patch:
+//
+// - `application/merge-patch+json`: a JSON Merge Patch, such as
+//   `{"title": "Elektra"}`, which must fit the `BookChange` struct.
+//   For convenience, we treat plain `application/json` the same way.
+//
+// - `application/json-patch+json`: a JSON Patch, which is a list of
+//   operations, such as `[{"op": "test", "path": "/title", "value":
+//   "Antigone"}, {"op": "replace", "path": "/title", "value": "Elektra"}]`.
+//   If any operation fails, including a test, then the patch fails.
+//
+// We patch a copy of the book, then check the copy is a valid book with
+// the same id, so a patch either applies in full, or not at all.
+pub fn apply(
+    book: &Book,
+    content_type: Option<&str>,
+    body: &[u8],
+    instance: &axum::http::Uri,
+) -> Result<Book, Problem> {
+    let media_type = content_type
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase());
+    let mut doc = serde_json::to_value(book).map_err(|err| internal(err, instance))?;
+    match media_type.as_deref() {
+        Some(APPLICATION_MERGE_PATCH_JSON) | Some("application/json") => {
+            let patch: serde_json::Value = parse(body, instance)?;
+            serde_json::from_value::<BookChange>(patch.clone())
+                .map_err(|err| invalid_patch(err, instance))?;
+            json_patch::merge(&mut doc, &patch);
+        }
+        Some(APPLICATION_JSON_PATCH_JSON) => {
+            let patch: json_patch::Patch = parse(body, instance)?;
+            json_patch::patch(&mut doc, &patch).map_err(|err| {
+                Problem::new(
+                    "patch-conflict",
+                    "Patch conflict",
+                    axum::http::StatusCode::CONFLICT,
+                    err.to_string(),
+                    instance,
+                )
+            })?;
+        }
+        _ => {
+            return Err(Problem::new(
+                "unsupported-patch-type",
+                "Unsupported patch type",
+                axum::http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("Content-Type must be one of: {}", ACCEPT_PATCH),
+                instance,
+            ))
+        }
+    }
+    let patched: Book = serde_json::from_value(doc).map_err(|err| invalid_book(err.to_string(), instance))?;
+    if patched.id != book.id {
+        return Err(invalid_book(format!("Book id {} must not change", book.id), instance));
+    }
+    if patched.title.trim().is_empty() || patched.author.trim().is_empty() {
+        return Err(invalid_book("Book title and author must not be blank".into(), instance));
+    }
+    Ok(patched)
+}
+
+// Parse a request body as JSON, or reject it as malformed or invalid.
+fn parse<T: serde::de::DeserializeOwned>(body: &[u8], instance: &axum::http::Uri) -> Result<T, Problem> {
+    serde_json::from_slice(body).map_err(|err| match err.classify() {
+        serde_json::error::Category::Data => invalid_patch(err, instance),
+        _ => Problem::new(
+            "malformed-json",
+            "Malformed JSON",
+            axum::http::StatusCode::BAD_REQUEST,
+            err.to_string(),
+            instance,
+        ),
+    })
+}
+
+fn invalid_patch(err: serde_json::Error, instance: &axum::http::Uri) -> Problem {
+    Problem::new(
+        "invalid-patch",
+        "Invalid patch",
+        axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+        err.to_string(),
+        instance,
+    )
+}
+
+fn invalid_book(detail: String, instance: &axum::http::Uri) -> Problem {
+    Problem::new(
+        "invalid-book",
+        "Invalid book",
+        axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+        detail,
+        instance,
+    )
+}
+
+fn internal(err: serde_json::Error, instance: &axum::http::Uri) -> Problem {
+    Problem::new(
+        "internal",
+        "Internal server error",
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        err.to_string(),
+        instance,
+    )
+}
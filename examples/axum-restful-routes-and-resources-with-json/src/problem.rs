@@ -0,0 +1,189 @@
+// Use Serialize to convert a Problem struct into response JSON.
+use serde::Serialize;
+
+// Use axum capabilities for responses and extractors.
+use axum::response::{IntoResponse, Response};
+
+// The media type for a problem details JSON body, per RFC 9457.
+pub const APPLICATION_PROBLEM_JSON: &str = "application/problem+json";
+
+// Problem details for an HTTP API, per RFC 9457.
+//
+// Every error path of this example responds with this JSON body,
+// so a client can tell why a request failed, not just that it did:
+//
+// ```json
+// {
+//     "type": "urn:demo-rust-axum:problem:book-not-found",
+//     "title": "Book not found",
+//     "status": 404,
+//     "detail": "Book id 9 not found",
+//     "instance": "/books/9"
+// }
+// ```
+//
+// The type is a URI that identifies the kind of problem; a client can
+// match on it. The title is the same for every problem of that type.
+// The detail is specific to this occurrence, and so is the instance,
+// which is the request path.
+#[derive(Debug, Serialize, Clone, Eq, PartialEq)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub instance: String,
+}
+
+impl Problem {
+    // Create a problem with a type slug, such as "book-not-found".
+    pub fn new(
+        slug: &str,
+        title: &str,
+        status: axum::http::StatusCode,
+        detail: impl Into<String>,
+        instance: &axum::http::Uri,
+    ) -> Self {
+        Problem {
+            problem_type: format!("urn:demo-rust-axum:problem:{}", slug),
+            title: title.into(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            instance: instance.path().into(),
+        }
+    }
+
+    // Create a problem for a book id that is not in our data store.
+    pub fn book_not_found(id: u32, instance: &axum::http::Uri) -> Self {
+        Problem::new(
+            "book-not-found",
+            "Book not found",
+            axum::http::StatusCode::NOT_FOUND,
+            format!("Book id {} not found", id),
+            instance,
+        )
+    }
+
+    // Create a problem for an `If-Match` header that doesn't match the book,
+    // because another client changed the book first.
+    pub fn precondition_failed(id: u32, instance: &axum::http::Uri) -> Self {
+        Problem::new(
+            "precondition-failed",
+            "Precondition failed",
+            axum::http::StatusCode::PRECONDITION_FAILED,
+            format!("Book id {} does not match the If-Match header", id),
+            instance,
+        )
+    }
+
+    // Create a problem for a request body with a book id that isn't the
+    // book id in the request path, such as "PUT /books/1" with id 2.
+    pub fn id_mismatch(id: u32, body_id: u32, instance: &axum::http::Uri) -> Self {
+        Problem::new(
+            "id-mismatch",
+            "Book id mismatch",
+            axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Book id {} must match the path id {}", body_id, id),
+            instance,
+        )
+    }
+}
+
+// Respond with the problem status code and an RFC 9457 JSON body.
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = axum::http::StatusCode::from_u16(self.status)
+            .unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        (
+            status,
+            [(axum::http::header::CONTENT_TYPE, APPLICATION_PROBLEM_JSON)],
+            axum::Json(self),
+        )
+            .into_response()
+    }
+}
+
+// Convert an axum JSON extractor rejection into a problem.
+fn json_rejection_problem(
+    rejection: axum::extract::rejection::JsonRejection,
+    instance: &axum::http::Uri,
+) -> Problem {
+    use axum::extract::rejection::JsonRejection;
+    let (slug, title) = match &rejection {
+        JsonRejection::MissingJsonContentType(_) => ("missing-json-content-type", "Missing JSON content type"),
+        JsonRejection::JsonSyntaxError(_) => ("malformed-json", "Malformed JSON"),
+        JsonRejection::JsonDataError(_) => ("invalid-json-data", "Invalid JSON data"),
+        _ => ("unreadable-body", "Unreadable request body"),
+    };
+    Problem::new(slug, title, rejection.status(), rejection.body_text(), instance)
+}
+
+// Convert an axum path extractor rejection into a problem.
+fn path_rejection_problem(
+    rejection: axum::extract::rejection::PathRejection,
+    instance: &axum::http::Uri,
+) -> Problem {
+    Problem::new("invalid-path", "Invalid path parameter", rejection.status(), rejection.body_text(), instance)
+}
+
+// JSON extractor that rejects a request with a problem, rather than with
+// axum's default plain text. Use it just like `axum::extract::Json`.
+pub struct Json<T>(pub T);
+
+impl<T, S> axum::extract::FromRequest<S> for Json<T>
+where
+    T: serde::de::DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Problem;
+
+    async fn from_request(req: axum::extract::Request, state: &S) -> Result<Self, Self::Rejection> {
+        let uri = req.uri().clone();
+        match axum::extract::Json::<T>::from_request(req, state).await {
+            Ok(axum::extract::Json(value)) => Ok(Json(value)),
+            Err(rejection) => Err(json_rejection_problem(rejection, &uri)),
+        }
+    }
+}
+
+// Path extractor that rejects a request with a problem, rather than with
+// axum's default plain text. Use it just like `axum::extract::Path`.
+pub struct Path<T>(pub T);
+
+impl<T, S> axum::extract::FromRequestParts<S> for Path<T>
+where
+    T: serde::de::DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = Problem;
+
+    async fn from_request_parts(parts: &mut axum::http::request::Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Path(value)),
+            Err(rejection) => Err(path_rejection_problem(rejection, &parts.uri)),
+        }
+    }
+}
+
+// axum handler for any request that fails to match the router routes.
+pub async fn fallback(uri: axum::http::Uri) -> Problem {
+    Problem::new(
+        "route-not-found",
+        "Route not found",
+        axum::http::StatusCode::NOT_FOUND,
+        format!("No route for {}", uri.path()),
+        &uri,
+    )
+}
+
+// axum handler for any request that matches a route, but not its method.
+pub async fn method_not_allowed_fallback(method: axum::http::Method, uri: axum::http::Uri) -> Problem {
+    Problem::new(
+        "method-not-allowed",
+        "Method not allowed",
+        axum::http::StatusCode::METHOD_NOT_ALLOWED,
+        format!("Method {} is not allowed for {}", method, uri.path()),
+        &uri,
+    )
+}
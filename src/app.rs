@@ -1,6 +1,9 @@
 /// Use axum capabilities.
 use axum::routing::*;
 
+/// Use axum IntoResponse for handlers that build a response in steps.
+use axum::response::IntoResponse;
+
 /// Use HashMap to deserialize a HTTP GET query into a key-value map.
 /// axum extracts query parameters by using `axum::extract::Query`.
 /// For the implementation, see function `get_query`.
@@ -11,8 +14,97 @@ use std::collections::HashMap;
 /// For this demo, see functions `get_demo_json` and `put_demo_json`.
 use serde_json::{json, Value};
 
+/// Use Arc to share our data store among handlers via axum `State`.
+use std::sync::Arc;
+
+/// See file data.rs, which defines the in-memory data store.
+use crate::data::{demo_books, InMemoryBookStore, StoreError};
+
+/// See file search.rs, which defines the `SearchIndex` for books.
+use crate::search::SearchIndex;
+
+/// See file idempotency.rs, which defines `Idempotency-Key` handling.
+use crate::idempotency::{idempotency, IdempotencyKeys};
+
+/// See file changes.rs, which defines the `ChangeFeed` of book changes.
+use crate::changes::{event_stream, Change, ChangeFeed, ChangeKind};
+
+/// See file ws.rs, which defines WebSocket editing and `Presence`.
+use crate::ws::Presence;
+
+/// See file webhook.rs, which defines `Webhooks` and their deliveries.
+use crate::webhook::{Delivery, NewWebhook, Webhook, Webhooks};
+
+/// See file schema.rs, which defines JSON Schemas, and the extractor
+/// `ValidJson` and the functions `form_value` and `validate`, which validate
+/// a request body.
+use crate::schema::{form_value, validate, ValidJson};
+
+/// See file openapi.rs, which defines our OpenAPI document.
+/// Use the `OpenApi` trait to generate the document from `ApiDoc`.
+use crate::openapi::ApiDoc;
+use utoipa::OpenApi;
+
+/// Application state that axum gives to any handler that asks for it.
+/// The data store is a trait object, so an app can swap implementations.
+/// The search index, the change feed, and the webhooks must be told
+/// whenever a handler changes a book; see function `book_changed`.
+/// The idempotency keys let a client safely retry an unsafe request.
+/// The presence is who is viewing which book, by using a WebSocket.
+/// The shutdown flag becomes true when the server starts to shut down,
+/// so long-lived connections, such as WebSockets, can close cleanly.
+#[derive(Clone)]
+pub struct AppState {
+    pub store: Arc<dyn BookStore>,
+    pub search: Arc<SearchIndex>,
+    pub changes: Arc<ChangeFeed>,
+    pub idempotency: Arc<IdempotencyKeys>,
+    pub presence: Arc<Presence>,
+    pub webhooks: Arc<Webhooks>,
+    pub shutdown: Arc<tokio::sync::watch::Sender<bool>>,
+}
+
+impl AppState {
+    /// Create application state with a data store, then index its books.
+    pub async fn new(store: Arc<dyn BookStore>) -> Result<Self, StoreError> {
+        let books = store.list().await?;
+        Ok(AppState::with_books(store, books))
+    }
+
+    /// Create application state with a data store and the books that it
+    /// has, which we index, such as a new in-memory data store's books.
+    pub fn with_books(store: Arc<dyn BookStore>, books: Vec<Book>) -> Self {
+        AppState {
+            store,
+            search: Arc::new(SearchIndex::from_books(books)),
+            changes: Arc::default(),
+            idempotency: Arc::default(),
+            presence: Arc::default(),
+            webhooks: Arc::default(),
+            shutdown: Arc::new(tokio::sync::watch::Sender::new(false)),
+        }
+    }
+
+    /// Tell the search index, the change feed, and the webhooks that a
+    /// handler changed a book in the data store. A deleted book has no version.
+    pub fn book_changed(&self, kind: ChangeKind, book: &Book, version: Option<u64>) {
+        match kind {
+            ChangeKind::Deleted => self.search.remove(book.id),
+            _ => self.search.index(book),
+        }
+        let change = self.changes.publish(kind, book.clone(), version);
+        self.webhooks.notify(&change);
+    }
+}
+
 /// Create our application which is an axum router.
+/// This uses a new in-memory data store that has our demo books.
 pub fn app() -> axum::Router {
+    app_with_state(AppState::with_books(Arc::new(InMemoryBookStore::new(demo_books())), demo_books()))
+}
+
+/// Create our application which is an axum router, using the given state.
+pub fn app_with_state(state: AppState) -> axum::Router {
     axum::Router::new()
         .fallback(fallback)
         .route("/", get(hello))
@@ -37,11 +129,40 @@ pub fn app() -> axum::Router {
         .route("/items", get(get_items))
         .route("/items/{id}", get(get_items_id))
         .route("/books", get(get_books).put(put_books))
-        .route("/books/{id}", get(get_books_id).delete(delete_books_id))
+        .route("/books.html", get(get_books))
+        .route("/books.json", get(get_books))
+        .route("/books.csv", get(get_books))
+        .route("/books/search", get(get_books_search))
+        .route("/books/events", get(get_books_events))
+        .route("/ws", get(get_ws))
+        .route("/books:batch", post(post_books_batch))
+        .route("/books/trash", get(get_books_trash).delete(delete_books_trash))
+        .route("/books/trash/{id}", delete(delete_books_trash_id))
+        .route(
+            "/books/{id}",
+            get(get_books_id)
+                .put(put_books_id)
+                .patch(patch_books_id)
+                .delete(delete_books_id),
+        )
         .route(
             "/books/{id}/form",
             get(get_books_id_form).post(post_books_id_form),
         )
+        .route("/books/{id}/restore", post(post_books_id_restore))
+        .route("/books/{id}/revisions", get(get_books_id_revisions))
+        .route("/books/{id}/revisions/diff", get(get_books_id_revisions_diff))
+        .route("/books/{id}/revisions/{n}", get(get_books_id_revisions_n))
+        .route("/books/{id}/revisions/{n}/revert", post(post_books_id_revisions_n_revert))
+        .route("/schemas/{name}", get(get_schemas_name))
+        .route("/admin/rebuild", post(post_admin_rebuild))
+        .route("/admin/webhooks", get(get_admin_webhooks).post(post_admin_webhooks))
+        .route("/admin/webhooks/{id}", get(get_admin_webhooks_id).delete(delete_admin_webhooks_id))
+        .route("/admin/webhooks/{id}/deliveries", get(get_admin_webhooks_id_deliveries))
+        .merge(utoipa_swagger_ui::SwaggerUi::new("/docs/api").url("/openapi.json", ApiDoc::openapi()))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), idempotency))
+        .layer(axum::middleware::from_fn(negotiate_error_format))
+        .with_state(state)
 }
 
 ////
@@ -54,31 +175,62 @@ pub fn app() -> axum::Router {
 
 /// axum handler for any request that fails to match the router routes.
 /// This implementation returns HTTP status code Not Found (404).
-pub async fn fallback(uri: axum::http::Uri) -> impl axum::response::IntoResponse {
-    (axum::http::StatusCode::NOT_FOUND, uri.to_string())
+pub async fn fallback(uri: axum::http::Uri) -> AppError {
+    AppError::NotFound(format!("No route for {}", uri))
 }
 
 /// axum handler for "GET /" which returns a string and causes axum to
 /// immediately respond with status code `200 OK` and with the string.
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "demo",
+    responses((status = 200, description = "Hello, World!", body = String, content_type = "text/plain"))
+)]
 pub async fn hello() -> String {
     "Hello, World!".to_string()
 }
 
-/// axum handler for "GET /string.html" which responds with a string.
-/// The `Html` type sets an HTTP header content-type of `text/html`.
-pub async fn string_html() -> axum::response::Html<&'static str> {
-    "<html><body><h1>Headline</h1><p>Paragraph</b></body></html>".into()
+/// axum handler for "GET /string.html" which responds with a page of a
+/// headline and a paragraph, in our layout; see file `templates/message.html`.
+/// The `Markup` type sets an HTTP header content-type of `text/html`.
+#[utoipa::path(
+    get,
+    path = "/string.html",
+    tag = "demo",
+    responses((status = 200, description = "A page", body = String, content_type = "text/html"))
+)]
+pub async fn string_html() -> Result<Markup, AppError> {
+    message_page("Headline", "Paragraph")
 }
 
 /// axum handler that responds with typical HTML coming from a file.
 /// This uses the Rust macro `std::include_str` to include a UTF-8 file
 /// path, relative to `main.rs`, as a `&'static str` at compile time.
-async fn file_html() -> axum::response::Html<&'static str> {
-    include_str!("file.html").into()
+/// We wrote the file, so it's markup, which the page shows in our layout;
+/// see file `templates/markup.html`.
+/// The file can't change while the program runs, so caches may keep it.
+#[utoipa::path(
+    get,
+    path = "/file.html",
+    tag = "demo",
+    responses((status = 200, description = "A page from a file", body = String, content_type = "text/html"), (status = 304, description = "Not Modified, because the client already has it"))
+)]
+async fn file_html(headers: axum::http::HeaderMap) -> Result<axum::response::Response, AppError> {
+    let content = Markup::raw(include_str!("file.html"));
+    let page = Markup::render(&MarkupPage { title: "Respond with HTML file", content })?;
+    let etag = content_etag(page.as_str().as_bytes());
+    Ok(conditional_get(&headers, page.into_response(), &etag, Some(*STARTED), CACHE_STATIC))
 }
 
 /// axum handler for "GET /status" which returns the HTTP status
 /// code OK (200) along with a user-visible string message.
+#[utoipa::path(
+    get,
+    path = "/status",
+    tag = "demo",
+    responses((status = 200, description = "OK", body = String, content_type = "text/plain"))
+)]
 pub async fn status() -> (axum::http::StatusCode, String) {
     (axum::http::StatusCode::OK, "OK".to_string())
 }
@@ -89,10 +241,16 @@ pub async fn status() -> (axum::http::StatusCode, String) {
 
 /// axum handler for "GET /epoch" which shows the current epoch time.
 /// This shows how to write a handler that uses time and can error.
-pub async fn epoch() -> Result<String, axum::http::StatusCode> {
+#[utoipa::path(
+    get,
+    path = "/epoch",
+    tag = "demo",
+    responses((status = 200, description = "Seconds since the Unix epoch", body = String, content_type = "text/plain"), (status = 500, description = "Internal Server Error", body = ErrorBody))
+)]
+pub async fn epoch() -> Result<String, AppError> {
     match std::time::SystemTime::now().duration_since(std::time::SystemTime::UNIX_EPOCH) {
         Ok(duration) => Ok(format!("{}", duration.as_secs())),
-        Err(_) => Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+        Err(err) => Err(AppError::Internal(err.to_string()))
     }
 }
 
@@ -101,10 +259,16 @@ pub async fn epoch() -> Result<String, axum::http::StatusCode> {
 ////
 
 /// Create the constant INSTANT so the program can track its own uptime.
-pub static INSTANT: std::sync::LazyLock<std::time::Instant> = std::sync::LazyLock::new(|| std::time::Instant::now());
+pub static INSTANT: std::sync::LazyLock<std::time::Instant> = std::sync::LazyLock::new(std::time::Instant::now);
 
 /// axum handler for "GET /uptime" which shows the program's uptime duration.
 /// This shows how to write a handler that uses a global static lazy value.
+#[utoipa::path(
+    get,
+    path = "/uptime",
+    tag = "demo",
+    responses((status = 200, description = "Seconds since the program started", body = String, content_type = "text/plain"))
+)]
 pub async fn uptime() -> String {
     format!("{}", INSTANT.elapsed().as_secs())
 }
@@ -118,6 +282,12 @@ pub static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsiz
 
 /// axum handler for "GET /count" which shows the program's count duration.
 /// This shows how to write a handler that uses a global static lazy value.
+#[utoipa::path(
+    get,
+    path = "/count",
+    tag = "demo",
+    responses((status = 200, description = "How many times this route ran", body = String, content_type = "text/plain"))
+)]
 pub async fn count() -> String {
     COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
     format!("{}", COUNT.load(std::sync::atomic::Ordering::SeqCst))
@@ -127,28 +297,54 @@ pub async fn count() -> String {
 
 /// axum handler for "GET /request-uri" which shows the request's own URI.
 /// This shows how to write a handler that receives the URI.
+#[utoipa::path(
+    get,
+    path = "/request-uri",
+    tag = "demo",
+    responses((status = 200, description = "The request URI", body = String, content_type = "text/plain"))
+)]
 pub async fn request_uri(uri: axum::http::Uri) -> String {
     format!("The URI is: {:?}", uri)
 }
 
-
-/// axum handler for "GET /demo.html" which responds with HTML text.
-/// The `Html` type sets an HTTP header content-type of `text/html`.
-pub async fn demo_html() -> axum::response::Html<&'static str> {
-    "<h1>Hello</h1>".into()
+/// axum handler for "GET /demo.html" which responds with a page of a
+/// headline and a greeting, in our layout; see file `templates/message.html`.
+/// The `Markup` type sets an HTTP header content-type of `text/html`.
+#[utoipa::path(
+    get,
+    path = "/demo.html",
+    tag = "demo",
+    responses((status = 200, description = "A headline", body = String, content_type = "text/html"))
+)]
+pub async fn demo_html() -> Result<Markup, AppError> {
+    message_page("Hello", "Hello, World!")
 }
 
 /// axum handler for "GET /demo.png" which responds with an image PNG.
 /// This sets a header "image/png" then sends the decoded image data.
-async fn demo_png() -> impl axum::response::IntoResponse {
+/// The image can't change while the program runs, so caches may keep it.
+#[utoipa::path(
+    get,
+    path = "/demo.png",
+    tag = "demo",
+    responses(
+        (status = 200, description = "An image", content_type = "image/png"),
+        (status = 304, description = "Not Modified, because the client already has it"),
+    )
+)]
+async fn demo_png(headers: axum::http::HeaderMap) -> Result<axum::response::Response, AppError> {
     use base64::Engine;
     let png = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mPk+89QDwADvgGOSHzRgAAAAABJRU5ErkJggg==";
-    (
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(png)
+        .map_err(|err| AppError::Internal(err.to_string()))?;
+    let etag = content_etag(&bytes);
+    let response = (
         axum::response::AppendHeaders([(axum::http::header::CONTENT_TYPE, "image/png")]),
-        base64::engine::general_purpose::STANDARD
-            .decode(png)
-            .unwrap(),
+        bytes,
     )
+        .into_response();
+    Ok(conditional_get(&headers, response, &etag, Some(*STARTED), CACHE_STATIC))
 }
 
 ////
@@ -169,13 +365,33 @@ async fn demo_png() -> impl axum::response::IntoResponse {
 /// axum handler for "GET /demo.json" which returns JSON data.
 /// The `Json` type sets an HTTP header content-type `application/json`.
 /// The `Json` type supports types that implement `serde::Deserialize`.
-pub async fn get_demo_json() -> axum::extract::Json<Value> {
-    json!({"a":"b"}).into()
+/// The data can't change while the program runs, so caches may keep it.
+#[utoipa::path(
+    get,
+    path = "/demo.json",
+    tag = "demo",
+    responses(
+        (status = 200, description = "JSON data", body = Object),
+        (status = 304, description = "Not Modified, because the client already has it"),
+    )
+)]
+pub async fn get_demo_json(headers: axum::http::HeaderMap) -> axum::response::Response {
+    let data: Value = json!({"a":"b"});
+    let etag = content_etag(data.to_string().as_bytes());
+    let response = axum::extract::Json(data).into_response();
+    conditional_get(&headers, response, &etag, Some(*STARTED), CACHE_STATIC)
 }
 
 /// axum handler for "PUT /demo.json" which uses `aumx::extract::Json`.
 /// This buffers the request body then deserializes it using serde.
 /// The `Json` type supports types that implement `serde::Deserialize`.
+#[utoipa::path(
+    put,
+    path = "/demo.json",
+    tag = "demo",
+    request_body = Object,
+    responses((status = 200, description = "The JSON data as a string", body = String, content_type = "text/plain"))
+)]
 pub async fn put_demo_json(
     axum::extract::Json(data): axum::extract::Json<serde_json::Value>,
 ) -> String {
@@ -194,30 +410,60 @@ pub async fn put_demo_json(
 
 /// axum handler for "GET /foo" which returns a string message.
 /// This shows our naming convention for HTTP GET handlers.
+#[utoipa::path(
+    get,
+    path = "/foo",
+    tag = "demo",
+    responses((status = 200, description = "GET foo", body = String, content_type = "text/plain"))
+)]
 pub async fn get_foo() -> String {
     "GET foo".to_string()
 }
 
 /// axum handler for "PUT /foo" which returns a string message.
 /// This shows our naming convention for HTTP PUT handlers.
+#[utoipa::path(
+    put,
+    path = "/foo",
+    tag = "demo",
+    responses((status = 200, description = "PUT foo", body = String, content_type = "text/plain"))
+)]
 pub async fn put_foo() -> String {
     "PUT foo".to_string()
 }
 
 /// axum handler for "PATCH /foo" which returns a string message.
 /// This shows our naming convention for HTTP PATCH handlers.
+#[utoipa::path(
+    patch,
+    path = "/foo",
+    tag = "demo",
+    responses((status = 200, description = "PATCH foo", body = String, content_type = "text/plain"))
+)]
 pub async fn patch_foo() -> String {
     "PATCH foo".to_string()
 }
 
 /// axum handler for "POST /foo" which returns a string message.
 /// This shows our naming convention for HTTP POST handlers.
+#[utoipa::path(
+    post,
+    path = "/foo",
+    tag = "demo",
+    responses((status = 200, description = "POST foo", body = String, content_type = "text/plain"))
+)]
 pub async fn post_foo() -> String {
     "POST foo".to_string()
 }
 
 /// axum handler for "DELETE /foo" which returns a string message.
 /// This shows our naming convention for HTTP DELETE handlers.
+#[utoipa::path(
+    delete,
+    path = "/foo",
+    tag = "demo",
+    responses((status = 200, description = "DELETE foo", body = String, content_type = "text/plain"))
+)]
 pub async fn delete_foo() -> String {
     "DELETE foo".to_string()
 }
@@ -231,6 +477,13 @@ pub async fn delete_foo() -> String {
 
 /// axum handler for "GET /items" which uses `axum::extract::Query`.
 /// This extracts query parameters and creates a key-value pair map.
+#[utoipa::path(
+    get,
+    path = "/items",
+    tag = "demo",
+    params(("params" = Option<Object>, Query, style = Form, explode, description = "Any query parameters")),
+    responses((status = 200, description = "The query parameters as a string", body = String, content_type = "text/plain"))
+)]
 pub async fn get_items(
     axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
 ) -> String {
@@ -239,6 +492,13 @@ pub async fn get_items(
 
 /// axum handler for "GET /items/{id}" which uses `axum::extract::Path`.
 /// This extracts a path parameter then deserializes it as needed.
+#[utoipa::path(
+    get,
+    path = "/items/{id}",
+    tag = "demo",
+    params(("id" = String, Path, description = "Item id")),
+    responses((status = 200, description = "The path id as a string", body = String, content_type = "text/plain"))
+)]
 pub async fn get_items_id(axum::extract::Path(id): axum::extract::Path<String>) -> String {
     format!("Get items with path id: {:?}", id)
 }
@@ -246,186 +506,1191 @@ pub async fn get_items_id(axum::extract::Path(id): axum::extract::Path<String>)
 /////
 // Demo books using RESTful routes and a data store.
 //
-// This section uses a `Book` struct, a `DATA` variable
-// that is a lazy mutex global variable, and handlers
+// This section uses a `Book` struct, a `BookStore` data store
+// that axum passes to each handler via `State`, and handlers
 // that process the routes for HTTP verbs GET, PUT, etc.
 /////
 
-/// See file book.rs, which defines the `Book` struct.
-use crate::book::Book;
+/// See file book.rs, which defines the `Book` and `NewBook` structs.
+use crate::book::{Book, NewBook};
 
-/// See file data.rs, which defines the DATA global variable.
-use crate::data::DATA;
+/// See file data.rs, which defines the `BookStore` trait.
+use crate::data::{check_version, BatchOp, BatchOutcome, BookStore, Revision, StoredBook};
 
-/// Use Thread for spawning a thread e.g. to acquire our crate::DATA mutex lock.
-use std::thread;
+/// See file error.rs, which defines the `AppError` type.
+use crate::error::{negotiate_error_format, AppError, ErrorBody, FieldError};
 
-/// To access data, create a thread, spawn it, then get the lock.
-/// When you're done, then join the thread with its parent thread.
-#[allow(dead_code)]
-async fn print_data() {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(data) => {
-        println!("data: {:?}", data);
-    })
-    .join()
-    .unwrap()
-}
+/// See file format.rs, which defines response formats: HTML, JSON, CSV.
+use crate::format::{format_books, render_book, render_books, respond, split_suffix, Format, Negotiate};
+
+/// See file html.rs, which defines `Markup`, which is HTML that is safe
+/// to send, because its values are escaped by default.
+use crate::html::Markup;
+
+/// See file page.rs, which defines the HTML pages, which use templates,
+/// with a base layout, and which escape each value, such as a book title.
+use crate::page::{BookFormPage, BooksPage, FormView, MarkupPage, MessagePage, Table, TablePage};
+
+/// See file conditional.rs, which defines `ETag` and `If-Match` helpers.
+use crate::conditional::{
+    conditional_get, content_etag, etag, with_etag, IfMatch, CACHE_REVALIDATE, CACHE_STATIC, STARTED,
+};
+
+/// See file book_query.rs, which defines the `BookQuery` parameters.
+use crate::book_query::BookQuery;
+
+/// See file search.rs, which defines the `SearchHit` result.
+use crate::search::SearchHit;
+
+/// See file trash.rs, which defines the `TrashedBook` result.
+use crate::trash::TrashedBook;
+
+/// See file revision.rs, which defines the `Who` extractor and diffs.
+use crate::revision::{diff, diff_table, revisions_table, FieldChange, RevisionView, Who};
 
 /// axum handler for "GET /books" which responds with a resource page.
-/// This demo uses our DATA; a production app could use a database.
-/// This demo must clone the DATA in order to sort items by title.
-pub async fn get_books() -> axum::response::Html<String> {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(data) => {
-        let mut books = data.values().collect::<Vec<_>>().clone();
-        books.sort_by(|a, b| a.title.cmp(&b.title));
-        books
-            .iter()
-            .map(|&book| format!("<p>{}</p>\n", &book))
-            .collect::<String>()
-    })
-    .join()
-    .unwrap()
-    .into()
+/// This demo uses our data store; a production app could use a database.
+/// This demo filters, sorts, and paginates books by query parameters;
+/// see file book_query.rs for the parameters.
+///
+/// The response format is HTML, JSON, or CSV; see file format.rs.
+/// HTML has pagination controls; JSON and CSV have `Link` headers
+/// for the next and prev pages.
+///
+/// The response has caching headers, and is 304 Not Modified if the
+/// client already has it; see file conditional.rs. The `ETag` header
+/// is a hash of the page, because a page has no version of its own.
+#[utoipa::path(
+    get,
+    path = "/books",
+    tag = "books",
+    params(
+        BookQuery,
+    ),
+    responses(
+        (status = 200, description = "A page of books", content((Vec<Book> = "application/json"), (String = "text/html"), (String = "text/csv"))),
+        (status = 304, description = "Not Modified, because the client already has it"),
+        (status = 406, description = "Not Acceptable", body = ErrorBody),
+        (status = 422, description = "Invalid query parameters", body = ErrorBody),
+    )
+)]
+pub async fn get_books(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    uri: axum::http::Uri,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<BookQuery>,
+) -> Result<axum::response::Response, AppError> {
+    // Get the modified time before the books, so if a book changes in
+    // between, then the time is too old rather than too new, which costs
+    // a client one more download rather than a stale page.
+    let last_modified = state.store.last_modified().await?;
+    let page = query.apply(state.store.list().await?)?;
+    let href = |query: &BookQuery| format!("{}?{}", uri.path(), query.to_query_string());
+    let mut links = Vec::new();
+    let body = if format == Format::Html {
+        let books_page = BooksPage {
+            prev: page.prev.as_ref().map(href),
+            next: page.next.as_ref().map(href),
+            ..BooksPage::new("Books", &page.books)
+        };
+        Markup::render(&books_page)?.into_string()
+    } else {
+        links = [("next", &page.next), ("prev", &page.prev)]
+            .into_iter()
+            .filter_map(|(rel, query)| {
+                query.as_ref().map(|query| format!("<{}>; rel=\"{}\"", href(query), rel))
+            })
+            .collect();
+        format_books(format, "Books", &page.books)?
+    };
+    let link = links.join(", ");
+    let etag = content_etag(format!("{}\n{}", link, body).as_bytes());
+    let mut response = respond(format, body);
+    if !link.is_empty()
+        && let Ok(value) = axum::http::HeaderValue::from_str(&link)
+    {
+        response.headers_mut().insert(axum::http::header::LINK, value);
+    }
+    Ok(conditional_get(&headers, response, &etag, Some(last_modified), CACHE_REVALIDATE))
+}
+
+/// Query parameters for "GET /books/search", such as "?q=toni+morrison".
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    pub q: String,
+}
+
+/// axum handler for "GET /books/search" which responds with matching books.
+/// This demo ranks books by relevance across title and author, by using
+/// our search index; see file search.rs for stemming, phrases, and typos.
+/// The response format is HTML, JSON with scores, or CSV.
+#[utoipa::path(
+    get,
+    path = "/books/search",
+    tag = "books",
+    params(
+        SearchParams,
+    ),
+    responses(
+        (status = 200, description = "Matching books, best first", content((Vec<SearchHit> = "application/json"), (String = "text/html"), (String = "text/csv"))),
+        (status = 406, description = "Not Acceptable", body = ErrorBody),
+        (status = 422, description = "The search query is blank", body = ErrorBody),
+    )
+)]
+pub async fn get_books_search(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    axum::extract::Query(params): axum::extract::Query<SearchParams>,
+) -> Result<axum::response::Response, AppError> {
+    if params.q.trim().is_empty() {
+        return Err(AppError::Validation("Search query q must not be blank".into()));
+    }
+    // List the books once, rather than get each hit from the store.
+    let mut books: HashMap<u32, Book> = state.store.list().await?.into_iter().map(|book| (book.id, book)).collect();
+    let hits = state
+        .search
+        .search(&params.q)
+        .into_iter()
+        .filter_map(|(id, score)| books.remove(&id).map(|book| SearchHit { book, score }))
+        .collect::<Vec<_>>();
+    if format == Format::Json {
+        return Ok(respond(format, serde_json::to_string(&hits).unwrap_or_default()));
+    }
+    let books = hits.into_iter().map(|hit| hit.book).collect::<Vec<_>>();
+    render_books(format, &format!("Search: {}", params.q), &books)
+}
+
+/// axum handler for "GET /books/events" which responds with a stream of
+/// server-sent events, one per change to a book: "created", "updated", or
+/// "deleted", with the change as JSON data. A restored book is "created",
+/// because it is back in the listings.
+///
+/// A client that reconnects with a `Last-Event-ID` header gets the changes
+/// that it missed, if they are still in the feed's buffer, else a "reset"
+/// event, which means the client should get all books again. An idle
+/// stream gets a heartbeat comment now and then, to keep it open.
+#[utoipa::path(
+    get,
+    path = "/books/events",
+    tag = "books",
+    params(
+        ("Last-Event-ID" = Option<u64>, Header, description = "The last event id that the client has seen"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent events, each with a change as JSON data", body = Change, content_type = "text/event-stream"),
+        (status = 422, description = "Last-Event-ID is not a number", body = ErrorBody),
+    )
+)]
+pub async fn get_books_events(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    headers: axum::http::HeaderMap,
+) -> Result<axum::response::Response, AppError> {
+    let last_id = match headers.get("last-event-id") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .ok_or_else(|| AppError::Validation("Last-Event-ID must be an event id number".into()))?,
+        ),
+        None => None,
+    };
+    let events = event_stream(state.changes.subscribe(last_id), state.shutdown.subscribe());
+    let keep_alive = axum::response::sse::KeepAlive::new().interval(crate::changes::HEARTBEAT).text("heartbeat");
+    Ok(axum::response::sse::Sse::new(events).keep_alive(keep_alive).into_response())
+}
+
+/// axum handler for "GET /ws" which upgrades to a WebSocket for
+/// collaborative editing. A client subscribes to book ids, then gets
+/// each book, each change to it, and who else is viewing it. A client
+/// can edit a book, with the same checks as "POST /books/{id}/form".
+/// For the JSON messages, see file ws.rs.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "books",
+    params(
+        ("From" = Option<String>, Header, description = "Who makes the change, such as an email address"),
+    ),
+    responses(
+        (status = 101, description = "Switching Protocols to a WebSocket; see file ws.rs for its JSON messages"),
+    )
+)]
+pub async fn get_ws(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Who(who): Who,
+    ws: axum::extract::ws::WebSocketUpgrade,
+) -> axum::response::Response {
+    ws.on_upgrade(move |socket| crate::ws::run(socket, state, who))
 }
 
 /// axum handler for "PUT /books" which creates a new book resource.
-/// This demo shows how axum can extract JSON data into a Book struct.
+/// This demo shows how axum can extract JSON data into a NewBook struct,
+/// after it checks the data against the NewBook schema; see file schema.rs.
+/// The data store assigns the id, so any id in the request is ignored.
+/// The response is 201 Created, with the new book in the negotiated
+/// format, and a `Location` header with the new book's path.
+#[utoipa::path(
+    put,
+    path = "/books",
+    tag = "books",
+    params(
+        ("From" = Option<String>, Header, description = "Who makes the change, such as an email address"),
+        ("Idempotency-Key" = Option<String>, Header, description = "A unique key that makes a retry safe"),
+    ),
+    request_body = NewBook,
+    responses(
+        (status = 201, description = "Created, with a Location header with the new book path", content((Book = "application/json"), (String = "text/html"), (String = "text/csv"))),
+        (status = 422, description = "The request body is invalid", body = ErrorBody),
+    )
+)]
 pub async fn put_books(
-    axum::extract::Json(book): axum::extract::Json<Book>,
-) -> axum::response::Html<String> {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(mut data) => {
-        data.insert(book.id, book.clone());
-        format!("Put book: {}", &book)
-    })
-    .join()
-    .unwrap()
-    .into()
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    Who(who): Who,
+    ValidJson(new_book): ValidJson<NewBook>,
+) -> Result<axum::response::Response, AppError> {
+    let stored = state.store.create(new_book, &who).await?;
+    state.book_changed(ChangeKind::Created, &stored.book, Some(stored.version));
+    let location = format!("/books/{}", stored.book.id);
+    let response = with_etag(render_book(format, &stored.book)?, stored.version, format);
+    Ok((
+        axum::http::StatusCode::CREATED,
+        [(axum::http::header::LOCATION, location)],
+        response,
+    )
+        .into_response())
 }
 
-/// axum handler for "GET /books/{id}" which responds with one resource HTML page.
-/// This demo app uses our crate::DATA variable, and iterates on it to find the id.
-pub async fn get_books_id(
+/// axum handler for "PUT /books/{id}" which replaces a book resource.
+/// This demo shows how axum can extract JSON data into a Book struct,
+/// after it checks the data against the Book schema. The book id must
+/// match the path id, and the book must exist, because only the data
+/// store assigns ids. With an `If-Match` header, the book version must
+/// still match, else this responds with Precondition Failed. The response
+/// is the book in the negotiated format, as for "GET /books/{id}".
+#[utoipa::path(
+    put,
+    path = "/books/{id}",
+    tag = "books",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+        ("From" = Option<String>, Header, description = "Who makes the change, such as an email address"),
+        ("If-Match" = Option<String>, Header, description = "The book version that the client expects, as an ETag"),
+    ),
+    request_body = Book,
+    responses(
+        (status = 200, description = "The replaced book", content((Book = "application/json"), (String = "text/html"), (String = "text/csv"))),
+        (status = 404, description = "Book not found", body = ErrorBody),
+        (status = 412, description = "Precondition Failed, because the book has changed", body = ErrorBody),
+        (status = 422, description = "The book id does not match the path id", body = ErrorBody),
+    )
+)]
+pub async fn put_books_id(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    Who(who): Who,
+    if_match: IfMatch,
     axum::extract::Path(id): axum::extract::Path<u32>,
-) -> axum::response::Html<String> {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(data) => {
-        match data.get(&id) {
-            Some(book) => format!("<p>{}</p>\n", &book),
-            None => format!("<p>Book id {} not found</p>", id),
+    ValidJson(book): ValidJson<Book>,
+) -> Result<axum::response::Response, AppError> {
+    if book.id != id {
+        return Err(AppError::Validation(format!(
+            "Book id {} does not match path id {}",
+            book.id, id
+        )));
+    }
+    match state.store.update(book.clone(), if_match.expected(), &who).await? {
+        Some(stored) => {
+            state.book_changed(ChangeKind::Updated, &book, Some(stored.version));
+            Ok(with_etag(render_book(format, &stored.book)?, stored.version, format))
         }
-    })
-    .join()
-    .unwrap()
-    .into()
+        None => Err(book_not_found(id)),
+    }
+}
+
+/// axum handler for "PATCH /books/{id}" which updates some fields of a book,
+/// by using a JSON Merge Patch, per RFC 7396, such as `{"title": "Elektra"}`,
+/// and responds with the book in the negotiated format. The patched book
+/// must be valid for the Book schema, with the same id. With an `If-Match`
+/// header, the book version must still match, else this responds with
+/// Precondition Failed. Either way, the patch applies only to the version
+/// that we read, so a concurrent change fails, rather than being lost.
+#[utoipa::path(
+    patch,
+    path = "/books/{id}",
+    tag = "books",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+        ("From" = Option<String>, Header, description = "Who makes the change, such as an email address"),
+        ("If-Match" = Option<String>, Header, description = "The book version that the client expects, as an ETag"),
+    ),
+    request_body(content = Object, content_type = "application/merge-patch+json", description = "A JSON Merge Patch of the book"),
+    responses(
+        (status = 200, description = "The patched book", content((Book = "application/json"), (String = "text/html"), (String = "text/csv"))),
+        (status = 404, description = "Book not found", body = ErrorBody),
+        (status = 412, description = "Precondition Failed, because the book has changed", body = ErrorBody),
+        (status = 422, description = "The patched book is invalid, or its id changed", body = ErrorBody),
+    )
+)]
+pub async fn patch_books_id(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    Who(who): Who,
+    if_match: IfMatch,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+    axum::extract::Json(patch): axum::extract::Json<Value>,
+) -> Result<axum::response::Response, AppError> {
+    let stored = state.store.get(id).await?.ok_or_else(|| book_not_found(id))?;
+    check_version(id, stored.version, if_match.expected())?;
+    let mut value = serde_json::to_value(&stored.book).map_err(|err| AppError::Internal(err.to_string()))?;
+    json_patch::merge(&mut value, &patch);
+    validate::<Book>(&value)?;
+    let book: Book = serde_json::from_value(value).map_err(|err| AppError::Validation(err.to_string()))?;
+    if book.id != id {
+        return Err(AppError::Validation(format!("Book id {} must not change", id)));
+    }
+    match state.store.update(book.clone(), Some(&[stored.version]), &who).await? {
+        Some(stored) => {
+            state.book_changed(ChangeKind::Updated, &book, Some(stored.version));
+            Ok(with_etag(render_book(format, &stored.book)?, stored.version, format))
+        }
+        None => Err(book_not_found(id)),
+    }
+}
+
+/// axum handler for "GET /books/{id}" which responds with one resource page.
+/// This demo app uses our data store, and asks it to find the id.
+/// The id may have a suffix for the response format, such as "1.json".
+/// The `ETag` header is the book version and the format, such as "1-json",
+/// for use with `If-Match`, and the response is 304 Not Modified if the
+/// client already has it.
+#[utoipa::path(
+    get,
+    path = "/books/{id}",
+    tag = "books",
+    params(
+        ("id" = String, Path, description = "Book id, with an optional format suffix, such as \"1.json\""),
+    ),
+    responses(
+        (status = 200, description = "The book, with an ETag header with its version", content((Book = "application/json"), (String = "text/html"), (String = "text/csv"))),
+        (status = 304, description = "Not Modified, because the client already has it"),
+        (status = 404, description = "Book not found", body = ErrorBody),
+        (status = 406, description = "Not Acceptable", body = ErrorBody),
+        (status = 422, description = "The book id is not a number", body = ErrorBody),
+    )
+)]
+pub async fn get_books_id(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    headers: axum::http::HeaderMap,
+    axum::extract::Path(segment): axum::extract::Path<String>,
+) -> Result<axum::response::Response, AppError> {
+    let (id, _) = split_suffix(&segment)?;
+    let id = id
+        .parse::<u32>()
+        .map_err(|_| AppError::Validation(format!("Book id {} is not a number", id)))?;
+    match state.store.get(id).await? {
+        Some(stored) => Ok(conditional_get(
+            &headers,
+            render_book(format, &stored.book)?,
+            &etag(stored.version, format),
+            Some(stored.modified),
+            CACHE_REVALIDATE,
+        )),
+        None => Err(book_not_found(id)),
+    }
 }
 
 /// axum handler for "DELETE /books/{id}" which destroys a resource.
-/// This demo extracts an id, then mutates the book in the DATA store.
+/// This demo extracts an id, then deletes the book in the data store,
+/// which moves the book to the trash, so a librarian can restore it.
+/// With an `If-Match` header, the book version must still match.
+#[utoipa::path(
+    delete,
+    path = "/books/{id}",
+    tag = "books",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+        ("From" = Option<String>, Header, description = "Who makes the change, such as an email address"),
+        ("If-Match" = Option<String>, Header, description = "The book version that the client expects, as an ETag"),
+    ),
+    responses(
+        (status = 200, description = "The book is in the trash", body = String, content_type = "text/html"),
+        (status = 404, description = "Book not found", body = ErrorBody),
+        (status = 412, description = "Precondition Failed, because the book has changed", body = ErrorBody),
+    )
+)]
 pub async fn delete_books_id(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Who(who): Who,
+    if_match: IfMatch,
     axum::extract::Path(id): axum::extract::Path<u32>,
-) -> axum::response::Html<String> {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(mut data) => {
-        if data.contains_key(&id) {
-            data.remove(&id);
-            format!("Delete book id: {}", &id)
-        } else {
-            format!("Book id not found: {}", &id)
+) -> Result<Markup, AppError> {
+    match state.store.delete(id, if_match.expected(), &who).await? {
+        Some(book) => {
+            state.book_changed(ChangeKind::Deleted, &book, None);
+            message_page("Delete book", &format!("Delete book id: {}", id))
         }
-    })
-    .join()
-    .unwrap()
-    .into()
+        None => Err(book_not_found(id)),
+    }
+}
+
+/// axum handler for "GET /books/trash" which responds with deleted books,
+/// most recently deleted first. Normal listings exclude these books.
+/// The response format is HTML, JSON with deleted times, or CSV.
+#[utoipa::path(
+    get,
+    path = "/books/trash",
+    tag = "books",
+    responses(
+        (status = 200, description = "Deleted books, most recently deleted first", content((Vec<TrashedBook> = "application/json"), (String = "text/html"), (String = "text/csv"))),
+        (status = 406, description = "Not Acceptable", body = ErrorBody),
+    )
+)]
+pub async fn get_books_trash(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+) -> Result<axum::response::Response, AppError> {
+    let mut trash = state.store.trash().await?;
+    trash.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then(a.book.id.cmp(&b.book.id)));
+    if format == Format::Json {
+        let trash: Vec<TrashedBook> = trash.into_iter().filter_map(TrashedBook::from_stored).collect();
+        return Ok(respond(format, serde_json::to_string(&trash).unwrap_or_default()));
+    }
+    let books = trash.into_iter().map(|stored| stored.book).collect::<Vec<_>>();
+    render_books(format, "Trash", &books)
+}
+
+/// axum handler for "POST /books/{id}/restore" which restores a book
+/// from the trash, and responds with the book in the negotiated format.
+/// A restore is a change, so the book gets a new version.
+#[utoipa::path(
+    post,
+    path = "/books/{id}/restore",
+    tag = "books",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+        ("From" = Option<String>, Header, description = "Who makes the change, such as an email address"),
+    ),
+    responses(
+        (status = 200, description = "The restored book", content((Book = "application/json"), (String = "text/html"), (String = "text/csv"))),
+        (status = 404, description = "The book is not in the trash", body = ErrorBody),
+    )
+)]
+pub async fn post_books_id_restore(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    Who(who): Who,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+) -> Result<axum::response::Response, AppError> {
+    match state.store.restore(id, &who).await? {
+        Some(stored) => {
+            state.book_changed(ChangeKind::Created, &stored.book, Some(stored.version));
+            Ok(with_etag(render_book(format, &stored.book)?, stored.version, format))
+        }
+        None => Err(AppError::NotFound(format!("Book id {} is not in the trash", id))),
+    }
+}
+
+/// axum handler for "DELETE /books/trash" which empties the trash,
+/// by purging every book in it for good. A background task also purges
+/// books after a retention time; see file trash.rs.
+#[utoipa::path(
+    delete,
+    path = "/books/trash",
+    tag = "books",
+    responses(
+        (status = 200, description = "How many books are purged", body = String, content_type = "text/html"),
+    )
+)]
+pub async fn delete_books_trash(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Result<Markup, AppError> {
+    let purged = state.store.purge(None, std::time::SystemTime::now()).await?;
+    message_page("Purge books", &format!("Purge books: {}", purged.len()))
+}
+
+/// axum handler for "DELETE /books/trash/{id}" which purges one book
+/// from the trash for good.
+#[utoipa::path(
+    delete,
+    path = "/books/trash/{id}",
+    tag = "books",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+    ),
+    responses(
+        (status = 200, description = "The book is purged", body = String, content_type = "text/html"),
+        (status = 404, description = "The book is not in the trash", body = ErrorBody),
+    )
+)]
+pub async fn delete_books_trash_id(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+) -> Result<Markup, AppError> {
+    let purged = state.store.purge(Some(id), std::time::SystemTime::now()).await?;
+    if purged.is_empty() {
+        return Err(AppError::NotFound(format!("Book id {} is not in the trash", id)));
+    }
+    message_page("Purge book", &format!("Purge book id: {}", id))
+}
+
+/// axum handler for "POST /admin/rebuild" which rebuilds the projections,
+/// which are views of the books that we can always rebuild from the data
+/// store's source of truth: the data store's own view, such as by replaying
+/// its event log, then the search index.
+#[utoipa::path(
+    post,
+    path = "/admin/rebuild",
+    tag = "admin",
+    responses(
+        (status = 200, description = "How many events and books are rebuilt", body = String, content_type = "text/html"),
+        (status = 500, description = "Internal Server Error", body = ErrorBody),
+    )
+)]
+pub async fn post_admin_rebuild(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Result<Markup, AppError> {
+    let events = state.store.rebuild().await?;
+    let books = state.store.list().await?;
+    let count = books.len();
+    state.search.rebuild(books);
+    message_page("Rebuild", &format!("Rebuild: {} events, {} books", events, count))
+}
+
+/// axum handler for "POST /admin/webhooks" which registers a webhook,
+/// such as `{"url": "https://example.com/hooks", "events": ["created",
+/// "updated", "deleted"], "secret": "…"}`. When a book changes, we POST
+/// the change as JSON to each webhook that wants it, signed with its
+/// secret; see file webhook.rs. The response is 201 Created, with the
+/// webhook, without its secret, and a `Location` header with its path.
+#[utoipa::path(
+    post,
+    path = "/admin/webhooks",
+    tag = "admin",
+    request_body = NewWebhook,
+    responses(
+        (status = 201, description = "Created, with a Location header with the new webhook path", body = Webhook),
+        (status = 422, description = "The webhook is invalid", body = ErrorBody),
+    )
+)]
+pub async fn post_admin_webhooks(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Json(new_webhook): axum::extract::Json<NewWebhook>,
+) -> Result<axum::response::Response, AppError> {
+    let webhook = state.webhooks.register(new_webhook)?;
+    let location = format!("/admin/webhooks/{}", webhook.id);
+    Ok((
+        axum::http::StatusCode::CREATED,
+        [(axum::http::header::LOCATION, location)],
+        axum::Json(webhook),
+    )
+        .into_response())
+}
+
+/// axum handler for "GET /admin/webhooks" which responds with each webhook as JSON.
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Each webhook", body = Vec<Webhook>),
+    )
+)]
+pub async fn get_admin_webhooks(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> axum::Json<Vec<Webhook>> {
+    axum::Json(state.webhooks.list())
+}
+
+/// axum handler for "GET /admin/webhooks/{id}" which responds with one webhook as JSON.
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks/{id}",
+    tag = "admin",
+    params(
+        ("id" = u32, Path, description = "Webhook id"),
+    ),
+    responses(
+        (status = 200, description = "The webhook", body = Webhook),
+        (status = 404, description = "Webhook not found", body = ErrorBody),
+    )
+)]
+pub async fn get_admin_webhooks_id(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+) -> Result<axum::Json<Webhook>, AppError> {
+    state.webhooks.get(id).map(axum::Json).ok_or_else(|| webhook_not_found(id))
+}
+
+/// axum handler for "DELETE /admin/webhooks/{id}" which removes a webhook,
+/// so it gets no more deliveries, not even retries.
+#[utoipa::path(
+    delete,
+    path = "/admin/webhooks/{id}",
+    tag = "admin",
+    params(
+        ("id" = u32, Path, description = "Webhook id"),
+    ),
+    responses(
+        (status = 200, description = "The webhook is removed", body = String, content_type = "text/html"),
+        (status = 404, description = "Webhook not found", body = ErrorBody),
+    )
+)]
+pub async fn delete_admin_webhooks_id(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+) -> Result<Markup, AppError> {
+    match state.webhooks.remove(id) {
+        Some(_) => message_page("Delete webhook", &format!("Delete webhook id: {}", id)),
+        None => Err(webhook_not_found(id)),
+    }
+}
+
+/// axum handler for "GET /admin/webhooks/{id}/deliveries" which responds
+/// with the recent deliveries of a webhook as JSON, newest first, each
+/// with its state, attempts, and the last response status or error.
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks/{id}/deliveries",
+    tag = "admin",
+    params(
+        ("id" = u32, Path, description = "Webhook id"),
+    ),
+    responses(
+        (status = 200, description = "Recent deliveries, newest first", body = Vec<Delivery>),
+        (status = 404, description = "Webhook not found", body = ErrorBody),
+    )
+)]
+pub async fn get_admin_webhooks_id_deliveries(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+) -> Result<axum::Json<Vec<Delivery>>, AppError> {
+    match state.webhooks.get(id) {
+        Some(_) => Ok(axum::Json(state.webhooks.deliveries(id))),
+        None => Err(webhook_not_found(id)),
+    }
 }
 
 /// axum handler for "GET /books/{id}/form" which responds with a form.
 /// This demo shows how to write a typical HTML form with input fields.
+/// The hidden version field lets a save detect a newer save by someone else.
+#[utoipa::path(
+    get,
+    path = "/books/{id}/form",
+    tag = "books",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+    ),
+    responses(
+        (status = 200, description = "An HTML form to edit the book", body = String, content_type = "text/html"),
+        (status = 404, description = "Book not found", body = ErrorBody),
+    )
+)]
 pub async fn get_books_id_form(
+    axum::extract::State(state): axum::extract::State<AppState>,
     axum::extract::Path(id): axum::extract::Path<u32>,
-) -> axum::response::Html<String> {
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(data) => {
-        match data.get(&id) {
-            Some(book) => format!(
-                concat!(
-                    "<form method=\"post\" action=\"/books/{}/form\">\n",
-                    "<input type=\"hidden\" name=\"id\" value=\"{}\">\n",
-                    "<p><input name=\"title\" value=\"{}\"></p>\n",
-                    "<p><input name=\"author\" value=\"{}\"></p>\n",
-                    "<input type=\"submit\" value=\"Save\">\n",
-                    "</form>\n"
-                ),
-                &book.id, &book.id, &book.title, &book.author
-            ),
-            None => format!("<p>Book id {} not found</p>", id),
+) -> Result<Markup, AppError> {
+    match state.store.get(id).await? {
+        Some(StoredBook { book, version, .. }) => {
+            let fields = [
+                ("id", book.id.to_string()),
+                ("version", version.to_string()),
+                ("title", book.title),
+                ("author", book.author),
+            ]
+            .map(|(name, value)| (name.to_string(), value));
+            book_form_html(id, &fields, &[])
         }
-    })
-    .join()
-    .unwrap()
-    .into()
+        None => Err(book_not_found(id)),
+    }
+}
+
+/// Render the HTML form page for a book, with the text of each form
+/// field, such as from the book, or as a user submitted it, and a message
+/// for each field error. The errors for the hidden fields go at the top.
+/// See file `templates/partials/book_form.html`.
+fn book_form_html(id: u32, fields: &[(String, String)], errors: &[FieldError]) -> Result<Markup, AppError> {
+    let title = format!("Edit book {}", id);
+    Markup::render(&BookFormPage { title, id, form: FormView { fields, errors } })
+}
+
+/// Form fields for "POST /books/{id}/form": a book, and the version of
+/// the book that the form showed, which acts like an `If-Match` header.
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct BookForm {
+    pub id: u32,
+    #[schema(schema_with = crate::book::text_schema)]
+    pub title: String,
+    #[schema(schema_with = crate::book::text_schema)]
+    pub author: String,
+    pub version: u64,
 }
 
 /// axum handler for "POST /books/{id}/form" which submits an HTML form.
-/// This demo shows how to do a form submission then update a resource.
-pub async fn post_books_id_form(form: axum::extract::Form<Book>) -> axum::response::Html<String> {
-    let new_book: Book = form.0;
-    thread::spawn(move || {
-        match DATA.lock() {
-            Ok(mut data) => {
-        if data.contains_key(&new_book.id) {
-            data.insert(new_book.id, new_book.clone());
-            format!("Post book: {}", &new_book)
-        } else {
-            format!("Book id not found: {}", &new_book.id)
+/// This demo shows how to do a form submission then update a resource,
+/// by using the Post/Redirect/Get pattern: a save responds with See Other
+/// (303) and the book's path, so a browser reload doesn't post again.
+///
+/// The form fields must be valid for the BookForm schema, such as a title
+/// that isn't blank, and the form's hidden id field must match the path
+/// id; see file schema.rs. Else this responds with Unprocessable Entity
+/// (422), and the form again, with the submitted values, and a message
+/// for each field error. The form's hidden version field must match the
+/// stored version, else someone else saved first, and this responds with
+/// Precondition Failed (412).
+#[utoipa::path(
+    post,
+    path = "/books/{id}/form",
+    tag = "books",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+        ("From" = Option<String>, Header, description = "Who makes the change, such as an email address"),
+    ),
+    request_body(content = BookForm, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "See Other: the book is saved, and the Location header has its path"),
+        (status = 404, description = "Book not found", body = ErrorBody),
+        (status = 412, description = "Precondition Failed, because someone else saved first", body = ErrorBody),
+        (status = 422, description = "The form is invalid, so here it is again, with its errors", body = String, content_type = "text/html"),
+    )
+)]
+pub async fn post_books_id_form(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Who(who): Who,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+    axum::extract::Form(fields): axum::extract::Form<Vec<(String, String)>>,
+) -> Result<axum::response::Response, AppError> {
+    let value = form_value::<BookForm>(&fields);
+    let mut errors = crate::schema::field_errors::<BookForm>(&value);
+    if let Some(form_id) = value["id"].as_u64()
+        && form_id != u64::from(id)
+    {
+        errors.push(FieldError { field: "id".into(), message: format!("must match the path id {}", id) });
+    }
+    if !errors.is_empty() {
+        let html = book_form_html(id, &fields, &errors)?;
+        return Ok((axum::http::StatusCode::UNPROCESSABLE_ENTITY, html).into_response());
+    }
+    let form = serde_json::from_value::<BookForm>(value).map_err(|err| AppError::Validation(err.to_string()))?;
+    save_book_form(&state, &who, id, form).await?;
+    Ok(axum::response::Redirect::to(&format!("/books/{}", id)).into_response())
+}
+
+/// Save a book form for the book with a path id: check the form, then
+/// update the book in the data store, and tell everyone that it changed.
+/// The WebSocket edit command uses this too, so both have the same rules.
+pub async fn save_book_form(state: &AppState, who: &str, id: u32, form: BookForm) -> Result<StoredBook, AppError> {
+    let BookForm { id: form_id, title, author, version } = form;
+    if form_id != id {
+        return Err(AppError::Validation(format!(
+            "Book id {} does not match path id {}",
+            form_id, id
+        )));
+    }
+    let new_book = Book { id, title, author };
+    match state.store.update(new_book.clone(), Some(&[version]), who).await? {
+        Some(stored) => {
+            state.book_changed(ChangeKind::Updated, &new_book, Some(stored.version));
+            Ok(stored)
         }
+        None => Err(book_not_found(id)),
+    }
+}
+
+/// The maximum number of operations in one batch.
+pub const MAX_BATCH: usize = 1000;
+
+/// The mode of a batch: all-or-nothing (the default), or best-effort.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum BatchMode {
+    #[default]
+    Atomic,
+    BestEffort,
+}
+
+/// Request body for "POST /books:batch", such as:
+/// `{"mode": "best-effort", "operations": [{"op": "delete", "id": 1}]}`
+#[derive(Debug, serde::Deserialize, utoipa::ToSchema)]
+pub struct BatchRequest {
+    #[serde(default)]
+    pub mode: BatchMode,
+    pub operations: Vec<BatchOp>,
+}
+
+/// axum handler for "POST /books:batch" which creates, updates, and
+/// deletes many books in one request; see `BatchOp` in file data.rs.
+///
+/// The response is JSON with one result per operation, in order, with
+/// an HTTP status code per result, and the book or the error.
+///
+/// - "atomic": all-or-nothing. If every operation succeeds, then this
+///   responds with OK (200). Else nothing changes, and this responds with
+///   Conflict (409), where the failed operations have their errors, and
+///   the other operations have Failed Dependency (424).
+/// - "best-effort": each operation succeeds or fails on its own. If any
+///   fails, then this responds with Multi-Status (207).
+#[utoipa::path(
+    post,
+    path = "/books:batch",
+    tag = "books",
+    params(
+        ("From" = Option<String>, Header, description = "Who makes the change, such as an email address"),
+        ("Idempotency-Key" = Option<String>, Header, description = "A unique key that makes a retry safe"),
+    ),
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Every operation succeeded, with one result per operation", body = Object),
+        (status = 207, description = "Multi-Status: some operations in a best-effort batch failed", body = Object),
+        (status = 409, description = "Conflict: an atomic batch failed, so nothing changed", body = Object),
+        (status = 422, description = "The batch is empty or too big", body = ErrorBody),
+    )
+)]
+pub async fn post_books_batch(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Who(who): Who,
+    ValidJson(request): ValidJson<BatchRequest>,
+) -> Result<axum::response::Response, AppError> {
+    if request.operations.is_empty() || request.operations.len() > MAX_BATCH {
+        return Err(AppError::Validation(format!(
+            "A batch must have from 1 to {} operations",
+            MAX_BATCH
+        )));
+    }
+    let atomic = request.mode == BatchMode::Atomic;
+    let outcomes = state.store.batch(request.operations, atomic, &who).await?;
+    let status = if outcomes.iter().all(BatchOutcome::is_done) {
+        axum::http::StatusCode::OK
+    } else if atomic {
+        axum::http::StatusCode::CONFLICT
+    } else {
+        axum::http::StatusCode::MULTI_STATUS
+    };
+    let results: Vec<Value> = outcomes
+        .into_iter()
+        .map(|outcome| {
+            match &outcome {
+                BatchOutcome::Created(stored) => {
+                    state.book_changed(ChangeKind::Created, &stored.book, Some(stored.version))
+                }
+                BatchOutcome::Updated(stored) => {
+                    state.book_changed(ChangeKind::Updated, &stored.book, Some(stored.version))
+                }
+                BatchOutcome::Deleted(book) => state.book_changed(ChangeKind::Deleted, book, None),
+                _ => {}
+            }
+            batch_result(outcome)
+        })
+        .collect();
+    Ok((status, axum::Json(json!({ "results": results }))).into_response())
+}
+
+/// Convert the outcome of one batch operation into its JSON result:
+/// a status and the book, or a status and an error, as for a JSON error.
+fn batch_result(outcome: BatchOutcome) -> Value {
+    let err = match outcome {
+        BatchOutcome::Created(StoredBook { book, version, .. }) => {
+            return json!({"status": 201, "book": book, "version": version});
+        }
+        BatchOutcome::Updated(StoredBook { book, version, .. }) => {
+            return json!({"status": 200, "book": book, "version": version});
+        }
+        BatchOutcome::Deleted(book) => return json!({"status": 200, "book": book}),
+        BatchOutcome::NotFound(id) => book_not_found(id),
+        BatchOutcome::VersionMismatch { id, version } => StoreError::VersionMismatch { id, version }.into(),
+        BatchOutcome::Aborted => {
+            return json!({
+                "status": 424,
+                "error": "Failed Dependency",
+                "message": "Aborted because another operation in the batch failed",
+            });
+        }
+    };
+    json!({
+        "status": err.status().as_u16(),
+        "error": err.status().canonical_reason().unwrap_or_default(),
+        "message": err.message(),
     })
-    .join()
-    .unwrap()
-    .into()
+}
+
+/// axum handler for "GET /books/{id}/revisions" which responds with the
+/// revision history of a book, oldest first, including a book in the trash.
+/// Each revision has who made the change, when, and the old and new values.
+/// The response format is an HTML table, or JSON.
+#[utoipa::path(
+    get,
+    path = "/books/{id}/revisions",
+    tag = "revisions",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+    ),
+    responses(
+        (status = 200, description = "The revisions of the book, oldest first", content((Vec<RevisionView> = "application/json"), (String = "text/html"))),
+        (status = 404, description = "Book not found", body = ErrorBody),
+        (status = 406, description = "Not Acceptable", body = ErrorBody),
+    )
+)]
+pub async fn get_books_id_revisions(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+) -> Result<axum::response::Response, AppError> {
+    let revisions = state.store.revisions(id).await?;
+    if revisions.is_empty() {
+        return Err(book_not_found(id));
+    }
+    match format {
+        Format::Json => {
+            let views: Vec<RevisionView> = revisions.into_iter().map(RevisionView::from).collect();
+            Ok(respond(format, serde_json::to_string(&views).unwrap_or_default()))
+        }
+        _ => {
+            let title = format!("Revisions of book {}", id);
+            Ok(respond(revisions_format(format)?, table_page(title, revisions_table(&revisions))?))
+        }
+    }
+}
+
+/// axum handler for "GET /books/{id}/revisions/{n}" which responds with
+/// one revision of a book, as an HTML table, or JSON.
+#[utoipa::path(
+    get,
+    path = "/books/{id}/revisions/{n}",
+    tag = "revisions",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+        ("n" = u64, Path, description = "Revision number"),
+    ),
+    responses(
+        (status = 200, description = "One revision of the book", content((RevisionView = "application/json"), (String = "text/html"))),
+        (status = 404, description = "Book or revision not found", body = ErrorBody),
+        (status = 406, description = "Not Acceptable", body = ErrorBody),
+    )
+)]
+pub async fn get_books_id_revisions_n(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    axum::extract::Path((id, n)): axum::extract::Path<(u32, u64)>,
+) -> Result<axum::response::Response, AppError> {
+    let revision = find_revision(state.store.revisions(id).await?, id, n)?;
+    match format {
+        Format::Json => Ok(respond(
+            format,
+            serde_json::to_string(&RevisionView::from(revision)).unwrap_or_default(),
+        )),
+        _ => {
+            let title = format!("Revision {} of book {}", n, id);
+            Ok(respond(revisions_format(format)?, table_page(title, revisions_table(&[revision]))?))
+        }
+    }
+}
+
+/// Query parameters for "GET /books/{id}/revisions/diff", such as
+/// "?from=1&to=3". The default `to` is the latest revision.
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiffParams {
+    pub from: u64,
+    pub to: Option<u64>,
+}
+
+/// axum handler for "GET /books/{id}/revisions/diff" which compares the
+/// book's values at two revisions, field by field, and responds with each
+/// field that differs, as an HTML table, or JSON.
+#[utoipa::path(
+    get,
+    path = "/books/{id}/revisions/diff",
+    tag = "revisions",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+        DiffParams,
+    ),
+    responses(
+        (status = 200, description = "Each field that differs between the revisions", content((Vec<FieldChange> = "application/json"), (String = "text/html"))),
+        (status = 404, description = "Book or revision not found", body = ErrorBody),
+        (status = 406, description = "Not Acceptable", body = ErrorBody),
+    )
+)]
+pub async fn get_books_id_revisions_diff(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+    axum::extract::Query(params): axum::extract::Query<DiffParams>,
+) -> Result<axum::response::Response, AppError> {
+    let revisions = state.store.revisions(id).await?;
+    let to = match params.to {
+        Some(to) => to,
+        None => revisions.last().map(|revision| revision.number).ok_or_else(|| book_not_found(id))?,
+    };
+    let old = find_revision(revisions.clone(), id, params.from)?;
+    let new = find_revision(revisions, id, to)?;
+    let changes: Vec<FieldChange> = diff(old.new.as_ref(), new.new.as_ref());
+    match format {
+        Format::Json => Ok(respond(
+            format,
+            json!({"from": params.from, "to": to, "changes": changes}).to_string(),
+        )),
+        _ => {
+            let title = format!("Diff of book {} from {} to {}", id, params.from, to);
+            Ok(respond(revisions_format(format)?, table_page(title, diff_table(&changes))?))
+        }
+    }
+}
+
+/// axum handler for "POST /books/{id}/revisions/{n}/revert" which sets
+/// a book back to its values at a revision. A revert is an update, so it
+/// creates a new revision, and the history keeps every revision before.
+/// With an `If-Match` header, the book version must still match.
+#[utoipa::path(
+    post,
+    path = "/books/{id}/revisions/{n}/revert",
+    tag = "revisions",
+    params(
+        ("id" = u32, Path, description = "Book id"),
+        ("n" = u64, Path, description = "Revision number"),
+        ("From" = Option<String>, Header, description = "Who makes the change, such as an email address"),
+        ("If-Match" = Option<String>, Header, description = "The book version that the client expects, as an ETag"),
+    ),
+    responses(
+        (status = 200, description = "The book, set back to its values at the revision", content((Book = "application/json"), (String = "text/html"), (String = "text/csv"))),
+        (status = 404, description = "Book or revision not found", body = ErrorBody),
+        (status = 412, description = "Precondition Failed, because the book has changed", body = ErrorBody),
+        (status = 422, description = "The revision is a delete", body = ErrorBody),
+    )
+)]
+pub async fn post_books_id_revisions_n_revert(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Negotiate(format): Negotiate,
+    Who(who): Who,
+    if_match: IfMatch,
+    axum::extract::Path((id, n)): axum::extract::Path<(u32, u64)>,
+) -> Result<axum::response::Response, AppError> {
+    let revision = find_revision(state.store.revisions(id).await?, id, n)?;
+    let Some(book) = revision.new else {
+        return Err(AppError::Validation(format!(
+            "Revision {} of book id {} is a delete, so it has no values to revert to",
+            n, id
+        )));
+    };
+    match state.store.update(book, if_match.expected(), &who).await? {
+        Some(stored) => {
+            state.book_changed(ChangeKind::Updated, &stored.book, Some(stored.version));
+            Ok(with_etag(render_book(format, &stored.book)?, stored.version, format))
+        }
+        None => Err(book_not_found(id)),
+    }
+}
+
+/// axum handler for "GET /schemas/{name}.json" which responds with the
+/// JSON Schema of a request body type, such as "/schemas/Book.json".
+/// Each schema has the constraints that handlers check, such as the
+/// minimum and maximum length of a book title; see file schema.rs.
+#[utoipa::path(
+    get,
+    path = "/schemas/{name}",
+    tag = "books",
+    params(
+        ("name" = String, Path, description = "Schema name then \".json\", such as \"Book.json\""),
+    ),
+    responses(
+        (status = 200, description = "The JSON Schema", body = Object, content_type = "application/schema+json"),
+        (status = 404, description = "Schema not found", body = ErrorBody),
+    )
+)]
+pub async fn get_schemas_name(
+    axum::extract::Path(file): axum::extract::Path<String>,
+) -> Result<axum::response::Response, AppError> {
+    let Some(schema) = file.strip_suffix(".json").and_then(crate::schema::schema) else {
+        return Err(AppError::NotFound(format!(
+            "Schema {} not found; the schemas are: {}",
+            file,
+            crate::schema::schema_names().join(", ")
+        )));
+    };
+    Ok((
+        [(axum::http::header::CONTENT_TYPE, "application/schema+json")],
+        schema.to_string(),
+    )
+        .into_response())
+}
+
+/// Find revision `n` of a book, or return Not Found.
+fn find_revision(revisions: Vec<Revision>, id: u32, n: u64) -> Result<Revision, AppError> {
+    revisions
+        .into_iter()
+        .find(|revision| revision.number == n)
+        .ok_or_else(|| AppError::NotFound(format!("Book id {} has no revision {}", id, n)))
+}
+
+/// Revisions are an HTML table, so a request for CSV is Not Acceptable.
+fn revisions_format(format: Format) -> Result<Format, AppError> {
+    match format {
+        Format::Csv => Err(AppError::NotAcceptable("Revisions are HTML or JSON, not CSV".into())),
+        _ => Ok(Format::Html),
+    }
+}
+
+/// Create the error for a webhook id that is not registered.
+fn webhook_not_found(id: u32) -> AppError {
+    AppError::NotFound(format!("Webhook id {} not found", id))
+}
+
+/// Create the error for a book id that is not in our data store.
+pub fn book_not_found(id: u32) -> AppError {
+    AppError::NotFound(format!("Book id {} not found", id))
 }
 
 ////
 // HTML rendering helpers.
 ////
 
-/// Render strings into an HTML table tag.
-pub fn html_table_tag(table: Vec<Vec<String>>) -> String {
-    format!("<table>\n{}</table>\n", html_table_tr_tags(table))
-}
-
-/// Render strings into HTML table tr tags.
-pub fn html_table_tr_tags(rows: Vec<Vec<String>>) -> String {
-    rows.iter()
-        .map(|row| 
-            format!("<tr>{}</tr>\n", html_table_td_tags(row))
-        )
-        .collect::<String>()
+/// Render a page with one message, such as what a change did.
+/// See file `templates/message.html`.
+pub fn message_page(title: &str, message: &str) -> Result<Markup, AppError> {
+    Markup::render(&MessagePage { title, message })
 }
 
-/// Render strings into HTML table td tags.
-pub fn html_table_td_tags(cells: &Vec<String>) -> String {
-    cells.iter().map(|cell| 
-        format!("<td>{}</td>", cell)
-    ).collect::<String>()
+/// Render a page with a table, such as revisions, as an HTML string.
+/// See file `templates/partials/table.html`.
+pub fn table_page(title: String, table: Table) -> Result<String, AppError> {
+    Ok(Markup::render(&TablePage { title, table })?.into_string())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use axum_test::TestServer;
+    use axum::http::{header, StatusCode};
+    use axum_test::{TestRequest, TestServer};
+
+    /// A test server of a new app, which has our demo books.
+    fn server() -> TestServer {
+        TestServer::new(app()).unwrap()
+    }
+
+    /// Ask for JSON, rather than the default HTML, such as for an error.
+    fn json(request: TestRequest) -> TestRequest {
+        request.add_header(header::ACCEPT, "application/json")
+    }
+
+    /// The content of an HTML page, which is between its layout's main tags.
+    fn main_of(html: &str) -> &str {
+        html.split_once("<main>\n")
+            .and_then(|(_, rest)| rest.split_once("</main>"))
+            .map_or(html, |(main, _)| main)
+    }
+
+    /// Get the content of an HTML page, such as "/books".
+    async fn page(server: &TestServer, uri: &str) -> String {
+        main_of(&server.get(uri).await.text()).to_string()
+    }
 
     #[tokio::test]
     async fn uptime() {
-        let server = TestServer::new(app()).unwrap();
+        let server = server();
         let response_text_0 = server.get("/uptime").await.text();
         std::thread::sleep(std::time::Duration::from_secs(1));
         let response_text_1 = server.get("/uptime").await.text();
@@ -434,20 +1699,602 @@ mod tests {
 
     #[tokio::test]
     async fn count() {
-        let server = TestServer::new(app()).unwrap();
-            let response_text_0 = server.get("/count").await.text();
+        let server = server();
+        let response_text_0 = server.get("/count").await.text();
         let response_text_1 = server.get("/count").await.text();
         assert!(response_text_0 < response_text_1, "{} < {}", response_text_0, response_text_1);
     }
 
     #[tokio::test]
     async fn epoch() {
-        let server = TestServer::new(app()).unwrap();
+        let server = server();
         let response_text_0 = server.get("/epoch").await.text();
         std::thread::sleep(std::time::Duration::from_secs(1));
         let response_text_1 = server.get("/epoch").await.text();
         assert!(response_text_0 < response_text_1, "{} < {}", response_text_0, response_text_1)
     }
 
+    #[tokio::test]
+    async fn html_pages() {
+        let server = server();
+        // Each demo page has the shared layout, with its title and navigation.
+        let pages = [
+            ("/string.html", "Headline", "<p>Paragraph</p>\n"),
+            ("/demo.html", "Hello", "<p>Hello, World!</p>\n"),
+            ("/file.html", "Respond with HTML file", "<p>This is our example HTML file.</p>\n"),
+        ];
+        for (path, title, main) in pages {
+            let html = server.get(path).await.text();
+            assert!(html.starts_with("<!doctype html>\n"), "{}", html);
+            assert!(html.contains(&format!("<title>{} - Demo Rust Axum</title>", title)), "{}", html);
+            assert!(html.contains("<nav>\n"), "{}", html);
+            assert_eq!(main_of(&html), main);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_books() {
+        let server = server();
+        let html = server.get("/books").await.text();
+        assert_eq!(main_of(&html), "<p>Antigone by Sophocles</p>\n<p>Beloved by Toni Morrison</p>\n<p>Candide by Voltaire</p>\n");
+        // Each page has the shared layout, with its title and navigation.
+        assert!(html.starts_with("<!doctype html>\n"));
+        assert!(html.contains("<title>Books - Demo Rust Axum</title>"));
+        assert!(html.contains("<nav>\n<a href=\"/books\">Books</a>\n"));
+        let html = server.get("/books/1").await.text();
+        assert!(html.contains("<title>Antigone - Demo Rust Axum</title>"));
+        let html = server.get("/books/9").await.text();
+        assert!(html.contains("<title>Not Found - Demo Rust Axum</title>"));
+    }
+
+    #[tokio::test]
+    async fn delete_books_id() {
+        let server = server();
+        assert_eq!(main_of(&server.delete("/books/1").await.text()), "<p>Delete book id: 1</p>\n");
+        server.get("/books/1").await.assert_status_not_found();
+        // Each app has its own data store, so other servers still have the book.
+        assert_eq!(page(&self::server(), "/books/1").await, "<p>Antigone by Sophocles</p>\n");
+    }
+
+    #[tokio::test]
+    async fn get_books_id_not_found() {
+        let server = server();
+        let response = server.get("/books/9").await;
+        response.assert_status_not_found();
+        assert_eq!(main_of(&response.text()), "<p>Book id 9 not found</p>\n");
+        let response = json(server.get("/books/9")).await;
+        response.assert_status_not_found();
+        response.assert_json(&json!({
+            "status": 404,
+            "error": "Not Found",
+            "message": "Book id 9 not found"
+        }));
+    }
+
+    #[tokio::test]
+    async fn put_books_assigns_id() {
+        let server = server();
+        // The store assigns the id, so a client can't overwrite book 1.
+        let j = json!({"id": 1, "title": "Elektra", "author": "Sophocles"});
+        let response = json(server.put("/books")).json(&j).await;
+        response.assert_status(StatusCode::CREATED);
+        response.assert_header(header::LOCATION, "/books/4");
+        response.assert_json(&json!({"id": 4, "title": "Elektra", "author": "Sophocles"}));
+        assert_eq!(page(&server, "/books/1").await, "<p>Antigone by Sophocles</p>\n");
+    }
+
+    #[tokio::test]
+    async fn put_books_with_invalid_fields() {
+        let server = server();
+        let j = json!({"title": " ", "author": "a".repeat(256)});
+        let response = json(server.put("/books")).json(&j).await;
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        let errors = response.json::<Value>()["errors"].clone();
+        let fields: Vec<&str> = errors.as_array().unwrap().iter().map(|e| e["field"].as_str().unwrap()).collect();
+        assert_eq!(fields.len(), 2);
+        assert!(fields.contains(&"title") && fields.contains(&"author"));
+        // A form gets every field error at once too.
+        let data = [["id", "one"], ["version", "1"], ["title", ""]];
+        let response = server.post("/books/1/form").form(&data).await;
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        let text = response.text();
+        for error in ["id must be an integer", "author is required", "title must not be empty"] {
+            assert!(text.contains(error), "{}", text);
+        }
+        assert_eq!(page(&server, "/books/1").await, "<p>Antigone by Sophocles</p>\n");
+    }
+
+    #[tokio::test]
+    async fn get_schemas_name() {
+        let server = server();
+        let response = server.get("/schemas/Book.json").await;
+        response.assert_header(header::CONTENT_TYPE, "application/schema+json");
+        let schema = response.json::<Value>();
+        assert_eq!(schema["$schema"], crate::schema::DIALECT);
+        assert_eq!(schema["properties"]["title"]["maxLength"], 255);
+        let schema = server.get("/schemas/BatchRequest.json").await.json::<Value>();
+        assert_eq!(schema["$defs"]["BatchOp"]["oneOf"][0]["properties"]["book"]["$ref"], "#/$defs/NewBook");
+        assert!(schema["$defs"]["NewBook"].is_object());
+        server.get("/schemas/Book").await.assert_status_not_found();
+        server.get("/schemas/Nope.json").await.assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn post_books_id_form_with_mismatched_id() {
+        let server = server();
+        let data = [["id", "2"], ["version", "1"], ["title", " "], ["author", "Sophocles \"Soph\" <of Athens>"]];
+        let response = server.post("/books/1/form").form(&data).await;
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        // The form shows again, with the submitted values, and each error by its field.
+        let html = response.text();
+        let form = "<form method=\"post\" action=\"/books/1/form\">\n";
+        assert!(main_of(&html).starts_with(&format!("{}<p class=\"error\">id must match the path id 1</p>\n", form)));
+        assert!(html.contains("<input type=\"hidden\" name=\"id\" value=\"2\">"));
+        assert!(html.contains("<p><input name=\"title\" value=\" \"></p>\n<p class=\"error\">title must not be blank</p>\n"));
+        assert!(html.contains("<p><input name=\"author\" value=\"Sophocles &#34;Soph&#34; &#60;of Athens&#62;\"></p>\n<input"));
+        assert_eq!(page(&server, "/books/1").await, "<p>Antigone by Sophocles</p>\n");
+    }
+
+    #[tokio::test]
+    async fn get_books_with_pagination() {
+        let server = server();
+        let response = json(server.get("/books?limit=2&sort=author&order=desc")).await;
+        response.assert_json(&json!([
+            {"id": 3, "title": "Candide", "author": "Voltaire"},
+            {"id": 2, "title": "Beloved", "author": "Toni Morrison"}
+        ]));
+        let link = response.header(header::LINK);
+        let link = link.to_str().unwrap();
+        assert!(link.ends_with("; rel=\"next\""), "{}", link);
+        let next = link.trim_start_matches('<').split('>').next().unwrap();
+        let response = json(server.get(next)).await;
+        response.assert_json(&json!([{"id": 1, "title": "Antigone", "author": "Sophocles"}]));
+        let html = server.get(next).await.text();
+        assert!(html.contains("<a rel=\"prev\" href=\"/books?limit=2&"), "{}", html);
+    }
+
+    #[tokio::test]
+    async fn get_books_search() {
+        let server = server();
+        assert_eq!(page(&server, "/books/search?q=morison").await, "<p>Beloved by Toni Morrison</p>\n");
+        let j = json!({"title": "Decameron", "author": "Giovanni Boccaccio"});
+        server.put("/books").json(&j).await.assert_status(StatusCode::CREATED);
+        assert_eq!(page(&server, "/books/search?q=decameron").await, "<p>Decameron by Giovanni Boccaccio</p>\n");
+        server.delete("/books/4").await.assert_status_ok();
+        assert_eq!(page(&server, "/books/search?q=decameron").await, "");
+        server.get("/books/search?q=").await.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn get_books_id_with_content_negotiation() {
+        let server = server();
+        let j = json!({"id": 1, "title": "Antigone", "author": "Sophocles"});
+        json(server.get("/books/1")).await.assert_json(&j);
+        server.get("/books/1.json").await.assert_json(&j);
+        // A suffix wins over the Accept header; see file format.rs.
+        assert_eq!(main_of(&json(server.get("/books/1.html")).await.text()), "<p>Antigone by Sophocles</p>\n");
+        server.get("/books.csv?limit=1").await.assert_text("id,title,author\r\n1,Antigone,Sophocles\r\n");
+        server.get("/books/1.xml").await.assert_status(StatusCode::NOT_ACCEPTABLE);
+        let not_found = json!({"status": 404, "error": "Not Found", "message": "Book id 9 not found"});
+        server.get("/books/9.json").await.assert_json(&not_found);
+    }
+
+    #[tokio::test]
+    async fn post_books_id_form_with_stale_version() {
+        let server = server();
+        server.get("/books/1").await.assert_header(header::ETAG, "\"1-html\"");
+        assert!(server.get("/books/1/form").await.text().contains("name=\"version\" value=\"1\""));
+        // Two editors load version 1; the first save wins.
+        let data = [["id", "1"], ["version", "1"], ["title", "Elektra"], ["author", "Sophocles"]];
+        let response = server.post("/books/1/form").form(&data).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        response.assert_header(header::LOCATION, "/books/1");
+        server.get("/books/1").await.assert_header(header::ETAG, "\"2-html\"");
+        let data = [["id", "1"], ["version", "1"], ["title", "Ajax"], ["author", "Sophocles"]];
+        server.post("/books/1/form").form(&data).await.assert_status(StatusCode::PRECONDITION_FAILED);
+        assert_eq!(page(&server, "/books/1").await, "<p>Elektra by Sophocles</p>\n");
+        // A delete or a replace with a stale If-Match fails too.
+        let stale = (header::IF_MATCH, "\"1\"");
+        server.delete("/books/1").add_header(stale.0.clone(), stale.1).await.assert_status(StatusCode::PRECONDITION_FAILED);
+        let j = json!({"id": 1, "title": "Ajax", "author": "Sophocles"});
+        server.put("/books/1").add_header(stale.0, stale.1).json(&j).await.assert_status(StatusCode::PRECONDITION_FAILED);
+        let response = server.put("/books/1").add_header(header::IF_MATCH, "\"2-json\"").json(&j).await;
+        response.assert_header(header::ETAG, "\"3-html\"");
+        // A JSON client gets the replaced book back as JSON.
+        let response = json(server.put("/books/1").add_header(header::IF_MATCH, "\"3-html\"")).json(&j).await;
+        response.assert_json(&j);
+        response.assert_header(header::ETAG, "\"4-json\"");
+        server.delete("/books/1").add_header(header::IF_MATCH, "\"4-json\"").await.assert_status_ok();
+    }
+
+    #[tokio::test]
+    async fn patch_books_id() {
+        let server = server();
+        let patch = |uri| server.patch(uri).content_type("application/merge-patch+json");
+        let response = json(patch("/books/1")).bytes(json!({"title": "Elektra"}).to_string().into()).await;
+        response.assert_json(&json!({"id": 1, "title": "Elektra", "author": "Sophocles"}));
+        response.assert_header(header::ETAG, "\"2-json\"");
+        assert_eq!(page(&server, "/books/search?q=elektra").await, "<p>Elektra by Sophocles</p>\n");
+        // A patch with a stale If-Match fails, and changes nothing.
+        let body = json!({"author": "Euripides"}).to_string();
+        let response = patch("/books/1").add_header(header::IF_MATCH, "\"1\"").bytes(body.clone().into()).await;
+        response.assert_status(StatusCode::PRECONDITION_FAILED);
+        patch("/books/1").add_header(header::IF_MATCH, "\"2\"").bytes(body.into()).await.assert_status_ok();
+        // The patched book must be valid, with the same id.
+        let body = json!({"title": null}).to_string();
+        patch("/books/1").bytes(body.into()).await.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        let body = json!({"id": 2}).to_string();
+        patch("/books/1").bytes(body.into()).await.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(page(&server, "/books/1").await, "<p>Elektra by Euripides</p>\n");
+        let body = json!({"title": "Elektra"}).to_string();
+        patch("/books/9").bytes(body.into()).await.assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn get_not_modified() {
+        let server = server();
+        let response = server.get("/books/1").await;
+        response.assert_header(header::CACHE_CONTROL, "no-cache");
+        let last_modified = response.header(header::LAST_MODIFIED);
+        let if_none_match = (header::IF_NONE_MATCH, "\"1-html\"");
+        server.get("/books/1").add_header(if_none_match.0.clone(), if_none_match.1).await.assert_status(StatusCode::NOT_MODIFIED);
+        server.get("/books/1").add_header(header::IF_MODIFIED_SINCE, last_modified).await.assert_status(StatusCode::NOT_MODIFIED);
+        // Each format has its own tag, so a client with the JSON has not the HTML.
+        let response = json(server.get("/books/1")).await;
+        response.assert_header(header::ETAG, "\"1-json\"");
+        response.assert_header(header::VARY, "Accept");
+        server.get("/books/1").add_header(header::IF_NONE_MATCH, "\"1-json\"").await.assert_status_ok();
+        // A list revalidates by its hash, so a change to any book changes it.
+        let books_etag = server.get("/books").await.header(header::ETAG);
+        server.get("/books").add_header(header::IF_NONE_MATCH, books_etag.clone()).await.assert_status(StatusCode::NOT_MODIFIED);
+        server.delete("/books/2").await.assert_status_ok();
+        server.get("/books").add_header(header::IF_NONE_MATCH, books_etag).await.assert_status_ok();
+        let data = [["id", "1"], ["version", "1"], ["title", "Elektra"], ["author", "Sophocles"]];
+        server.post("/books/1/form").form(&data).await.assert_status(StatusCode::SEE_OTHER);
+        let response = server.get("/books/1").add_header(if_none_match.0, if_none_match.1).await;
+        assert_eq!(main_of(&response.text()), "<p>Elektra by Sophocles</p>\n");
+        // Files that we include at compile time have caching headers too.
+        for path in ["/file.html", "/demo.png", "/demo.json"] {
+            let response = server.get(path).await;
+            response.assert_header(header::CACHE_CONTROL, "public, max-age=3600");
+            let etag = response.header(header::ETAG);
+            let response = server.get(path).add_header(header::IF_NONE_MATCH, etag).await;
+            response.assert_status(StatusCode::NOT_MODIFIED);
+            assert!(response.as_bytes().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn put_books_with_idempotency_key() {
+        let server = server();
+        let key = (axum::http::HeaderName::from_static("idempotency-key"), "5f0c6a2e");
+        let j = json!({"title": "Decameron", "author": "Giovanni Boccaccio"});
+        let first = server.put("/books").add_header(key.0.clone(), key.1).json(&j).await;
+        first.assert_status(StatusCode::CREATED);
+        // A retry replays the first response, rather than create another book.
+        let retry = server.put("/books").add_header(key.0.clone(), key.1).json(&j).await;
+        retry.assert_status(StatusCode::CREATED);
+        retry.assert_header(header::LOCATION, "/books/4");
+        retry.assert_header("idempotent-replayed", "true");
+        assert_eq!(retry.text(), first.text());
+        server.get("/books/5").await.assert_status_not_found();
+        // Reusing the key for a different request is a client bug.
+        let j = json!({"title": "Elektra", "author": "Sophocles"});
+        let response = json(server.put("/books").add_header(key.0.clone(), key.1)).json(&j).await;
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.json::<Value>()["status"], 422);
+        // A retry with another precondition is another request, rather than
+        // a replay of the first, which would hide that its version is stale.
+        let key = (key.0, "9b1d7c44");
+        let j = json!({"id": 1, "title": "Elektra", "author": "Sophocles"});
+        let put = |version| server.put("/books/1").add_header(key.0.clone(), key.1).add_header(header::IF_MATCH, version);
+        put("\"1\"").json(&j).await.assert_status_ok();
+        put("\"9\"").json(&j).await.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn post_books_batch() {
+        let server = server();
+        let operations = json!([
+            {"op": "create", "book": {"title": "Decameron", "author": "Giovanni Boccaccio"}},
+            {"op": "update", "book": {"id": 2, "title": "Jazz", "author": "Toni Morrison"}, "version": 1},
+            {"op": "delete", "id": 9}
+        ]);
+        // All-or-nothing, by default: the missing book fails the batch.
+        let response = server.post("/books:batch").json(&json!({"operations": operations})).await;
+        response.assert_status(StatusCode::CONFLICT);
+        let results = response.json::<Value>()["results"].clone();
+        assert_eq!(results[0]["status"], 424);
+        assert_eq!(results[2], json!({"status": 404, "error": "Not Found", "message": "Book id 9 not found"}));
+        assert_eq!(page(&server, "/books/2").await, "<p>Beloved by Toni Morrison</p>\n");
+        // Best-effort: the other operations succeed, and the search index sees them.
+        let response = server.post("/books:batch").json(&json!({"mode": "best-effort", "operations": operations})).await;
+        response.assert_status(StatusCode::MULTI_STATUS);
+        let results = response.json::<Value>()["results"].clone();
+        assert_eq!(results[0], json!({"status": 201, "book": {"id": 4, "title": "Decameron", "author": "Giovanni Boccaccio"}, "version": 1}));
+        assert_eq!(results[1]["version"], 2);
+        assert_eq!(page(&server, "/books/search?q=jazz").await, "<p>Jazz by Toni Morrison</p>\n");
+        let j = json!({"operations": [{"op": "delete", "id": 4}, {"op": "delete", "id": 2, "version": 2}]});
+        server.post("/books:batch").json(&j).await.assert_status_ok();
+        server.post("/books:batch").json(&json!({"operations": []})).await.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn delete_books_id_moves_to_trash() {
+        let server = server();
+        server.delete("/books/1").await.assert_status_ok();
+        assert_eq!(page(&server, "/books").await, "<p>Beloved by Toni Morrison</p>\n<p>Candide by Voltaire</p>\n");
+        assert_eq!(page(&server, "/books/search?q=antigone").await, "");
+        assert_eq!(page(&server, "/books/trash").await, "<p>Antigone by Sophocles</p>\n");
+        let trash = json(server.get("/books/trash")).await.json::<Value>();
+        assert_eq!(trash[0]["id"], 1);
+        assert!(trash[0]["deleted_at"].is_string());
+        // A restore makes a new version, and the book is in listings again.
+        let response = server.post("/books/1/restore").await;
+        assert_eq!(main_of(&response.text()), "<p>Antigone by Sophocles</p>\n");
+        response.assert_header(header::ETAG, "\"3-html\"");
+        assert_eq!(page(&server, "/books/search?q=antigone").await, "<p>Antigone by Sophocles</p>\n");
+        server.post("/books/1/restore").await.assert_status_not_found();
+        // A purge deletes a book for good.
+        server.delete("/books/1").await.assert_status_ok();
+        server.delete("/books/2").await.assert_status_ok();
+        assert_eq!(main_of(&server.delete("/books/trash/1").await.text()), "<p>Purge book id: 1</p>\n");
+        server.delete("/books/trash/1").await.assert_status_not_found();
+        assert_eq!(main_of(&server.delete("/books/trash").await.text()), "<p>Purge books: 1</p>\n");
+        server.post("/books/2/restore").await.assert_status_not_found();
+    }
+
+    #[tokio::test]
+    async fn get_books_events() {
+        use tokio_stream::StreamExt;
+        use tower::ServiceExt;
+        let state = AppState::new(Arc::new(InMemoryBookStore::new(demo_books()))).await.unwrap();
+        let server = TestServer::new(app_with_state(state.clone())).unwrap();
+        server.delete("/books/1").await.assert_status_ok();
+        server.put("/books").json(&json!({"title": "Faust", "author": "Goethe"})).await;
+        // Resume after the first change, so the stream starts with the second.
+        let request = axum::http::Request::get("/books/events")
+            .header("last-event-id", "1")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app_with_state(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+        let mut body = response.into_body().into_data_stream();
+        let mut next = async || {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(1), body.next()).await;
+            String::from_utf8(chunk.unwrap().unwrap().unwrap().to_vec()).unwrap()
+        };
+        assert!(next().await.starts_with("id: 2\nevent: created\ndata: {\"id\":2,\"type\":\"created\""));
+        server.delete("/books/2").await.assert_status_ok();
+        assert!(next().await.starts_with("id: 3\nevent: deleted\n"));
+        // A server shutdown ends the stream, so a graceful shutdown can finish.
+        state.shutdown.send_replace(true);
+        let end = tokio::time::timeout(std::time::Duration::from_secs(1), body.next()).await;
+        assert!(end.unwrap().is_none());
+        state.shutdown.send_replace(false);
+        let request = axum::http::Request::get("/books/events")
+            .header("last-event-id", "soon")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app_with_state(state).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn get_ws() {
+        let state = AppState::new(Arc::new(InMemoryBookStore::new(demo_books()))).await.unwrap();
+        let server = TestServer::builder().http_transport().build(app_with_state(state.clone())).unwrap();
+        let from = header::FROM;
+        let mut alice =
+            server.get_websocket("/ws").add_header(from.clone(), "alice@example.com").await.into_websocket().await;
+        alice.send_json(&json!({"type": "subscribe", "ids": [1, 9]})).await;
+        let antigone = json!({"id": 1, "title": "Antigone", "author": "Sophocles"});
+        alice.assert_receive_json(&json!({"type": "book", "book": antigone, "version": 1})).await;
+        alice.assert_receive_json(&json!({"type": "error", "status": 404, "message": "Book id 9 not found"})).await;
+        alice.assert_receive_json(&json!({"type": "presence", "id": 1, "viewers": ["alice@example.com"]})).await;
+        let mut bob = server.get_websocket("/ws").add_header(from, "bob@example.com").await.into_websocket().await;
+        bob.send_json(&json!({"type": "subscribe", "ids": [1]})).await;
+        bob.assert_receive_json(&json!({"type": "book", "book": antigone, "version": 1})).await;
+        let viewers = json!({"type": "presence", "id": 1, "viewers": ["alice@example.com", "bob@example.com"]});
+        bob.assert_receive_json(&viewers).await;
+        alice.assert_receive_json(&viewers).await;
+        // An edit saves like the form, then each subscriber gets the change.
+        let elektra = json!({"id": 1, "title": "Elektra", "author": "Sophocles"});
+        bob.send_json(&json!({"type": "edit", "id": 1, "version": 1, "title": "Elektra", "author": "Sophocles"})).await;
+        bob.assert_receive_json(&json!({"type": "saved", "book": elektra, "version": 2})).await;
+        let change = json!({"id": 1, "type": "updated", "book": elektra, "version": 2});
+        bob.assert_receive_json(&change).await;
+        alice.assert_receive_json(&change).await;
+        alice.send_json(&json!({"type": "edit", "id": 1, "version": 1, "title": "Medea", "author": "Euripides"})).await;
+        let error = alice.receive_json::<Value>().await;
+        assert_eq!(error["status"], 412);
+        assert_eq!(page(&server, "/books/1").await, "<p>Elektra by Sophocles</p>\n");
+        // A server shutdown closes each WebSocket with "going away". Bob may
+        // close first, so Alice may first get a presence update without him.
+        state.shutdown.send_replace(true);
+        loop {
+            match alice.receive_message().await {
+                axum_test::WsMessage::Text(_) => continue,
+                axum_test::WsMessage::Close(Some(frame)) => break assert_eq!(u16::from(frame.code), 1001),
+                message => panic!("expected a close frame, got {:?}", message),
+            }
+        }
+    }
 
+    #[tokio::test]
+    async fn post_admin_webhooks() {
+        // A stand-in receiver, which records each delivery, and fails the first.
+        type Received = Arc<std::sync::Mutex<Vec<(axum::http::HeaderMap, String)>>>;
+        let received = Received::default();
+        let receiver = axum::Router::new()
+            .route(
+                "/hooks",
+                post(
+                    |axum::extract::State(received): axum::extract::State<Received>,
+                     headers: axum::http::HeaderMap,
+                     body: String| async move {
+                        let mut received = received.lock().unwrap();
+                        received.push((headers, body));
+                        match received.len() {
+                            1 => StatusCode::INTERNAL_SERVER_ERROR,
+                            _ => StatusCode::NO_CONTENT,
+                        }
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hooks", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        let mut state = AppState::new(Arc::new(InMemoryBookStore::new(demo_books()))).await.unwrap();
+        // The receiver is on loopback, so the webhooks allow a private address.
+        state.webhooks = Arc::new(Webhooks::new(std::time::Duration::from_millis(10), true));
+        let server = TestServer::new(app_with_state(state)).unwrap();
+        let secret = "0123456789abcdef";
+        let response =
+            server.post("/admin/webhooks").json(&json!({"url": url, "events": ["deleted"], "secret": secret})).await;
+        response.assert_status(StatusCode::CREATED);
+        response.assert_header(header::LOCATION, "/admin/webhooks/1");
+        response.assert_json(&json!({"id": 1, "url": url, "events": ["deleted"]}));
+        let invalid = json!({"url": "nope", "events": ["deleted"], "secret": secret});
+        server.post("/admin/webhooks").json(&invalid).await.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+        // The webhook wants deletes only, so it gets one delivery, on its second attempt.
+        server.put("/books").json(&json!({"title": "Faust", "author": "Goethe"})).await;
+        server.delete("/books/1").await.assert_status_ok();
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(5);
+        let deliveries = loop {
+            let deliveries = server.get("/admin/webhooks/1/deliveries").await.json::<Value>();
+            if deliveries[0]["state"] != "pending" || tokio::time::Instant::now() > deadline {
+                break deliveries;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        };
+        assert_eq!(deliveries.as_array().unwrap().len(), 1);
+        assert_eq!(deliveries[0]["state"], "delivered");
+        assert_eq!(deliveries[0]["attempts"], 2);
+        assert_eq!(deliveries[0]["response_status"], 204);
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2);
+        let (headers, body) = &received[1];
+        let change = serde_json::from_str::<Value>(body).unwrap();
+        assert_eq!(change["type"], "deleted");
+        assert_eq!(change["book"]["id"], 1);
+        assert_eq!(headers[crate::webhook::SIGNATURE_HEADER], crate::webhook::sign(secret, body.as_bytes()));
+        // Each retry is the same delivery, so a receiver can skip one it has.
+        assert_eq!(headers[crate::webhook::DELIVERY_HEADER], received[0].0[crate::webhook::DELIVERY_HEADER]);
+
+        assert_eq!(main_of(&server.delete("/admin/webhooks/1").await.text()), "<p>Delete webhook id: 1</p>\n");
+        server.get("/admin/webhooks/1").await.assert_status_not_found();
+        server.get("/admin/webhooks").await.assert_json(&json!([]));
+    }
+
+    #[tokio::test]
+    async fn openapi_paths_are_routes() {
+        let server = server();
+        let openapi = server.get("/openapi.json").await.json::<Value>();
+        assert_eq!(openapi["openapi"], "3.1.0");
+        assert_eq!(openapi["components"]["schemas"]["Book"]["required"], json!(["id", "title", "author"]));
+        for (path, item) in openapi["paths"].as_object().unwrap() {
+            // The event stream never ends, so we can't wait for its response.
+            if path == "/books/events" {
+                continue;
+            }
+            let uri = path.replace("{id}", "1").replace("{n}", "1");
+            for method in item.as_object().unwrap().keys().filter(|key| *key != "parameters") {
+                let method = axum::http::Method::from_bytes(method.to_uppercase().as_bytes()).unwrap();
+                let response = server.method(method.clone(), &uri).await;
+                assert_ne!(response.status_code(), StatusCode::METHOD_NOT_ALLOWED, "{} {}", method, uri);
+                assert!(!response.as_bytes().starts_with(b"<p>No route"), "{} {}", method, uri);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn get_docs_api() {
+        let server = server();
+        let response = server.get("/docs/api/").await;
+        response.assert_status_ok();
+        response.assert_header(header::CONTENT_TYPE, "text/html");
+        assert!(response.text().contains("swagger-ui"));
+    }
+
+    #[tokio::test]
+    async fn post_admin_rebuild() {
+        let server = server();
+        // An in-memory store has no events, so we rebuild only the search index.
+        assert_eq!(main_of(&server.post("/admin/rebuild").await.text()), "<p>Rebuild: 0 events, 3 books</p>\n");
+        assert_eq!(page(&server, "/books/search?q=candide").await, "<p>Candide by Voltaire</p>\n");
+    }
+
+    #[tokio::test]
+    async fn get_books_id_revisions() {
+        let server = server();
+        let from = (header::FROM, "librarian@example.com");
+        let data = [["id", "1"], ["version", "1"], ["title", "Elektra"], ["author", "Sophocles"]];
+        let response = server.post("/books/1/form").add_header(from.0.clone(), from.1).form(&data).await;
+        response.assert_status(StatusCode::SEE_OTHER);
+        server.delete("/books/1").add_header(from.0.clone(), from.1).await.assert_status_ok();
+        server.post("/books/1/restore").await.assert_status_ok();
+        let revisions = json(server.get("/books/1/revisions")).await.json::<Value>();
+        let actions: Vec<&Value> = revisions.as_array().unwrap().iter().map(|r| &r["action"]).collect();
+        assert_eq!(actions, ["create", "update", "delete", "restore"]);
+        assert_eq!(revisions[1]["who"], "librarian@example.com");
+        assert_eq!(revisions[1]["old"], json!({"id": 1, "title": "Antigone", "author": "Sophocles"}));
+        assert_eq!(revisions[2]["new"], Value::Null);
+        assert_eq!(revisions[3]["who"], "anonymous");
+        let revision = json(server.get("/books/1/revisions/2")).await.json::<Value>();
+        assert_eq!(revision["new"]["title"], "Elektra");
+        assert!(server.get("/books/1/revisions").await.text().contains("<td>librarian@example.com</td>"));
+        server.get("/books/1/revisions/9").await.assert_status_not_found();
+        server.get("/books/9/revisions").await.assert_status_not_found();
+        // A diff shows each field that differs between two revisions.
+        let response = json(server.get("/books/1/revisions/diff?from=1")).await;
+        response.assert_json(&json!({
+            "from": 1,
+            "to": 4,
+            "changes": [{"field": "title", "old": "Antigone", "new": "Elektra"}]
+        }));
+        // A revert creates a new revision, with the old values.
+        let response = server.post("/books/1/revisions/1/revert").add_header(header::IF_MATCH, "\"4\"").await;
+        assert_eq!(main_of(&response.text()), "<p>Antigone by Sophocles</p>\n");
+        response.assert_header(header::ETAG, "\"5-html\"");
+        assert_eq!(page(&server, "/books/search?q=antigone").await, "<p>Antigone by Sophocles</p>\n");
+        server.post("/books/1/revisions/3/revert").await.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        let response = json(server.get("/books/1/revisions/diff?from=1&to=5")).await;
+        response.assert_json(&json!({"from": 1, "to": 5, "changes": []}));
+    }
+
+    #[tokio::test]
+    async fn html_escapes_hostile_payloads() {
+        let server = server();
+        let j = json!({"title": "<script>alert(1)</script>", "author": "\"><img src=x onerror=alert(1)>"});
+        let response = server.put("/books").add_header(header::FROM, "<b>x</b>").json(&j).await;
+        response.assert_status(StatusCode::CREATED);
+        let title = "&#60;script&#62;alert(1)&#60;/script&#62;";
+        let author = "&#34;&#62;&#60;img src=x onerror=alert(1)&#62;";
+        let book = format!("<p>{} by {}</p>\n", title, author);
+        for uri in ["/books", "/books/4", "/books/search?q=script", "/books/4/form", "/books/4/revisions"] {
+            let text = server.get(uri).await.text();
+            assert!(!text.contains("<script>") && !text.contains("<img") && !text.contains("<b>"), "{}: {}", uri, text);
+        }
+        assert!(server.get("/books").await.text().contains(&book));
+        assert_eq!(page(&server, "/books/4").await, book);
+        assert_eq!(page(&server, "/books/search?q=script").await, book);
+        let form = server.get("/books/4/form").await.text();
+        assert!(form.contains(&format!("<input name=\"title\" value=\"{}\">", title)));
+        assert!(form.contains(&format!("<input name=\"author\" value=\"{}\">", author)));
+        assert!(server.get("/books/4/revisions").await.text().contains("<td>&#60;b&#62;x&#60;/b&#62;</td>"));
+        // A form that a user resubmits shows their text, escaped.
+        let data = [["id", "4"], ["version", "<i>"], ["title", "</p><script>"], ["author", ""]];
+        let response = server.post("/books/4/form").form(&data).await;
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        let text = response.text();
+        assert!(text.contains("value=\"&#60;/p&#62;&#60;script&#62;\"") && !text.contains("<script>") && !text.contains("<i>"));
+        // An error message that quotes a request value escapes it too.
+        let response = server.get("/books?order=%3Cscript%3E").await;
+        response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(main_of(&response.text()), "<p>order must be asc or desc, not &#60;script&#62;</p>\n");
+    }
 }
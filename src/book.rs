@@ -1,14 +1,62 @@
 // Use Deserialize to convert e.g. from request JSON into Book struct.
 use serde::{Serialize, Deserialize};
 
+// Use ToSchema to describe the struct in our OpenAPI document.
+use utoipa::ToSchema;
+
+// Use the OpenAPI schema builder, for a field's constraints; see text_schema.
+use utoipa::openapi::{ObjectBuilder, schema::Type};
+
+// The pattern of a book's text, such as a title: at least one character
+// that is not a space, and no control characters, such as a newline.
+pub const TEXT_PATTERN: &str = r"^[^\x00-\x1F\x7F]*[^\s\x00-\x1F\x7F][^\x00-\x1F\x7F]*$";
+
+// The schema of a book's text, such as a title: from 1 to 255 characters
+// that match TEXT_PATTERN. Each text field uses this schema, by using the
+// attribute `#[schema(schema_with = text_schema)]`.
+pub fn text_schema() -> utoipa::openapi::Object {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .min_length(Some(1))
+        .max_length(Some(255))
+        .pattern(Some(TEXT_PATTERN))
+        .build()
+}
+
 // Demo book structure with some example fields for title and author.
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, Hash, PartialEq)]
+//
+// The title and author each have from 1 to 255 characters, with at least
+// one that is not a space, and none that is a control character, such as
+// a newline. Handlers check these constraints by using the schema; see
+// file schema.rs.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Eq, Hash, PartialEq)]
 pub struct Book {
     pub id: u32,
+    #[schema(schema_with = text_schema)]
+    pub title: String,
+    #[schema(schema_with = text_schema)]
+    pub author: String,
+}
+
+// Demo new book structure, for a request that creates a book.
+// The data store assigns the id, so a client can't choose one, and
+// any id in the request is ignored. The title and author have the same
+// constraints as for a book.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Eq, Hash, PartialEq)]
+pub struct NewBook {
+    #[schema(schema_with = text_schema)]
     pub title: String,
+    #[schema(schema_with = text_schema)]
     pub author: String,
 }
 
+impl NewBook {
+    // Create a book from this new book, with an id from the data store.
+    pub fn with_id(self, id: u32) -> Book {
+        Book { id, title: self.title, author: self.author }
+    }
+}
+
 // Display the book using the format "{title} by {author}".
 // This is a typical Rust trait and is not axum-specific.
 impl std::fmt::Display for Book {
@@ -0,0 +1,234 @@
+// Use Serialize and Deserialize to convert query parameters and cursors.
+use serde::{Deserialize, Serialize};
+
+// Use IntoParams to describe the query parameters in our OpenAPI document.
+use utoipa::IntoParams;
+
+// Use base64 to encode a cursor as an opaque URL-safe string.
+use base64::Engine;
+
+// Use the Book struct and the AppError type.
+use crate::book::Book;
+use crate::error::AppError;
+
+// The default number of books per page.
+pub const DEFAULT_LIMIT: usize = 100;
+
+// The maximum number of books per page.
+pub const MAX_LIMIT: usize = 1000;
+
+// Query parameters for listing books, such as for "GET /books".
+//
+// Example: "/books?author=mor&sort=title&order=desc&limit=10"
+//
+// - `limit`: how many books per page, from 1 to `MAX_LIMIT`.
+// - `offset`: how many books to skip, for offset pagination.
+// - `cursor`: an opaque position, for cursor pagination; a response
+//   provides cursors in its next and prev links.
+// - `author`: only books whose author contains this text, ignoring case.
+// - `title`: only books whose title contains this text, ignoring case.
+// - `sort`: the book field to sort by: "id", "title" (default), "author".
+// - `order`: "asc" (default) or "desc".
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams, Clone, Eq, PartialEq)]
+#[into_params(parameter_in = Query)]
+pub struct BookQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<String>,
+}
+
+// A book field that we can sort by.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    Id,
+    Title,
+    Author,
+}
+
+// A position in a sorted list of books, which is either just after,
+// or just before, a boundary book. We encode a cursor as base64 JSON,
+// so a client treats it as opaque. The cursor keeps its sort field and
+// order, so a client can't apply a cursor to a differently sorted list.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+struct Cursor {
+    before: bool,
+    sort: SortField,
+    desc: bool,
+    book: Book,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    fn decode(s: &str) -> Option<Self> {
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(s).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+// One page of books, with query parameters for the next and prev pages.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Page {
+    pub books: Vec<Book>,
+    pub next: Option<BookQuery>,
+    pub prev: Option<BookQuery>,
+}
+
+impl BookQuery {
+    // Filter, sort, and paginate books, or return a validation error.
+    pub fn apply(&self, books: Vec<Book>) -> Result<Page, AppError> {
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        if limit == 0 || limit > MAX_LIMIT {
+            return Err(AppError::Validation(format!("limit must be from 1 to {}", MAX_LIMIT)));
+        }
+        let sort = match self.sort.as_deref() {
+            None | Some("title") => SortField::Title,
+            Some("id") => SortField::Id,
+            Some("author") => SortField::Author,
+            Some(other) => {
+                return Err(AppError::Validation(format!(
+                    "sort must be id, title, or author, not {}",
+                    other
+                )))
+            }
+        };
+        let desc = match self.order.as_deref() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => {
+                return Err(AppError::Validation(format!("order must be asc or desc, not {}", other)))
+            }
+        };
+
+        // Filter, then sort, with the id as a tie-breaker for a stable order.
+        let author = self.author.as_deref().map(str::to_lowercase);
+        let title = self.title.as_deref().map(str::to_lowercase);
+        let mut books: Vec<Book> = books
+            .into_iter()
+            .filter(|book| contains(&book.author, author.as_deref()))
+            .filter(|book| contains(&book.title, title.as_deref()))
+            .collect();
+        books.sort_by(|a, b| compare(a, b, sort, desc));
+
+        // Find the range of this page, by cursor or by offset.
+        let (start, end) = match &self.cursor {
+            Some(cursor) => {
+                if self.offset.is_some() {
+                    return Err(AppError::Validation("use either cursor or offset, not both".into()));
+                }
+                let cursor = Cursor::decode(cursor)
+                    .filter(|cursor| cursor.sort == sort && cursor.desc == desc)
+                    .ok_or_else(|| AppError::Validation("cursor is invalid for this sort".into()))?;
+                if cursor.before {
+                    // The page ends just before the boundary book.
+                    let end = books.partition_point(|book| {
+                        compare(book, &cursor.book, sort, desc) == std::cmp::Ordering::Less
+                    });
+                    (end.saturating_sub(limit), end)
+                } else {
+                    // The page starts just after the boundary book.
+                    let start = books.partition_point(|book| {
+                        compare(book, &cursor.book, sort, desc) != std::cmp::Ordering::Greater
+                    });
+                    (start, (start + limit).min(books.len()))
+                }
+            }
+            None => {
+                let start = self.offset.unwrap_or(0).min(books.len());
+                (start, (start + limit).min(books.len()))
+            }
+        };
+
+        // Link to the pages before and after, using cursors.
+        let link = |before: bool, book: &Book| BookQuery {
+            cursor: Some(Cursor { before, sort, desc, book: book.clone() }.encode()),
+            offset: None,
+            ..self.clone()
+        };
+        let next = (end < books.len() && end > start).then(|| link(false, &books[end - 1]));
+        let prev = (start > 0 && start < books.len()).then(|| link(true, &books[start]));
+        books.truncate(end);
+        books.drain(..start);
+        Ok(Page { books, next, prev })
+    }
+
+    // Format these query parameters as a URL query string, such as "limit=10".
+    pub fn to_query_string(&self) -> String {
+        serde_urlencoded::to_string(self).unwrap_or_default()
+    }
+}
+
+// Does the text contain the lowercase pattern, ignoring case?
+fn contains(text: &str, pattern: Option<&str>) -> bool {
+    match pattern {
+        Some(pattern) => text.to_lowercase().contains(pattern),
+        None => true,
+    }
+}
+
+// Compare two books by a sort field, then by id, in either order.
+fn compare(a: &Book, b: &Book, sort: SortField, desc: bool) -> std::cmp::Ordering {
+    let ordering = match sort {
+        SortField::Id => a.id.cmp(&b.id),
+        SortField::Title => a.title.cmp(&b.title).then(a.id.cmp(&b.id)),
+        SortField::Author => a.author.cmp(&b.author).then(a.id.cmp(&b.id)),
+    };
+    if desc { ordering.reverse() } else { ordering }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::demo_books;
+
+    fn query(s: &str) -> BookQuery {
+        serde_urlencoded::from_str(s).unwrap()
+    }
+
+    fn titles(page: &Page) -> Vec<&str> {
+        page.books.iter().map(|book| book.title.as_str()).collect()
+    }
+
+    #[test]
+    fn filter_and_sort() {
+        let page = query("author=O&sort=author&order=desc").apply(demo_books()).unwrap();
+        assert_eq!(titles(&page), ["Candide", "Beloved", "Antigone"]);
+        let page = query("title=ANTI").apply(demo_books()).unwrap();
+        assert_eq!(titles(&page), ["Antigone"]);
+        assert!(query("sort=isbn").apply(demo_books()).is_err());
+    }
+
+    #[test]
+    fn cursor_pagination_round_trip() {
+        let first = query("limit=2").apply(demo_books()).unwrap();
+        assert_eq!(titles(&first), ["Antigone", "Beloved"]);
+        assert!(first.prev.is_none());
+        let second = first.next.unwrap().apply(demo_books()).unwrap();
+        assert_eq!(titles(&second), ["Candide"]);
+        assert!(second.next.is_none());
+        let back = second.prev.unwrap().apply(demo_books()).unwrap();
+        assert_eq!(titles(&back), ["Antigone", "Beloved"]);
+    }
+
+    #[test]
+    fn offset_pagination() {
+        let page = query("limit=1&offset=1").apply(demo_books()).unwrap();
+        assert_eq!(titles(&page), ["Beloved"]);
+        assert!(page.prev.is_some() && page.next.is_some());
+    }
+}
@@ -0,0 +1,238 @@
+// Use VecDeque for the replay buffer, which drops its oldest change first.
+use std::collections::VecDeque;
+
+// Use Mutex for thread-safe access to the replay buffer. We never hold the
+// lock across an await, so a std lock is fine.
+use std::sync::{Mutex, PoisonError};
+
+// Use Duration for the heartbeat interval.
+use std::time::Duration;
+
+// Use Serialize to convert a change into event JSON, and Deserialize to
+// read a change kind, such as in a webhook subscription.
+use serde::{Deserialize, Serialize};
+
+// Use ToSchema to describe changes in our OpenAPI document.
+use utoipa::ToSchema;
+
+// Use a tokio broadcast channel to send each change to every subscriber,
+// and a tokio watch channel for the app's shutdown flag.
+use tokio::sync::{broadcast, watch};
+
+// Use tokio streams to turn a subscription into server-sent events, and
+// to watch for a server shutdown, which ends the events.
+use tokio_stream::wrappers::{BroadcastStream, WatchStream};
+use tokio_stream::{Stream, StreamExt};
+
+// Use the Book struct.
+use crate::book::Book;
+
+// How many recent changes we keep, by default, so a client that reconnects
+// can resume from its `Last-Event-ID`.
+pub const REPLAY_BUFFER: usize = 1024;
+
+// How often we send a comment to an idle event stream, so proxies and
+// browsers keep the connection open.
+pub const HEARTBEAT: Duration = Duration::from_secs(15);
+
+// The kind of a change, which is also its server-sent event name.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl ChangeKind {
+    // The name of this kind, such as "created".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+// One change to a book, as subscribers see it, such as:
+// `{"id": 7, "type": "updated", "book": {...}, "version": 2}`
+//
+// The id counts changes since the server started, starting at 1. A deleted
+// book has no version, because it has left the listings.
+#[derive(Debug, Serialize, ToSchema, Clone, Eq, PartialEq)]
+pub struct Change {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub kind: ChangeKind,
+    pub book: Book,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+}
+
+// A feed of changes to books, which handlers publish to whenever they
+// change the data store, and which clients subscribe to, such as by using
+// "GET /books/events" rather than polling "GET /books".
+//
+// The feed keeps a bounded buffer of recent changes, so a client that
+// reconnects can get the changes that it missed.
+#[derive(Debug)]
+pub struct ChangeFeed {
+    capacity: usize,
+    inner: Mutex<Inner>,
+    sender: broadcast::Sender<Change>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    next_id: u64,
+    buffer: VecDeque<Change>,
+}
+
+// A new subscription: the changes that a client missed, and a receiver
+// of changes from now on. If the client missed changes that the buffer
+// no longer has, then there's a gap, and the client must start over.
+#[derive(Debug)]
+pub struct Subscription {
+    pub missed: Vec<Change>,
+    pub gap: bool,
+    pub receiver: broadcast::Receiver<Change>,
+}
+
+impl ChangeFeed {
+    // Create a feed that keeps up to `capacity` recent changes.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        ChangeFeed {
+            capacity,
+            inner: Mutex::new(Inner { next_id: 1, buffer: VecDeque::with_capacity(capacity) }),
+            sender: broadcast::channel(capacity).0,
+        }
+    }
+
+    // Publish a change, and return it with its id. We send it while we
+    // hold the lock, so a new subscriber gets each change exactly once:
+    // either in its missed changes, or from its receiver.
+    pub fn publish(&self, kind: ChangeKind, book: Book, version: Option<u64>) -> Change {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        let change = Change { id: inner.next_id, kind, book, version };
+        inner.next_id += 1;
+        if inner.buffer.len() == self.capacity {
+            inner.buffer.pop_front();
+        }
+        inner.buffer.push_back(change.clone());
+        // A send fails only if there are no subscribers, which is fine.
+        let _ = self.sender.send(change.clone());
+        change
+    }
+
+    // Subscribe to changes after the last change that a client has seen,
+    // if any. A last id that we never sent, such as from before a restart,
+    // is a gap, because we can't know what the client missed.
+    pub fn subscribe(&self, last_id: Option<u64>) -> Subscription {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        let receiver = self.sender.subscribe();
+        let Some(last_id) = last_id else {
+            return Subscription { missed: vec![], gap: false, receiver };
+        };
+        let oldest = inner.buffer.front().map_or(inner.next_id, |change| change.id);
+        let gap = last_id + 1 < oldest || last_id >= inner.next_id;
+        let missed = inner.buffer.iter().filter(|change| change.id > last_id).cloned().collect();
+        Subscription { missed, gap, receiver }
+    }
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        ChangeFeed::new(REPLAY_BUFFER)
+    }
+}
+
+// Convert a change into a server-sent event, with its id, so a browser
+// sends it back as `Last-Event-ID` when it reconnects.
+fn change_event(change: &Change) -> axum::response::sse::Event {
+    axum::response::sse::Event::default()
+        .id(change.id.to_string())
+        .event(change.kind.as_str())
+        .data(serde_json::to_string(change).unwrap_or_default())
+}
+
+// The server-sent event for a gap, which tells a client to get all books
+// again, such as by using "GET /books", because it missed some changes.
+fn reset_event() -> axum::response::sse::Event {
+    axum::response::sse::Event::default()
+        .event("reset")
+        .data("Some changes are no longer available; get all books again")
+}
+
+// Convert a subscription into a stream of server-sent events: a reset if
+// there's a gap, then the missed changes, then each change from now on.
+// A client that falls too far behind the feed gets a reset too. The stream
+// never fails, so it suits `axum::response::sse::Sse` as is.
+//
+// The stream ends when the shutdown flag becomes true, because a graceful
+// shutdown waits for each open connection, and an event stream otherwise
+// stays open for good. A browser reconnects later, with `Last-Event-ID`.
+pub fn event_stream(
+    subscription: Subscription,
+    shutdown: watch::Receiver<bool>,
+) -> impl Stream<Item = Result<axum::response::sse::Event, std::convert::Infallible>> {
+    let Subscription { missed, gap, receiver } = subscription;
+    let missed = gap.then(reset_event).into_iter().chain(missed.iter().map(change_event)).collect::<Vec<_>>();
+    let live = BroadcastStream::new(receiver).map(|result| match result {
+        Ok(change) => change_event(&change),
+        Err(_lagged) => reset_event(),
+    });
+    let shutdown = WatchStream::new(shutdown).filter(|going_away| *going_away).map(|_| None);
+    tokio_stream::iter(missed).chain(live).map(Some).merge(shutdown).map_while(|event| event.map(Ok))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: u32) -> Book {
+        Book { id, title: "Antigone".into(), author: "Sophocles".into() }
+    }
+
+    #[test]
+    fn subscribe_resumes_from_buffer() {
+        let feed = ChangeFeed::new(2);
+        assert!(!feed.subscribe(None).gap);
+        for id in 1..=3 {
+            feed.publish(ChangeKind::Updated, book(id), Some(2));
+        }
+        // The buffer has changes 2 and 3.
+        let subscription = feed.subscribe(Some(1));
+        assert!(!subscription.gap);
+        assert_eq!(subscription.missed.iter().map(|change| change.id).collect::<Vec<_>>(), [2, 3]);
+        assert!(feed.subscribe(Some(3)).missed.is_empty());
+        // Change 1 is gone, and change 9 never happened.
+        assert!(feed.subscribe(Some(0)).gap);
+        assert!(feed.subscribe(Some(9)).gap);
+    }
+
+    #[tokio::test]
+    async fn subscriber_receives_new_changes() {
+        let feed = ChangeFeed::default();
+        let mut subscription = feed.subscribe(None);
+        let change = feed.publish(ChangeKind::Deleted, book(1), None);
+        assert_eq!(subscription.receiver.recv().await.unwrap(), change);
+        assert_eq!(
+            serde_json::to_value(&change).unwrap(),
+            serde_json::json!({"id": 1, "type": "deleted", "book": book(1)})
+        );
+    }
+
+    #[tokio::test]
+    async fn event_stream_ends_on_shutdown() {
+        let feed = ChangeFeed::default();
+        let shutdown = watch::Sender::new(false);
+        let mut events = Box::pin(event_stream(feed.subscribe(None), shutdown.subscribe()));
+        feed.publish(ChangeKind::Created, book(1), Some(1));
+        assert!(events.next().await.is_some());
+        shutdown.send_replace(true);
+        let end = tokio::time::timeout(Duration::from_secs(1), events.next()).await;
+        assert!(end.unwrap().is_none());
+    }
+}
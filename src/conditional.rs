@@ -0,0 +1,223 @@
+// Use axum capabilities for responses.
+use axum::response::{IntoResponse, Response};
+
+// Use Hash to derive an entity tag from the bytes of a response body.
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// Use SystemTime for last-modified times.
+use std::time::SystemTime;
+
+// Use the AppError type.
+use crate::error::AppError;
+
+// Use the Format type, because each format of a resource has its own tag.
+use crate::format::Format;
+
+// A strong entity tag for one representation of a version of a resource,
+// such as `"3-json"`, for the `ETag` header. Per RFC 9110, a strong tag
+// must differ between representations, such as the HTML and the JSON of
+// one book, because their bytes differ.
+pub fn etag(version: u64, format: Format) -> String {
+    format!("\"{}-{}\"", version, format.suffix())
+}
+
+// A strong entity tag for the bytes of a representation, such as a
+// response body, for a resource that has no version.
+pub fn content_etag(bytes: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+// Add an `ETag` header to a response, for a version of a resource,
+// in the format of the response.
+pub fn with_etag(mut response: Response, version: u64, format: Format) -> Response {
+    if let Ok(value) = axum::http::HeaderValue::from_str(&etag(version, format)) {
+        response.headers_mut().insert(axum::http::header::ETAG, value);
+    }
+    response
+}
+
+// The `If-Match` header of a request, per RFC 9110.
+//
+// A client sends `If-Match` with the `ETag` that it read, so a write
+// succeeds only if nobody else has changed the resource since then.
+//
+// - `Absent`: the request has no `If-Match` header.
+// - `Any`: the header is "*", which matches any current version.
+// - `Versions`: the header is a list of entity tags. A tag of any format
+//   of a version, such as `"3-json"` or `"3-html"`, or a bare version,
+//   such as `"3"`, expects that version. A weak tag such as `W/"3-json"`
+//   never matches, because `If-Match` uses strong comparison.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum IfMatch {
+    Absent,
+    Any,
+    Versions(Vec<u64>),
+}
+
+impl IfMatch {
+    // Parse an `If-Match` header value.
+    pub fn parse(value: &str) -> IfMatch {
+        if value.trim() == "*" {
+            return IfMatch::Any;
+        }
+        IfMatch::Versions(
+            value
+                .split(',')
+                .filter_map(|tag| version_of(tag.trim().strip_prefix('"')?.strip_suffix('"')?))
+                .collect(),
+        )
+    }
+
+    // The versions that a conditional write expects, or `None` for any version.
+    pub fn expected(&self) -> Option<&[u64]> {
+        match self {
+            IfMatch::Versions(versions) => Some(versions),
+            IfMatch::Absent | IfMatch::Any => None,
+        }
+    }
+}
+
+// Get the version of an entity tag's text, such as "3-json" or "3".
+fn version_of(tag: &str) -> Option<u64> {
+    let version = match tag.split_once('-') {
+        Some((version, suffix)) => Format::from_suffix(suffix).map(|_| version)?,
+        None => tag,
+    };
+    version.parse().ok()
+}
+
+// axum extractor for the `If-Match` header.
+impl<S: Send + Sync> axum::extract::FromRequestParts<S> for IfMatch {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        match parts.headers.get(axum::http::header::IF_MATCH) {
+            None => Ok(IfMatch::Absent),
+            Some(value) => value
+                .to_str()
+                .map(IfMatch::parse)
+                .map_err(|_| AppError::Validation("If-Match header is not valid text".into())),
+        }
+    }
+}
+
+// `Cache-Control` for a resource that can change at any time, such as
+// a book: a cache may store it, yet must revalidate it before each use,
+// which costs only a 304 Not Modified response if nothing changed.
+pub const CACHE_REVALIDATE: &str = "no-cache";
+
+// `Cache-Control` for a resource that changes only when the program
+// changes, such as a file that we include at compile time.
+pub const CACHE_STATIC: &str = "public, max-age=3600";
+
+// The time that this program started. A resource that we include at
+// compile time can't change while the program runs, so this is its
+// last-modified time.
+pub static STARTED: std::sync::LazyLock<SystemTime> = std::sync::LazyLock::new(SystemTime::now);
+
+// Respond to a GET request with caching headers, which are `ETag`,
+// `Last-Modified` if known, and `Cache-Control`.
+//
+// If the request shows that the client already has this representation,
+// then respond with 304 Not Modified, which has the same headers and no
+// body. Per RFC 9110, `If-None-Match` wins over `If-Modified-Since`:
+//
+// - `If-None-Match` is a list of entity tags, or "*"; it shows that the
+//   client has this representation if any tag matches, ignoring `W/`.
+// - `If-Modified-Since` is an HTTP date; it shows that the client has
+//   this representation if the resource hasn't changed since then.
+pub fn conditional_get(
+    request_headers: &axum::http::HeaderMap,
+    mut response: Response,
+    etag: &str,
+    last_modified: Option<SystemTime>,
+    cache_control: &'static str,
+) -> Response {
+    let headers = response.headers_mut();
+    if let Ok(value) = axum::http::HeaderValue::from_str(etag) {
+        headers.insert(axum::http::header::ETAG, value);
+    }
+    if let Some(time) = last_modified
+        && let Ok(value) = axum::http::HeaderValue::from_str(&httpdate::fmt_http_date(time))
+    {
+        headers.insert(axum::http::header::LAST_MODIFIED, value);
+    }
+    headers.insert(
+        axum::http::header::CACHE_CONTROL,
+        axum::http::HeaderValue::from_static(cache_control),
+    );
+    if !not_modified(request_headers, etag, last_modified) {
+        return response;
+    }
+    let mut not_modified = axum::http::StatusCode::NOT_MODIFIED.into_response();
+    for name in [
+        axum::http::header::ETAG,
+        axum::http::header::LAST_MODIFIED,
+        axum::http::header::CACHE_CONTROL,
+        axum::http::header::VARY,
+    ] {
+        if let Some(value) = response.headers().get(&name) {
+            not_modified.headers_mut().insert(name, value.clone());
+        }
+    }
+    not_modified
+}
+
+// Does the request show that the client already has this representation?
+fn not_modified(headers: &axum::http::HeaderMap, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    if let Some(value) = headers.get(axum::http::header::IF_NONE_MATCH) {
+        let Ok(value) = value.to_str() else {
+            return false;
+        };
+        let weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+        return value.trim() == "*" || value.split(',').any(|tag| weak(tag) == weak(etag));
+    }
+    let since = headers
+        .get(axum::http::header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok());
+    match (since, last_modified) {
+        // HTTP dates have a precision of one second, so compare seconds.
+        (Some(since), Some(modified)) => unix_secs(modified) <= unix_secs(since),
+        _ => false,
+    }
+}
+
+// Convert a time to Unix seconds.
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_if_match() {
+        assert_eq!(IfMatch::parse("*"), IfMatch::Any);
+        assert_eq!(etag(3, Format::Json), "\"3-json\"");
+        assert_eq!(IfMatch::parse(&etag(3, Format::Json)), IfMatch::Versions(vec![3]));
+        assert_eq!(IfMatch::parse("\"1\", W/\"2\", \"x\", \"4-csv\", \"5-xml\""), IfMatch::Versions(vec![1, 4]));
+        assert_eq!(IfMatch::Any.expected(), None);
+        assert_eq!(IfMatch::parse("W/\"2\"").expected(), Some(&[][..]));
+    }
+
+    #[test]
+    fn not_modified_by_etag_or_date() {
+        let mut headers = axum::http::HeaderMap::new();
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        assert!(!not_modified(&headers, "\"1\"", Some(modified)));
+        headers.insert(axum::http::header::IF_MODIFIED_SINCE, httpdate::fmt_http_date(modified).parse().unwrap());
+        assert!(not_modified(&headers, "\"1\"", Some(modified)));
+        assert!(!not_modified(&headers, "\"1\"", Some(modified + std::time::Duration::from_secs(1))));
+        // If-None-Match wins over If-Modified-Since, and uses weak comparison.
+        headers.insert(axum::http::header::IF_NONE_MATCH, "W/\"1\", \"2\"".parse().unwrap());
+        assert!(not_modified(&headers, "\"1\"", None));
+        assert!(!not_modified(&headers, "\"3\"", Some(modified)));
+    }
+}
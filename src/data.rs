@@ -1,61 +1,699 @@
-// Use LazyLock for creating a thread-safe global variable e.g. our DATA.
-use std::sync::LazyLock;
+// Use Arc for sharing one data store among many axum handlers.
+use std::sync::Arc;
 
-// Use Mutex for thread-safe access to a variable e.g. our DATA.
-use std::sync::Mutex;
+// Use an atomic counter to allocate book ids.
+use std::sync::atomic::{AtomicU32, Ordering};
 
-// Use HashMap for storing data as key-value pairs e.g. our DATA.
+// Use a tokio read-write lock for async access to our books map.
+// Tasks await the lock rather than block a tokio worker thread,
+// and many readers can hold the lock at the same time.
+use tokio::sync::RwLock;
+
+// Use HashMap for storing data as key-value pairs e.g. our books map.
 use std::collections::HashMap;
 
-// Use the Book struct.
-use crate::book::Book;
+// Use SystemTime for the time that a book last changed.
+use std::time::SystemTime;
+
+// Use async_trait so a trait with async functions can be a `dyn` object.
+use async_trait::async_trait;
+
+// Use Serialize and Deserialize to convert batch operations from request JSON.
+use serde::{Deserialize, Serialize};
+
+// Use ToSchema to describe batch operations and revision actions in our OpenAPI document.
+use utoipa::ToSchema;
+
+// Use the Book and NewBook structs.
+use crate::book::{Book, NewBook};
+
+// Demo books that we use to seed a new data store.
+//
+// The in-memory store uses these as its initial contents,
+// so a freshly started app always has something to show.
+pub fn demo_books() -> Vec<Book> {
+    vec![
+        Book {
+            id: 1,
+            title: "Antigone".into(),
+            author: "Sophocles".into(),
+        },
+        Book {
+            id: 2,
+            title: "Beloved".into(),
+            author: "Toni Morrison".into(),
+        },
+        Book {
+            id: 3,
+            title: "Candide".into(),
+            author: "Voltaire".into(),
+        },
+    ]
+}
+
+// A book as stored, with its version and its last-modified time.
+//
+// The store sets a new book's version to 1, then increments the version
+// on each change, so a client can tell whether a book has changed since
+// the client read it. Handlers send the version as an `ETag` header,
+// and the modified time as a `Last-Modified` header.
+//
+// A delete moves a book to the trash, by setting its deleted time,
+// so a librarian can restore it until the trash is purged.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct StoredBook {
+    pub book: Book,
+    pub version: u64,
+    pub modified: SystemTime,
+    pub deleted_at: Option<SystemTime>,
+}
+
+// What a change did to a book, for its revision.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+}
+
+impl RevisionAction {
+    // The name of this action, such as "update".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RevisionAction::Create => "create",
+            RevisionAction::Update => "update",
+            RevisionAction::Delete => "delete",
+            RevisionAction::Restore => "restore",
+        }
+    }
+}
+
+// An immutable record of one change to a book: who made the change,
+// when, and the book's old and new values. A book in the trash has no
+// value, so a delete has no new value, and a restore has no old value.
+// Who is what the client says, with its address, and is not verified;
+// see the `Who` extractor in file revision.rs.
+//
+// A revision's number is the book's version after the change, so a
+// client can use the number with `If-Match`, as with an `ETag` header.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Revision {
+    pub book_id: u32,
+    pub number: u64,
+    pub action: RevisionAction,
+    pub who: String,
+    pub when: SystemTime,
+    pub old: Option<Book>,
+    pub new: Option<Book>,
+}
+
+impl Revision {
+    // Create the revision of a change, given the stored book after the
+    // change, and the book's old value.
+    pub fn of(action: RevisionAction, who: &str, stored: &StoredBook, old: Option<Book>) -> Self {
+        Revision {
+            book_id: stored.book.id,
+            number: stored.version,
+            action,
+            who: who.to_string(),
+            when: stored.modified,
+            old,
+            new: stored.deleted_at.is_none().then(|| stored.book.clone()),
+        }
+    }
+}
+
+// Error for any data store operation that fails to complete.
+//
+// A version mismatch means a conditional write found that another
+// client changed the book first; handlers map it to an HTTP status
+// code of Precondition Failed (412). Any other failure is meant for
+// logs; handlers map it to Internal Server Error (500).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    VersionMismatch { id: u32, version: u64 },
+    Failed(String),
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StoreError::VersionMismatch { id, version } => {
+                write!(f, "store error: book id {} is at version {}", id, version)
+            }
+            StoreError::Failed(message) => write!(f, "store error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+// One operation in a batch of changes; see `BookStore::batch`.
+//
+// JSON has an "op" field, such as `{"op": "delete", "id": 1}`.
+// An update or delete may have the version that the client expects,
+// which acts like an `If-Match` header for that one operation.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchOp {
+    Create {
+        book: NewBook,
+    },
+    Update {
+        book: Book,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<u64>,
+    },
+    Delete {
+        id: u32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<u64>,
+    },
+}
+
+impl BatchOp {
+    // The versions that this operation expects, if any, as for `update`.
+    pub fn expected(version: &Option<u64>) -> Option<&[u64]> {
+        version.as_ref().map(std::slice::from_ref)
+    }
+}
+
+// The outcome of one operation in a batch of changes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BatchOutcome {
+    Created(StoredBook),
+    Updated(StoredBook),
+    Deleted(Book),
+    NotFound(u32),
+    VersionMismatch { id: u32, version: u64 },
+    // The operation would have succeeded, but another operation in the
+    // same all-or-nothing batch failed, so the store changed nothing.
+    Aborted,
+}
+
+impl BatchOutcome {
+    // Did the operation change the store?
+    pub fn is_done(&self) -> bool {
+        matches!(self, BatchOutcome::Created(_) | BatchOutcome::Updated(_) | BatchOutcome::Deleted(_))
+    }
+
+    // Convert the result of an update or delete into an outcome. A missing
+    // book or a version mismatch fails only this operation; any other store
+    // error fails the whole batch.
+    pub fn of<T>(
+        id: u32,
+        result: Result<Option<T>, StoreError>,
+        done: fn(T) -> BatchOutcome,
+    ) -> Result<BatchOutcome, StoreError> {
+        match result {
+            Ok(Some(value)) => Ok(done(value)),
+            Ok(None) => Ok(BatchOutcome::NotFound(id)),
+            Err(StoreError::VersionMismatch { id, version }) => Ok(BatchOutcome::VersionMismatch { id, version }),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Abort the operations that succeeded, because another one failed.
+    pub fn abort(outcomes: Vec<BatchOutcome>) -> Vec<BatchOutcome> {
+        outcomes
+            .into_iter()
+            .map(|outcome| if outcome.is_done() { BatchOutcome::Aborted } else { outcome })
+            .collect()
+    }
+}
+
+// Data store for books.
+//
+// The axum handlers use this trait via axum `State`, rather than
+// a global variable, so an app can swap storage implementations,
+// and so each test can create its own isolated data store.
+#[async_trait]
+pub trait BookStore: Send + Sync {
+    // Get one book by id, with its version, or `None` if the id is not found
+    // or the book is in the trash.
+    async fn get(&self, id: u32) -> Result<Option<StoredBook>, StoreError>;
+
+    // List all books, except books in the trash, in no particular order.
+    async fn list(&self) -> Result<Vec<Book>, StoreError>;
+
+    // Get the time of the latest change to any book, including a delete,
+    // so a handler can tell whether a list of books has changed.
+    async fn last_modified(&self) -> Result<SystemTime, StoreError>;
+
+    // Create a book with a new id, and return it with its version.
+    //
+    // The store assigns ids in increasing order, and never reuses an id,
+    // even after a delete, so an old link to a deleted book can't lead
+    // to a different book.
+    async fn create(&self, new_book: NewBook, who: &str) -> Result<StoredBook, StoreError>;
+
+    // Update an existing book, and return it with its new version,
+    // or return `None` without any change if the id is not found
+    // or the book is in the trash.
+    //
+    // If `expected` has versions, then the stored version must be one
+    // of them, else the update fails with a version mismatch.
+    async fn update(&self, book: Book, expected: Option<&[u64]>, who: &str) -> Result<Option<StoredBook>, StoreError>;
+
+    // Delete one book by id, by moving it to the trash, which is a change
+    // that gets a new version, and return it, or `None` if not found.
+    // If `expected` has versions, then the stored version must be one of them.
+    async fn delete(&self, id: u32, expected: Option<&[u64]>, who: &str) -> Result<Option<Book>, StoreError>;
+
+    // List the books in the trash, with their deleted times, in no particular order.
+    async fn trash(&self) -> Result<Vec<StoredBook>, StoreError>;
 
-// Create a data store as a global variable with `LazyLock` and `Mutex`.
+    // Restore one book by id from the trash, and return it with its new
+    // version, or `None` if the book is not in the trash.
+    async fn restore(&self, id: u32, who: &str) -> Result<Option<StoredBook>, StoreError>;
+
+    // Purge books from the trash, which deletes them and their revisions for
+    // good, and return them. This purges each book deleted at or before a time,
+    // or only the book with the given id, if any. A purged book's id is still
+    // never reused.
+    async fn purge(&self, id: Option<u32>, deleted_before: SystemTime) -> Result<Vec<Book>, StoreError>;
+
+    // Apply a batch of operations in order, and return each outcome.
+    //
+    // If `atomic`, then the batch is all-or-nothing: if any operation
+    // fails, then the store changes nothing, and each operation that
+    // would have succeeded has the outcome `Aborted`. Otherwise, the
+    // batch is best-effort: each operation succeeds or fails on its own.
+    // Either way, a later operation sees the changes of earlier ones.
+    async fn batch(&self, ops: Vec<BatchOp>, atomic: bool, who: &str) -> Result<Vec<BatchOutcome>, StoreError>;
+
+    // List the revisions of one book by id, oldest first, including a book in
+    // the trash. The list is empty if the id is not found.
+    //
+    // Each change to a book, by `create`, `update`, `delete`, `restore`,
+    // or `batch`, records a revision, with `who` made the change.
+    async fn revisions(&self, id: u32) -> Result<Vec<Revision>, StoreError>;
+
+    // Rebuild the store's view of its books from its source of truth, such
+    // as an event log, and return how many events it replayed. A store that
+    // keeps no events has nothing to rebuild, so by default this does nothing.
+    async fn rebuild(&self) -> Result<u64, StoreError> {
+        Ok(0)
+    }
+}
+
+// Check that a stored version is one of the expected versions, if any.
+pub fn check_version(id: u32, version: u64, expected: Option<&[u64]>) -> Result<(), StoreError> {
+    match expected {
+        Some(versions) if !versions.contains(&version) => Err(StoreError::VersionMismatch { id, version }),
+        _ => Ok(()),
+    }
+}
+
+// Create a data store that keeps books in memory.
 //
 // This demo implementation uses a `HashMap` for ease and speed.
-// The map key is a primary key for lookup; the map value is a Book.
-//
-// To access data, create a thread, spawn it, and acquire the lock:
-//
-// ```
-// async fn example() {
-//     thread::spawn(move || {
-//         match DATA.lock() {
-            Ok(data) => {
-//         …
-// }).join().unwrap()
-// ```
-
-// Note: static items do not call [`Drop`] on program termination, so this won't
-// be deallocated. this is fine, as the OS can deallocate the terminated program
-// faster than we can free memory but tools like valgrind might report "memory
-// leaks" as it isn't obvious this is intentional.
-
-pub static DATA: LazyLock<Mutex<HashMap<u32, Book>>> = LazyLock::new(|| {
-    Mutex::new(HashMap::from([
-        (
-            1,
-            Book {
-                id: 1,
-                title: "Antigone".into(),
-                author: "Sophocles".into(),
-            },
-        ),
-        (
-            2,
-            Book {
-                id: 2,
-                title: "Beloved".into(),
-                author: "Toni Morrison".into(),
-            },
-        ),
-        (
-            3,
-            Book {
-                id: 3,
-                title: "Candide".into(),
-                author: "Voltaire".into(),
-            },
-        ),
-    ]))
-});
+// The map key is a primary key for lookup; the map value is a Book
+// with its version. A writer changes the modified time and records
+// revisions while it holds the books lock, so a reader sees them all
+// change together. The next id only ever increases, so the store
+// never reuses an id.
+#[derive(Debug, Clone)]
+pub struct InMemoryBookStore {
+    books: Arc<RwLock<HashMap<u32, StoredBook>>>,
+    revisions: Arc<RwLock<HashMap<u32, Vec<Revision>>>>,
+    modified: Arc<RwLock<SystemTime>>,
+    next_id: Arc<AtomicU32>,
+}
+
+impl InMemoryBookStore {
+    // Create a new in-memory store that contains the given books,
+    // each with a first revision by "system".
+    pub fn new(books: impl IntoIterator<Item = Book>) -> Self {
+        let modified = SystemTime::now();
+        let books: HashMap<u32, StoredBook> = books
+            .into_iter()
+            .map(|book| (book.id, StoredBook { book, version: 1, modified, deleted_at: None }))
+            .collect();
+        let revisions = books
+            .values()
+            .map(|stored| (stored.book.id, vec![Revision::of(RevisionAction::Create, "system", stored, None)]))
+            .collect();
+        let next_id = books.keys().max().map_or(1, |id| id + 1);
+        Self {
+            books: Arc::new(RwLock::new(books)),
+            revisions: Arc::new(RwLock::new(revisions)),
+            modified: Arc::new(RwLock::new(modified)),
+            next_id: Arc::new(AtomicU32::new(next_id)),
+        }
+    }
+
+    // Keep new revisions, and the modified time, if any, of a change.
+    // The caller must hold the books write lock.
+    async fn commit(&self, new_revisions: Vec<Revision>) {
+        let Some(last) = new_revisions.last() else {
+            return;
+        };
+        *self.modified.write().await = last.when;
+        let mut revisions = self.revisions.write().await;
+        for revision in new_revisions {
+            revisions.entry(revision.book_id).or_default().push(revision);
+        }
+    }
+}
+
+////
+// Helpers that change a map of books, which is the in-memory store's data.
+// Each helper that changes a book adds its revision to a list, so a caller
+// can keep the revisions only if it keeps the change. The event-sourced
+// store uses these helpers too, so both stores have the same rules.
+////
+
+// Get a book that is not in the trash from a map of books, to change it.
+fn live_book(books: &mut HashMap<u32, StoredBook>, id: u32) -> Option<&mut StoredBook> {
+    books.get_mut(&id).filter(|stored| stored.deleted_at.is_none())
+}
+
+// Create a book with an id in a map of books, at a modified time, and add
+// its revision to a list; see `BookStore::create`.
+pub fn create_book(
+    books: &mut HashMap<u32, StoredBook>,
+    log: &mut Vec<Revision>,
+    book: Book,
+    modified: SystemTime,
+    who: &str,
+) -> StoredBook {
+    let stored = StoredBook { book, version: 1, modified, deleted_at: None };
+    log.push(Revision::of(RevisionAction::Create, who, &stored, None));
+    books.insert(stored.book.id, stored.clone());
+    stored
+}
+
+// Update a book in a map of books, at a modified time, and add its revision
+// to a list; see `BookStore::update`.
+pub fn update_book(
+    books: &mut HashMap<u32, StoredBook>,
+    log: &mut Vec<Revision>,
+    book: Book,
+    expected: Option<&[u64]>,
+    modified: SystemTime,
+    who: &str,
+) -> Result<Option<StoredBook>, StoreError> {
+    let Some(stored) = live_book(books, book.id) else {
+        return Ok(None);
+    };
+    check_version(book.id, stored.version, expected)?;
+    let old = std::mem::replace(&mut stored.book, book);
+    stored.version += 1;
+    stored.modified = modified;
+    log.push(Revision::of(RevisionAction::Update, who, stored, Some(old)));
+    Ok(Some(stored.clone()))
+}
+
+// Move a book to the trash in a map of books, at a modified time, and add
+// its revision to a list; see `BookStore::delete`.
+pub fn delete_book(
+    books: &mut HashMap<u32, StoredBook>,
+    log: &mut Vec<Revision>,
+    id: u32,
+    expected: Option<&[u64]>,
+    modified: SystemTime,
+    who: &str,
+) -> Result<Option<Book>, StoreError> {
+    let Some(stored) = live_book(books, id) else {
+        return Ok(None);
+    };
+    check_version(id, stored.version, expected)?;
+    stored.version += 1;
+    stored.modified = modified;
+    stored.deleted_at = Some(modified);
+    log.push(Revision::of(RevisionAction::Delete, who, stored, Some(stored.book.clone())));
+    Ok(Some(stored.book.clone()))
+}
+
+// Restore a book from the trash in a map of books, at a modified time,
+// and add its revision to a list; see `BookStore::restore`.
+pub fn restore_book(
+    books: &mut HashMap<u32, StoredBook>,
+    log: &mut Vec<Revision>,
+    id: u32,
+    modified: SystemTime,
+    who: &str,
+) -> Option<StoredBook> {
+    let stored = books.get_mut(&id).filter(|stored| stored.deleted_at.is_some())?;
+    stored.version += 1;
+    stored.modified = modified;
+    stored.deleted_at = None;
+    log.push(Revision::of(RevisionAction::Restore, who, stored, None));
+    Some(stored.clone())
+}
+
+// Purge books from the trash in a map of books, with their revisions;
+// see `BookStore::purge`.
+pub fn purge_books(
+    books: &mut HashMap<u32, StoredBook>,
+    revisions: &mut HashMap<u32, Vec<Revision>>,
+    id: Option<u32>,
+    deleted_before: SystemTime,
+) -> Vec<Book> {
+    let ids: Vec<u32> = books
+        .values()
+        .filter(|stored| stored.deleted_at.is_some_and(|deleted_at| deleted_at <= deleted_before))
+        .filter(|stored| id.is_none_or(|id| id == stored.book.id))
+        .map(|stored| stored.book.id)
+        .collect();
+    ids.into_iter()
+        .filter_map(|id| {
+            revisions.remove(&id);
+            books.remove(&id)
+        })
+        .map(|stored| stored.book)
+        .collect()
+}
+
+#[async_trait]
+impl BookStore for InMemoryBookStore {
+    async fn get(&self, id: u32) -> Result<Option<StoredBook>, StoreError> {
+        Ok(self.books.read().await.get(&id).filter(|stored| stored.deleted_at.is_none()).cloned())
+    }
+
+    async fn list(&self) -> Result<Vec<Book>, StoreError> {
+        Ok(self
+            .books
+            .read()
+            .await
+            .values()
+            .filter(|stored| stored.deleted_at.is_none())
+            .map(|stored| stored.book.clone())
+            .collect())
+    }
+
+    async fn last_modified(&self) -> Result<SystemTime, StoreError> {
+        Ok(*self.modified.read().await)
+    }
+
+    async fn create(&self, new_book: NewBook, who: &str) -> Result<StoredBook, StoreError> {
+        let mut books = self.books.write().await;
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut log = Vec::new();
+        let stored = create_book(&mut books, &mut log, new_book.with_id(id), SystemTime::now(), who);
+        self.commit(log).await;
+        Ok(stored)
+    }
+
+    async fn update(&self, book: Book, expected: Option<&[u64]>, who: &str) -> Result<Option<StoredBook>, StoreError> {
+        let mut books = self.books.write().await;
+        let mut log = Vec::new();
+        let stored = update_book(&mut books, &mut log, book, expected, SystemTime::now(), who)?;
+        self.commit(log).await;
+        Ok(stored)
+    }
+
+    async fn delete(&self, id: u32, expected: Option<&[u64]>, who: &str) -> Result<Option<Book>, StoreError> {
+        let mut books = self.books.write().await;
+        let mut log = Vec::new();
+        let book = delete_book(&mut books, &mut log, id, expected, SystemTime::now(), who)?;
+        self.commit(log).await;
+        Ok(book)
+    }
+
+    async fn trash(&self) -> Result<Vec<StoredBook>, StoreError> {
+        Ok(self.books.read().await.values().filter(|stored| stored.deleted_at.is_some()).cloned().collect())
+    }
+
+    async fn restore(&self, id: u32, who: &str) -> Result<Option<StoredBook>, StoreError> {
+        let mut books = self.books.write().await;
+        let mut log = Vec::new();
+        let stored = restore_book(&mut books, &mut log, id, SystemTime::now(), who);
+        self.commit(log).await;
+        Ok(stored)
+    }
+
+    async fn purge(&self, id: Option<u32>, deleted_before: SystemTime) -> Result<Vec<Book>, StoreError> {
+        let mut books = self.books.write().await;
+        let mut revisions = self.revisions.write().await;
+        Ok(purge_books(&mut books, &mut revisions, id, deleted_before))
+    }
+
+    // The batch changes a copy of the books, then keeps the copy only if
+    // the batch may change the store, so an all-or-nothing batch that
+    // fails leaves the books and the next id as they were.
+    async fn batch(&self, ops: Vec<BatchOp>, atomic: bool, who: &str) -> Result<Vec<BatchOutcome>, StoreError> {
+        let mut books = self.books.write().await;
+        let mut changed = books.clone();
+        let mut log = Vec::new();
+        let mut next_id = self.next_id.load(Ordering::SeqCst);
+        let modified = SystemTime::now();
+        let mut outcomes = Vec::with_capacity(ops.len());
+        for op in ops {
+            outcomes.push(match op {
+                BatchOp::Create { book } => {
+                    let stored = create_book(&mut changed, &mut log, book.with_id(next_id), modified, who);
+                    next_id += 1;
+                    BatchOutcome::Created(stored)
+                }
+                BatchOp::Update { book, version } => {
+                    let id = book.id;
+                    let result = update_book(&mut changed, &mut log, book, BatchOp::expected(&version), modified, who);
+                    BatchOutcome::of(id, result, BatchOutcome::Updated)?
+                }
+                BatchOp::Delete { id, version } => {
+                    let result = delete_book(&mut changed, &mut log, id, BatchOp::expected(&version), modified, who);
+                    BatchOutcome::of(id, result, BatchOutcome::Deleted)?
+                }
+            });
+        }
+        if atomic && !outcomes.iter().all(BatchOutcome::is_done) {
+            return Ok(BatchOutcome::abort(outcomes));
+        }
+        if outcomes.iter().any(BatchOutcome::is_done) {
+            *books = changed;
+            self.next_id.store(next_id, Ordering::SeqCst);
+            self.commit(log).await;
+        }
+        Ok(outcomes)
+    }
+
+    async fn revisions(&self, id: u32) -> Result<Vec<Revision>, StoreError> {
+        Ok(self.revisions.read().await.get(&id).cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn update_requires_existing_id() {
+        let store = InMemoryBookStore::new(demo_books());
+        let book = Book { id: 9, title: "Decameron".into(), author: "Giovanni Boccaccio".into() };
+        assert_eq!(store.update(book, None, "test").await, Ok(None));
+        assert_eq!(store.get(9).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn stores_are_isolated() {
+        let a = InMemoryBookStore::new(demo_books());
+        let b = InMemoryBookStore::new(demo_books());
+        a.delete(1, None, "test").await.unwrap();
+        assert_eq!(a.list().await.unwrap().len(), 2);
+        assert_eq!(b.list().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn update_checks_version() {
+        let store = InMemoryBookStore::new(demo_books());
+        let book = Book { id: 1, title: "Elektra".into(), author: "Sophocles".into() };
+        let stored = store.update(book.clone(), Some(&[1]), "test").await.unwrap().unwrap();
+        assert_eq!(stored.version, 2);
+        assert_eq!(store.last_modified().await, Ok(stored.modified));
+        // A second writer that read version 1 must not overwrite version 2.
+        assert_eq!(
+            store.update(book, Some(&[1]), "test").await,
+            Err(StoreError::VersionMismatch { id: 1, version: 2 })
+        );
+        assert!(store.delete(1, Some(&[1]), "test").await.is_err());
+        assert!(store.delete(1, Some(&[2]), "test").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn create_never_reuses_ids() {
+        let store = InMemoryBookStore::new(demo_books());
+        let new_book = NewBook { title: "Decameron".into(), author: "Giovanni Boccaccio".into() };
+        assert_eq!(store.create(new_book.clone(), "test").await.unwrap().book.id, 4);
+        store.delete(4, None, "test").await.unwrap();
+        assert_eq!(store.create(new_book.clone(), "test").await.unwrap().book.id, 5);
+        assert_eq!(InMemoryBookStore::new(vec![]).create(new_book, "test").await.unwrap().book.id, 1);
+    }
+
+    #[tokio::test]
+    async fn batch_is_atomic_or_best_effort() {
+        let store = InMemoryBookStore::new(demo_books());
+        let ops = vec![
+            BatchOp::Create { book: NewBook { title: "Decameron".into(), author: "Giovanni Boccaccio".into() } },
+            BatchOp::Delete { id: 1, version: Some(1) },
+            BatchOp::Delete { id: 9, version: None },
+        ];
+        // All-or-nothing: the missing book aborts the batch.
+        let outcomes = store.batch(ops.clone(), true, "test").await.unwrap();
+        assert_eq!(outcomes, [BatchOutcome::Aborted, BatchOutcome::Aborted, BatchOutcome::NotFound(9)]);
+        assert_eq!(store.list().await.unwrap().len(), 3);
+        // Best-effort: the other operations succeed, and the aborted id is used.
+        let outcomes = store.batch(ops, false, "test").await.unwrap();
+        assert!(matches!(&outcomes[0], BatchOutcome::Created(stored) if stored.book.id == 4));
+        assert!(outcomes[1].is_done());
+        assert_eq!(outcomes[2], BatchOutcome::NotFound(9));
+        assert_eq!(store.get(1).await, Ok(None));
+        // A later operation sees an earlier one, so it can use a new version.
+        let book = Book { id: 4, title: "The Decameron".into(), author: "Giovanni Boccaccio".into() };
+        let ops = vec![
+            BatchOp::Update { book: book.clone(), version: Some(1) },
+            BatchOp::Update { book, version: Some(1) },
+        ];
+        let outcomes = store.batch(ops, true, "test").await.unwrap();
+        assert_eq!(outcomes[1], BatchOutcome::VersionMismatch { id: 4, version: 2 });
+        assert_eq!(store.get(4).await.unwrap().unwrap().version, 1);
+    }
+
+    #[tokio::test]
+    async fn delete_moves_to_trash() {
+        let store = InMemoryBookStore::new(demo_books());
+        store.delete(1, None, "test").await.unwrap();
+        assert_eq!(store.get(1).await, Ok(None));
+        assert_eq!(store.list().await.unwrap().len(), 2);
+        let trash = store.trash().await.unwrap();
+        assert_eq!((trash[0].book.id, trash[0].version), (1, 2));
+        // A trashed book can't change, and can't be deleted again.
+        assert_eq!(store.delete(1, None, "test").await, Ok(None));
+        let restored = store.restore(1, "test").await.unwrap().unwrap();
+        assert_eq!((restored.version, restored.deleted_at), (3, None));
+        assert_eq!(store.restore(1, "test").await, Ok(None));
+        // Purge only books that were deleted at or before a time.
+        store.delete(1, None, "test").await.unwrap();
+        let deleted_at = store.trash().await.unwrap()[0].deleted_at.unwrap();
+        let before = deleted_at - std::time::Duration::from_secs(1);
+        assert_eq!(store.purge(None, before).await, Ok(vec![]));
+        assert_eq!(store.purge(Some(2), deleted_at).await, Ok(vec![]));
+        assert_eq!(store.purge(Some(1), deleted_at).await.unwrap().len(), 1);
+        assert_eq!(store.restore(1, "test").await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn changes_record_revisions() {
+        let store = InMemoryBookStore::new(demo_books());
+        let book = Book { id: 1, title: "Elektra".into(), author: "Sophocles".into() };
+        store.update(book.clone(), None, "ann").await.unwrap();
+        // A failed batch records nothing.
+        let ops = vec![BatchOp::Delete { id: 1, version: None }, BatchOp::Delete { id: 9, version: None }];
+        store.batch(ops, true, "bob").await.unwrap();
+        store.delete(1, None, "cy").await.unwrap();
+        let revisions = store.revisions(1).await.unwrap();
+        let summary: Vec<(u64, RevisionAction, &str)> =
+            revisions.iter().map(|r| (r.number, r.action, r.who.as_str())).collect();
+        assert_eq!(
+            summary,
+            [(1, RevisionAction::Create, "system"), (2, RevisionAction::Update, "ann"), (3, RevisionAction::Delete, "cy")]
+        );
+        assert_eq!((revisions[1].old.as_ref().unwrap().title.as_str(), revisions[1].new.as_ref()), ("Antigone", Some(&book)));
+        assert_eq!((revisions[2].old.as_ref(), revisions[2].new.as_ref()), (Some(&book), None));
+        store.purge(Some(1), SystemTime::now()).await.unwrap();
+        assert_eq!(store.revisions(1).await, Ok(vec![]));
+    }
+}
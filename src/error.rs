@@ -0,0 +1,193 @@
+// Use axum capabilities for responses and middleware.
+use axum::response::{IntoResponse, Response};
+
+// Use the message page, which escapes the message, which may quote a request.
+use crate::html::Markup;
+use crate::page::MessagePage;
+
+// Use the StoreError type, which we convert into an AppError.
+use crate::data::StoreError;
+
+// Use the response format negotiation, which chooses HTML or JSON.
+use crate::format::{negotiate, Format};
+
+// Use Serialize and ToSchema for the JSON error body, which our OpenAPI
+// document describes.
+use serde::Serialize;
+use utoipa::ToSchema;
+
+// Error for any axum handler that can fail.
+//
+// Each variant maps to one HTTP status code. A handler returns
+// `Result<_, AppError>`, then uses `?` or `Err(AppError::…)`.
+//
+// The response body is HTML by default. The middleware function
+// `negotiate_error_format` rewrites the body as JSON when the
+// request asks for JSON, so every route gets both formats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+    // The resource does not exist: Not Found (404).
+    NotFound(String),
+    // The request is well-formed yet invalid: Unprocessable Entity (422).
+    Validation(String),
+    // The request body has invalid fields, each with its own error, and a
+    // message that lists them all: Unprocessable Entity (422).
+    InvalidFields(String, Vec<FieldError>),
+    // The request conflicts with the current resource: Conflict (409).
+    Conflict(String),
+    // The request's precondition, such as `If-Match`, is false: Precondition Failed (412).
+    PreconditionFailed(String),
+    // The request asks for no format that we support: Not Acceptable (406).
+    NotAcceptable(String),
+    // The server failed; the detail is only for logs: Internal Server Error (500).
+    Internal(String),
+}
+
+impl AppError {
+    // The HTTP status code for this error.
+    pub fn status(&self) -> axum::http::StatusCode {
+        match self {
+            AppError::NotFound(_) => axum::http::StatusCode::NOT_FOUND,
+            AppError::Validation(_) | AppError::InvalidFields(..) => axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Conflict(_) => axum::http::StatusCode::CONFLICT,
+            AppError::PreconditionFailed(_) => axum::http::StatusCode::PRECONDITION_FAILED,
+            AppError::NotAcceptable(_) => axum::http::StatusCode::NOT_ACCEPTABLE,
+            AppError::Internal(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // The user-visible message for this error.
+    // An internal error hides its detail, which may contain secrets.
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::NotFound(message)
+            | AppError::Validation(message)
+            | AppError::InvalidFields(message, _)
+            | AppError::Conflict(message)
+            | AppError::PreconditionFailed(message)
+            | AppError::NotAcceptable(message) => message,
+            AppError::Internal(_) => "Internal server error",
+        }
+    }
+
+    // Create the error for invalid fields, such as from file schema.rs,
+    // with a message that lists each field error.
+    pub fn invalid_fields(errors: Vec<FieldError>) -> Self {
+        let list = errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ");
+        AppError::InvalidFields(format!("Invalid fields: {}", list), errors)
+    }
+
+    // The field errors, if any.
+    pub fn field_errors(&self) -> &[FieldError] {
+        match self {
+            AppError::InvalidFields(_, errors) => errors,
+            _ => &[],
+        }
+    }
+}
+
+// One invalid field in a request body, such as:
+// `{"field": "title", "message": "must have at least 1 characters"}`
+//
+// A nested field has a path, such as "operations[0].book.title".
+#[derive(Debug, Serialize, ToSchema, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+// Display the field error using the format "{field} {message}".
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AppError::Internal(detail) => write!(f, "{}: {}", self.status(), detail),
+            _ => write!(f, "{}: {}", self.status(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+// The JSON body of an error, such as:
+// `{"status": 404, "error": "Not Found", "message": "Book id 9 not found"}`
+//
+// An error for invalid fields has each field error too, in "errors".
+#[derive(Debug, Serialize, ToSchema, Clone, PartialEq, Eq)]
+pub struct ErrorBody {
+    pub status: u16,
+    pub error: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl From<&AppError> for ErrorBody {
+    fn from(err: &AppError) -> Self {
+        ErrorBody {
+            status: err.status().as_u16(),
+            error: err.status().canonical_reason().unwrap_or_default().to_string(),
+            message: err.message().to_string(),
+            errors: err.field_errors().to_vec(),
+        }
+    }
+}
+
+// Convert a data store error into an app error. A version mismatch means
+// another client changed the book first, so the client's precondition
+// is false; any other store error is an internal error.
+impl From<StoreError> for AppError {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::VersionMismatch { id, version } => AppError::PreconditionFailed(format!(
+                "Book id {} has changed; its version is now {}",
+                id, version
+            )),
+            StoreError::Failed(_) => AppError::Internal(err.to_string()),
+        }
+    }
+}
+
+// Convert an error into an HTML page with the matching status code.
+// The response keeps a copy of the error as an extension, so middleware
+// can render the error in another format.
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        if let AppError::Internal(_) = self {
+            tracing::error!("{}", self);
+        }
+        let title = self.status().canonical_reason().unwrap_or("Error");
+        let page = MessagePage { title, message: self.message() };
+        // If the error page fails to render, then send the message as text.
+        let body = match Markup::render(&page) {
+            Ok(markup) => markup.into_response(),
+            Err(_) => self.message().to_string().into_response(),
+        };
+        let mut response = (self.status(), body).into_response();
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+// axum middleware that renders any AppError as JSON, when the request
+// asks for JSON, by using the format {"status", "error", "message"}.
+// Any other request, including one that asks for CSV, gets HTML.
+pub async fn negotiate_error_format(
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Response {
+    let json = negotiate(request.uri(), request.headers()) == Ok(Format::Json);
+    let response = next.run(request).await;
+    if !json {
+        return response;
+    }
+    match response.extensions().get::<AppError>() {
+        Some(err) => (err.status(), axum::Json(ErrorBody::from(err))).into_response(),
+        None => response,
+    }
+}
@@ -0,0 +1,618 @@
+// Use HashMap for the view of books and their revisions.
+use std::collections::HashMap;
+
+// Use file I/O for the event log and its snapshot.
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// Use Arc and Mutex to share one event log among many axum handlers.
+use std::sync::{Arc, Mutex};
+
+// Use SystemTime for the time of each commit.
+use std::time::SystemTime;
+
+// Use Serialize and Deserialize to write events and snapshots as JSON.
+use serde::{Deserialize, Serialize};
+
+// Use async_trait so a trait with async functions can be a `dyn` object.
+use async_trait::async_trait;
+
+// Use the Book and NewBook structs, and the BookStore trait.
+use crate::book::{Book, NewBook};
+use crate::data::{
+    check_version, create_book, delete_book, demo_books, restore_book, update_book, BatchOp, BatchOutcome,
+    BookStore, Revision, StoreError, StoredBook,
+};
+
+// How many events we append before we write a new snapshot, by default.
+pub const DEFAULT_SNAPSHOT_EVERY: u64 = 1000;
+
+// One change to one book.
+//
+// The log has no "current value" of a book; a book's value is the result
+// of replaying each of its events in order, starting from no books.
+// Each event's name is its "type" in the log, such as "BookCreated".
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(tag = "type")]
+#[allow(clippy::enum_variant_names)]
+pub enum BookEvent {
+    BookCreated { book: Book },
+    BookUpdated { book: Book },
+    BookDeleted { id: u32 },
+    BookRestored { id: u32 },
+    BookPurged { id: u32 },
+}
+
+// One commit: the events of one change, which is one line of JSON in the log.
+//
+// A batch is one commit, so a crash while we write it loses all of its
+// events or none of them, and an all-or-nothing batch stays that way.
+// The sequence number counts commits, starting at 1, with no gaps.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Commit {
+    pub seq: u64,
+    pub at: SystemTime,
+    pub who: String,
+    pub events: Vec<BookEvent>,
+}
+
+// The view of the books that we build by replaying commits, which is
+// also called a projection. It has the same data as the in-memory store,
+// and uses the same helpers to change it, so both stores have the same rules.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+struct Projection {
+    books: HashMap<u32, StoredBook>,
+    revisions: HashMap<u32, Vec<Revision>>,
+    next_id: u32,
+    modified: SystemTime,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection {
+            books: HashMap::new(),
+            revisions: HashMap::new(),
+            next_id: 1,
+            modified: SystemTime::UNIX_EPOCH,
+        }
+    }
+}
+
+impl Projection {
+    // Get a book that is not in the trash.
+    fn live(&self, id: u32) -> Option<&StoredBook> {
+        self.books.get(&id).filter(|stored| stored.deleted_at.is_none())
+    }
+
+    // Apply the events of a commit, or fail if an event doesn't fit the
+    // view, such as an update of a book that doesn't exist. We check each
+    // change before we append it, so only a damaged log can fail here.
+    fn apply(&mut self, commit: &Commit) -> Result<(), StoreError> {
+        let invalid = |event: &BookEvent| {
+            StoreError::Failed(format!("event log commit {} has an invalid event: {:?}", commit.seq, event))
+        };
+        let (at, who) = (commit.at, commit.who.as_str());
+        let mut log = Vec::new();
+        for event in &commit.events {
+            let books = &mut self.books;
+            let applied = match event {
+                BookEvent::BookCreated { book } if !books.contains_key(&book.id) => {
+                    create_book(books, &mut log, book.clone(), at, who);
+                    true
+                }
+                BookEvent::BookCreated { .. } => false,
+                BookEvent::BookUpdated { book } => update_book(books, &mut log, book.clone(), None, at, who)?.is_some(),
+                BookEvent::BookDeleted { id } => delete_book(books, &mut log, *id, None, at, who)?.is_some(),
+                BookEvent::BookRestored { id } => restore_book(books, &mut log, *id, at, who).is_some(),
+                BookEvent::BookPurged { id } => {
+                    let trashed = books.get(id).is_some_and(|stored| stored.deleted_at.is_some());
+                    if trashed {
+                        books.remove(id);
+                        self.revisions.remove(id);
+                    }
+                    trashed
+                }
+            };
+            if let BookEvent::BookCreated { book } = event {
+                self.next_id = self.next_id.max(book.id + 1);
+            }
+            if !applied {
+                return Err(invalid(event));
+            }
+        }
+        if !commit.events.is_empty() {
+            self.modified = at;
+        }
+        for revision in log {
+            self.revisions.entry(revision.book_id).or_default().push(revision);
+        }
+        Ok(())
+    }
+}
+
+// A snapshot of the view as of a commit, and where the next commit starts
+// in the log, so startup replays only the commits after the snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    seq: u64,
+    offset: u64,
+    projection: Projection,
+}
+
+// The event log file, its snapshot file, and the view.
+#[derive(Debug)]
+struct EventLog {
+    path: PathBuf,
+    file: File,
+    // The length of the log, which is where the next commit starts.
+    len: u64,
+    // The sequence number of the last commit.
+    seq: u64,
+    // The sequence number of the last snapshot.
+    snapshot_seq: u64,
+    snapshot_every: u64,
+    projection: Projection,
+}
+
+// What we get by replaying a log: the view, and where the replay ended.
+struct Replay {
+    projection: Projection,
+    seq: u64,
+    len: u64,
+    events: u64,
+}
+
+// Create a data store that keeps a durable log of book events.
+//
+// Each change appends a commit to the log file, which is JSON Lines, and
+// syncs it to disk, then applies the commit to the in-memory view. At
+// startup, we rebuild the view by replaying the log. To bound replay time,
+// we write a snapshot of the view every so many commits; then startup
+// loads the snapshot and replays only the commits after it.
+//
+// The log is the source of truth. The snapshot is a cache, which we can
+// delete at any time, and the admin can rebuild the view from the log.
+//
+// File I/O is synchronous, so each operation runs on tokio's blocking
+// thread pool, and a mutex serializes access to the log and the view.
+#[derive(Debug, Clone)]
+pub struct EventSourcedBookStore {
+    log: Arc<Mutex<EventLog>>,
+}
+
+impl EventSourcedBookStore {
+    // Open an event log file, creating it if needed, then rebuild the view.
+    // A new empty log starts with our demo books, if seeding is on.
+    pub fn open(path: impl AsRef<Path>, seed: bool) -> Result<Self, StoreError> {
+        Self::open_with(path, seed, DEFAULT_SNAPSHOT_EVERY)
+    }
+
+    // Open an event log file, with a snapshot every so many commits.
+    pub fn open_with(path: impl AsRef<Path>, seed: bool, snapshot_every: u64) -> Result<Self, StoreError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().read(true).append(true).create(true).open(&path)?;
+        let snapshot = read_snapshot(&path, file.metadata()?.len());
+        let snapshot_seq = snapshot.as_ref().map_or(0, |snapshot| snapshot.seq);
+        let replay = match snapshot {
+            Some(Snapshot { seq, offset, projection }) => replay(&file, projection, seq, offset)?,
+            None => replay(&file, Projection::default(), 0, 0)?,
+        };
+        let mut log = EventLog {
+            path,
+            file,
+            len: replay.len,
+            seq: replay.seq,
+            snapshot_seq,
+            snapshot_every: snapshot_every.max(1),
+            projection: replay.projection,
+        };
+        if seed && log.seq == 0 {
+            let events = demo_books().into_iter().map(|book| BookEvent::BookCreated { book }).collect();
+            log.commit(SystemTime::now(), "system", events)?;
+        }
+        Ok(Self { log: Arc::new(Mutex::new(log)) })
+    }
+
+    // Run a closure with the event log, on the blocking thread pool.
+    async fn with_log<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut EventLog) -> Result<T, StoreError> + Send + 'static,
+    {
+        let log = self.log.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut log = log.lock().map_err(|e| StoreError::Failed(e.to_string()))?;
+            f(&mut log)
+        })
+        .await
+        .map_err(|e| StoreError::Failed(e.to_string()))?
+    }
+}
+
+impl EventLog {
+    // Append a commit of events at a time to the log, sync it, then apply
+    // it to the view. If the write fails, then we cut off any partial line,
+    // so the log and the view still agree. The caller picks the time, so
+    // anything that it returns, such as a batch's outcomes, has the same
+    // time as the log.
+    fn commit(&mut self, at: SystemTime, who: &str, events: Vec<BookEvent>) -> Result<(), StoreError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let commit = Commit { seq: self.seq + 1, at, who: who.to_string(), events };
+        let mut line = serde_json::to_vec(&commit).map_err(|e| StoreError::Failed(e.to_string()))?;
+        line.push(b'\n');
+        if let Err(err) = self.file.write_all(&line).and_then(|()| self.file.sync_data()) {
+            let _ = self.file.set_len(self.len);
+            return Err(err.into());
+        }
+        self.len += line.len() as u64;
+        self.seq = commit.seq;
+        self.projection.apply(&commit)?;
+        if self.seq - self.snapshot_seq >= self.snapshot_every {
+            // The commit is durable, so a failed snapshot only costs replay time.
+            if let Err(err) = self.snapshot() {
+                tracing::warn!("failed to write event log snapshot: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    // Write a snapshot of the view to a temporary file, then rename it,
+    // so a crash never leaves a partial snapshot.
+    fn snapshot(&mut self) -> Result<(), StoreError> {
+        let snapshot = Snapshot { seq: self.seq, offset: self.len, projection: self.projection.clone() };
+        let path = snapshot_path(&self.path);
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        serde_json::to_writer(&mut file, &snapshot).map_err(|e| StoreError::Failed(e.to_string()))?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &path)?;
+        self.snapshot_seq = self.seq;
+        Ok(())
+    }
+
+    // Rebuild the view by replaying the whole log, ignoring any snapshot,
+    // then write a new snapshot, and return how many events we replayed.
+    fn rebuild(&mut self) -> Result<u64, StoreError> {
+        let replay = replay(&self.file, Projection::default(), 0, 0)?;
+        self.projection = replay.projection;
+        self.seq = replay.seq;
+        self.len = replay.len;
+        self.snapshot()?;
+        Ok(replay.events)
+    }
+}
+
+// The snapshot file of a log file, such as "books.log.snapshot".
+fn snapshot_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".snapshot");
+    PathBuf::from(path)
+}
+
+// Read the snapshot of a log file, if it exists and fits the log. A bad
+// snapshot is only a lost cache, so we warn and replay the whole log.
+fn read_snapshot(path: &Path, log_len: u64) -> Option<Snapshot> {
+    let file = File::open(snapshot_path(path)).ok()?;
+    match serde_json::from_reader::<_, Snapshot>(BufReader::new(file)) {
+        Ok(snapshot) if snapshot.offset <= log_len => Some(snapshot),
+        Ok(_) => {
+            tracing::warn!("event log snapshot is past the end of the log; replaying the whole log");
+            None
+        }
+        Err(err) => {
+            tracing::warn!("event log snapshot is unreadable; replaying the whole log: {}", err);
+            None
+        }
+    }
+}
+
+// Replay the commits of a log, starting at an offset, onto a view as of
+// a sequence number.
+//
+// A crash while we append a commit can leave a partial last line, which
+// was never acknowledged, so we cut it off. Any other bad line is damage
+// that we can't fix, so we fail, rather than serve a wrong view.
+fn replay(file: &File, mut projection: Projection, mut seq: u64, offset: u64) -> Result<Replay, StoreError> {
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(offset))?;
+    let (mut len, mut events) = (offset, 0);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let n = reader.read_until(b'\n', &mut line)? as u64;
+        if n == 0 {
+            break;
+        }
+        let commit = match serde_json::from_slice::<Commit>(&line) {
+            Ok(commit) if line.ends_with(b"\n") => commit,
+            result => {
+                if !reader.fill_buf()?.is_empty() {
+                    let err = result.err().map(|e| e.to_string()).unwrap_or_default();
+                    return Err(StoreError::Failed(format!("event log is damaged at byte {}: {}", len, err)));
+                }
+                tracing::warn!("event log has a partial last commit at byte {}; cutting it off", len);
+                file.set_len(len)?;
+                break;
+            }
+        };
+        if commit.seq != seq + 1 {
+            return Err(StoreError::Failed(format!(
+                "event log commit {} follows commit {}",
+                commit.seq, seq
+            )));
+        }
+        projection.apply(&commit)?;
+        seq = commit.seq;
+        len += n;
+        events += commit.events.len() as u64;
+    }
+    Ok(Replay { projection, seq, len, events })
+}
+
+// Convert a file error into a data store error.
+impl From<std::io::Error> for StoreError {
+    fn from(err: std::io::Error) -> Self {
+        StoreError::Failed(err.to_string())
+    }
+}
+
+#[async_trait]
+impl BookStore for EventSourcedBookStore {
+    async fn get(&self, id: u32) -> Result<Option<StoredBook>, StoreError> {
+        self.with_log(move |log| Ok(log.projection.live(id).cloned())).await
+    }
+
+    async fn list(&self) -> Result<Vec<Book>, StoreError> {
+        self.with_log(|log| {
+            Ok(log
+                .projection
+                .books
+                .values()
+                .filter(|stored| stored.deleted_at.is_none())
+                .map(|stored| stored.book.clone())
+                .collect())
+        })
+        .await
+    }
+
+    async fn last_modified(&self) -> Result<SystemTime, StoreError> {
+        self.with_log(|log| Ok(log.projection.modified)).await
+    }
+
+    async fn create(&self, new_book: NewBook, who: &str) -> Result<StoredBook, StoreError> {
+        let who = who.to_string();
+        self.with_log(move |log| {
+            let book = new_book.with_id(log.projection.next_id);
+            let id = book.id;
+            log.commit(SystemTime::now(), &who, vec![BookEvent::BookCreated { book }])?;
+            Ok(log.projection.books[&id].clone())
+        })
+        .await
+    }
+
+    async fn update(&self, book: Book, expected: Option<&[u64]>, who: &str) -> Result<Option<StoredBook>, StoreError> {
+        let (expected, who) = (expected.map(<[u64]>::to_vec), who.to_string());
+        self.with_log(move |log| {
+            let id = book.id;
+            let Some(stored) = log.projection.live(id) else {
+                return Ok(None);
+            };
+            check_version(id, stored.version, expected.as_deref())?;
+            log.commit(SystemTime::now(), &who, vec![BookEvent::BookUpdated { book }])?;
+            Ok(log.projection.live(id).cloned())
+        })
+        .await
+    }
+
+    async fn delete(&self, id: u32, expected: Option<&[u64]>, who: &str) -> Result<Option<Book>, StoreError> {
+        let (expected, who) = (expected.map(<[u64]>::to_vec), who.to_string());
+        self.with_log(move |log| {
+            let Some(stored) = log.projection.live(id) else {
+                return Ok(None);
+            };
+            check_version(id, stored.version, expected.as_deref())?;
+            let book = stored.book.clone();
+            log.commit(SystemTime::now(), &who, vec![BookEvent::BookDeleted { id }])?;
+            Ok(Some(book))
+        })
+        .await
+    }
+
+    async fn trash(&self) -> Result<Vec<StoredBook>, StoreError> {
+        self.with_log(|log| {
+            Ok(log.projection.books.values().filter(|stored| stored.deleted_at.is_some()).cloned().collect())
+        })
+        .await
+    }
+
+    async fn restore(&self, id: u32, who: &str) -> Result<Option<StoredBook>, StoreError> {
+        let who = who.to_string();
+        self.with_log(move |log| {
+            if log.projection.books.get(&id).is_none_or(|stored| stored.deleted_at.is_none()) {
+                return Ok(None);
+            }
+            log.commit(SystemTime::now(), &who, vec![BookEvent::BookRestored { id }])?;
+            Ok(log.projection.live(id).cloned())
+        })
+        .await
+    }
+
+    async fn purge(&self, id: Option<u32>, deleted_before: SystemTime) -> Result<Vec<Book>, StoreError> {
+        self.with_log(move |log| {
+            let books: Vec<Book> = log
+                .projection
+                .books
+                .values()
+                .filter(|stored| stored.deleted_at.is_some_and(|deleted_at| deleted_at <= deleted_before))
+                .filter(|stored| id.is_none_or(|id| id == stored.book.id))
+                .map(|stored| stored.book.clone())
+                .collect();
+            let events = books.iter().map(|book| BookEvent::BookPurged { id: book.id }).collect();
+            log.commit(SystemTime::now(), "system", events)?;
+            Ok(books)
+        })
+        .await
+    }
+
+    // The batch changes a copy of the view, to check each operation and
+    // to get its outcome, then appends one commit of the events of the
+    // operations that succeed, only if the batch may change the store.
+    async fn batch(&self, ops: Vec<BatchOp>, atomic: bool, who: &str) -> Result<Vec<BatchOutcome>, StoreError> {
+        let who = who.to_string();
+        self.with_log(move |log| {
+            let mut changed = log.projection.books.clone();
+            let mut revisions = Vec::new();
+            let mut next_id = log.projection.next_id;
+            let at = SystemTime::now();
+            let mut outcomes = Vec::with_capacity(ops.len());
+            for op in ops {
+                outcomes.push(match op {
+                    BatchOp::Create { book } => {
+                        let stored = create_book(&mut changed, &mut revisions, book.with_id(next_id), at, &who);
+                        next_id += 1;
+                        BatchOutcome::Created(stored)
+                    }
+                    BatchOp::Update { book, version } => {
+                        let id = book.id;
+                        let expected = BatchOp::expected(&version);
+                        let result = update_book(&mut changed, &mut revisions, book, expected, at, &who);
+                        BatchOutcome::of(id, result, BatchOutcome::Updated)?
+                    }
+                    BatchOp::Delete { id, version } => {
+                        let expected = BatchOp::expected(&version);
+                        let result = delete_book(&mut changed, &mut revisions, id, expected, at, &who);
+                        BatchOutcome::of(id, result, BatchOutcome::Deleted)?
+                    }
+                });
+            }
+            if atomic && !outcomes.iter().all(BatchOutcome::is_done) {
+                return Ok(BatchOutcome::abort(outcomes));
+            }
+            let events = outcomes
+                .iter()
+                .filter_map(|outcome| match outcome {
+                    BatchOutcome::Created(stored) => Some(BookEvent::BookCreated { book: stored.book.clone() }),
+                    BatchOutcome::Updated(stored) => Some(BookEvent::BookUpdated { book: stored.book.clone() }),
+                    BatchOutcome::Deleted(book) => Some(BookEvent::BookDeleted { id: book.id }),
+                    _ => None,
+                })
+                .collect();
+            log.commit(at, &who, events)?;
+            Ok(outcomes)
+        })
+        .await
+    }
+
+    async fn revisions(&self, id: u32) -> Result<Vec<Revision>, StoreError> {
+        self.with_log(move |log| Ok(log.projection.revisions.get(&id).cloned().unwrap_or_default())).await
+    }
+
+    async fn rebuild(&self) -> Result<u64, StoreError> {
+        self.with_log(EventLog::rebuild).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A log file path in a new temporary directory, which we clear first,
+    // so each test starts with no log and no snapshot.
+    fn temp_log(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("demo-rust-axum-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("books.log")
+    }
+
+    #[tokio::test]
+    async fn books_persist_across_restarts() {
+        let path = temp_log("event-restart");
+        let store = EventSourcedBookStore::open(&path, true).unwrap();
+        let new_book = NewBook { title: "Decameron".into(), author: "Giovanni Boccaccio".into() };
+        let stored = store.create(new_book, "test").await.unwrap();
+        assert_eq!(stored.book.id, 4);
+        store.delete(1, None, "test").await.unwrap();
+        let book = Book { id: 2, title: "Elektra".into(), author: "Sophocles".into() };
+        store.update(book.clone(), Some(&[1]), "test").await.unwrap();
+        drop(store);
+        let store = EventSourcedBookStore::open(&path, true).unwrap();
+        assert_eq!(store.list().await.unwrap().len(), 3);
+        assert_eq!(store.get(2).await.unwrap().unwrap().book, book);
+        assert_eq!(store.trash().await.unwrap().len(), 1);
+        assert_eq!(store.revisions(2).await.unwrap().len(), 2);
+        // Ids still only ever increase after a restart.
+        let new_book = NewBook { title: "Faust".into(), author: "Goethe".into() };
+        assert_eq!(store.create(new_book, "test").await.unwrap().book.id, 5);
+    }
+
+    #[tokio::test]
+    async fn snapshot_bounds_replay() {
+        let path = temp_log("event-snapshot");
+        let store = EventSourcedBookStore::open_with(&path, true, 2).unwrap();
+        for version in 1..=3 {
+            let book = Book { id: 1, title: format!("Antigone {}", version), author: "Sophocles".into() };
+            store.update(book, Some(&[version]), "test").await.unwrap();
+        }
+        let before = store.get(1).await.unwrap();
+        drop(store);
+        let snapshot = read_snapshot(&path, u64::MAX).unwrap();
+        assert_eq!(snapshot.seq, 4);
+        // The snapshot is a cache, so any view matches the log's.
+        let store = EventSourcedBookStore::open_with(&path, true, 2).unwrap();
+        assert_eq!(store.get(1).await.unwrap(), before);
+        std::fs::remove_file(snapshot_path(&path)).unwrap();
+        let store = EventSourcedBookStore::open_with(&path, true, 2).unwrap();
+        assert_eq!(store.get(1).await.unwrap(), before);
+        assert_eq!(store.rebuild().await, Ok(6));
+        assert_eq!(store.get(1).await.unwrap(), before);
+    }
+
+    #[tokio::test]
+    async fn partial_last_commit_is_cut_off() {
+        let path = temp_log("event-partial");
+        let store = EventSourcedBookStore::open(&path, true).unwrap();
+        store.delete(1, None, "test").await.unwrap();
+        drop(store);
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"seq\":3,\"at\"").unwrap();
+        let store = EventSourcedBookStore::open(&path, true).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        assert_eq!(store.list().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn atomic_batch_appends_nothing_on_failure() {
+        let path = temp_log("event-batch");
+        let store = EventSourcedBookStore::open(&path, true).unwrap();
+        let len = std::fs::metadata(&path).unwrap().len();
+        let ops = vec![
+            BatchOp::Create { book: NewBook { title: "Faust".into(), author: "Goethe".into() } },
+            BatchOp::Delete { id: 9, version: None },
+        ];
+        let outcomes = store.batch(ops.clone(), true, "test").await.unwrap();
+        assert_eq!(outcomes[1], BatchOutcome::NotFound(9));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        store.batch(ops, false, "test").await.unwrap();
+        drop(store);
+        let store = EventSourcedBookStore::open(&path, true).unwrap();
+        assert_eq!(store.list().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn batch_outcomes_have_the_time_of_the_log() {
+        let path = temp_log("event-batch-time");
+        let store = EventSourcedBookStore::open(&path, true).unwrap();
+        let book = Book { id: 1, title: "Antigone".into(), author: "Sophocles".into() };
+        let outcomes = store.batch(vec![BatchOp::Update { book, version: None }], true, "test").await.unwrap();
+        let BatchOutcome::Updated(stored) = &outcomes[0] else { panic!("{:?}", outcomes) };
+        drop(store);
+        let store = EventSourcedBookStore::open(&path, true).unwrap();
+        assert_eq!(store.get(1).await.unwrap().unwrap().modified, stored.modified);
+        assert_eq!(store.revisions(1).await.unwrap().last().unwrap().when, stored.modified);
+        assert_eq!(store.last_modified().await.unwrap(), stored.modified);
+    }
+}
@@ -0,0 +1,244 @@
+// Use axum capabilities for responses and extractors.
+use axum::response::{IntoResponse, Response};
+
+// Use the Book struct and the AppError type.
+use crate::book::Book;
+use crate::error::AppError;
+
+// Use the books page, which escapes each book, so a title can't inject HTML.
+use crate::html::Markup;
+use crate::page::BooksPage;
+
+// A response format that a client can ask for.
+//
+// A client asks by using a path suffix, such as "/books/1.json",
+// or else by using an HTTP `Accept` header. The suffix wins, so
+// a browser can follow a plain link to any format.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    Html,
+    Json,
+    Csv,
+}
+
+impl Format {
+    // The media type of this format, for the `Content-Type` header.
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Format::Html => "text/html; charset=utf-8",
+            Format::Json => "application/json",
+            Format::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    // The path suffix of this format, such as "json".
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Format::Html => "html",
+            Format::Json => "json",
+            Format::Csv => "csv",
+        }
+    }
+
+    // Get the format of a path suffix such as "json", if we support it.
+    pub fn from_suffix(suffix: &str) -> Option<Format> {
+        match suffix {
+            "html" => Some(Format::Html),
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    // Get the format of one media range such as "text/*", if we support it.
+    fn from_media_range(range: &str) -> Option<Format> {
+        match range {
+            "text/html" | "text/*" | "*/*" => Some(Format::Html),
+            "application/json" => Some(Format::Json),
+            "text/csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    // Choose the format that an `Accept` header value ranks highest,
+    // by using each media range's quality value "q", which defaults
+    // to 1. A missing or empty header means any format, so HTML.
+    pub fn from_accept(accept: Option<&str>) -> Result<Format, AppError> {
+        let accept = match accept.map(str::trim) {
+            None | Some("") => return Ok(Format::Html),
+            Some(accept) => accept,
+        };
+        let mut best: Option<(Format, f32)> = None;
+        for item in accept.split(',') {
+            let mut parts = item.split(';').map(str::trim);
+            let range = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if let Some(format) = Format::from_media_range(&range)
+                && q > 0.0
+                && best.is_none_or(|(_, best_q)| q > best_q)
+            {
+                best = Some((format, q));
+            }
+        }
+        best.map(|(format, _)| format).ok_or_else(|| {
+            AppError::NotAcceptable(format!(
+                "Accept header {} has no supported type: text/html, application/json, text/csv",
+                accept
+            ))
+        })
+    }
+}
+
+// Split a path segment such as "1.json" into "1" and its format.
+// A segment without a suffix has no format.
+pub fn split_suffix(segment: &str) -> Result<(&str, Option<Format>), AppError> {
+    match segment.rsplit_once('.') {
+        None => Ok((segment, None)),
+        Some((stem, suffix)) => match Format::from_suffix(suffix) {
+            Some(format) => Ok((stem, Some(format))),
+            None => Err(AppError::NotAcceptable(format!(
+                "Suffix .{} is not supported: use .html, .json, or .csv",
+                suffix
+            ))),
+        },
+    }
+}
+
+// axum extractor for the response format that a request asks for,
+// by its path suffix, else by its `Accept` header. If the request asks
+// only for formats that we don't support, respond with 406 Not Acceptable.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Negotiate(pub Format);
+
+impl<S: Send + Sync> axum::extract::FromRequestParts<S> for Negotiate {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        negotiate(&parts.uri, &parts.headers).map(Negotiate)
+    }
+}
+
+// Choose the response format for a request URI and its headers.
+pub fn negotiate(uri: &axum::http::Uri, headers: &axum::http::HeaderMap) -> Result<Format, AppError> {
+    let segment = uri.path().rsplit('/').next().unwrap_or_default();
+    if let (_, Some(format)) = split_suffix(segment)? {
+        return Ok(format);
+    }
+    let accept = headers
+        .get(axum::http::header::ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    Format::from_accept(accept)
+}
+
+// Render books as a response in a format.
+//
+// HTML is a page with the title, then one paragraph per book.
+// JSON is an array of books. CSV is a header row, then one row per book.
+pub fn render_books(format: Format, title: &str, books: &[Book]) -> Result<Response, AppError> {
+    Ok(respond(format, format_books(format, title, books)?))
+}
+
+// Format books as a response body in a format; see `render_books`.
+pub fn format_books(format: Format, title: &str, books: &[Book]) -> Result<String, AppError> {
+    Ok(match format {
+        Format::Html => Markup::render(&BooksPage::new(title, books))?.into_string(),
+        Format::Json => serde_json::to_string(books).unwrap_or_default(),
+        Format::Csv => csv(books),
+    })
+}
+
+// Render one book as a response in a format.
+pub fn render_book(format: Format, book: &Book) -> Result<Response, AppError> {
+    match format {
+        Format::Json => Ok(respond(format, serde_json::to_string(book).unwrap_or_default())),
+        _ => render_books(format, &book.title, std::slice::from_ref(book)),
+    }
+}
+
+// Respond with a body in a format. The response varies by the `Accept`
+// header, so we say so, which tells any cache to keep each format apart.
+pub fn respond(format: Format, body: String) -> Response {
+    (
+        [
+            (axum::http::header::CONTENT_TYPE, format.media_type()),
+            (axum::http::header::VARY, "Accept"),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+// Format books as CSV, with a header row, per RFC 4180.
+fn csv(books: &[Book]) -> String {
+    let mut csv = String::from("id,title,author\r\n");
+    for book in books {
+        csv.push_str(&format!(
+            "{},{},{}\r\n",
+            book.id,
+            csv_field(&book.title),
+            csv_field(&book.author)
+        ));
+    }
+    csv
+}
+
+// Quote a CSV field if it contains a comma, quote, or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_accept() {
+        assert_eq!(Format::from_accept(None), Ok(Format::Html));
+        assert_eq!(Format::from_accept(Some("application/json")), Ok(Format::Json));
+        assert_eq!(Format::from_accept(Some("text/html;q=0.5, text/csv")), Ok(Format::Csv));
+        assert_eq!(Format::from_accept(Some("image/png, */*;q=0.1")), Ok(Format::Html));
+        assert!(Format::from_accept(Some("image/png")).is_err());
+        assert!(Format::from_accept(Some("application/json;q=0")).is_err());
+    }
+
+    #[test]
+    fn negotiate_by_suffix_then_accept() {
+        let uri = |uri: &str| uri.parse::<axum::http::Uri>().unwrap();
+        let accept = |accept: &str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert(axum::http::header::ACCEPT, accept.parse().unwrap());
+            headers
+        };
+        assert_eq!(negotiate(&uri("/books/1.html"), &accept("application/json")), Ok(Format::Html));
+        assert_eq!(negotiate(&uri("/books/1"), &accept("text/csv")), Ok(Format::Csv));
+        assert_eq!(negotiate(&uri("/books.csv?limit=1"), &accept("text/html")), Ok(Format::Csv));
+        assert!(negotiate(&uri("/books/1"), &accept("image/png")).is_err());
+        assert!(negotiate(&uri("/books/1.xml"), &axum::http::HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn format_books_in_each_format() {
+        let book = Book { id: 1, title: "Antigone".into(), author: "Sophocles".into() };
+        let books = [book];
+        let html = format_books(Format::Html, "Books", &books).unwrap();
+        assert!(html.contains("<main>\n<p>Antigone by Sophocles</p>\n</main>"), "{}", html);
+        assert_eq!(format_books(Format::Json, "Books", &books).unwrap(), r#"[{"id":1,"title":"Antigone","author":"Sophocles"}]"#);
+        assert_eq!(format_books(Format::Csv, "Books", &books).unwrap(), "id,title,author\r\n1,Antigone,Sophocles\r\n");
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        let book = Book { id: 1, title: "Crime, and \"Punishment\"".into(), author: "Dostoevsky".into() };
+        assert_eq!(csv(&[book]), "id,title,author\r\n1,\"Crime, and \"\"Punishment\"\"\",Dostoevsky\r\n");
+    }
+}
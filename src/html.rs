@@ -0,0 +1,91 @@
+// Use axum capabilities, so a handler can respond with markup.
+use axum::response::{IntoResponse, Response};
+
+// Use askama templates, which escape each value; see file page.rs.
+use askama::Template;
+
+// Use the AppError type, for a template that fails to render.
+use crate::error::AppError;
+
+// HTML that is safe to send to a browser, because each value in it is
+// escaped, such as "<" as "&#60;", unless a handler opts out on purpose.
+//
+// Build markup by rendering a template, such as a page in file page.rs,
+// which escapes each value by default. The one way to opt out is
+// `Markup::raw`, which is for HTML that we wrote, such as a constant,
+// and never for a value from a request or a data store.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Markup(String);
+
+impl Markup {
+    // Trust a string as HTML, as is, without escaping.
+    pub fn raw(html: impl Into<String>) -> Markup {
+        Markup(html.into())
+    }
+
+    // Render a template into markup, or fail with an internal error, such
+    // as when a value's `Display` fails, rather than send an empty page.
+    pub fn render(template: &impl Template) -> Result<Markup, AppError> {
+        template
+            .render()
+            .map(Markup)
+            .map_err(|err| AppError::Internal(format!("Template failed to render: {}", err)))
+    }
+
+    // View the HTML string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // Convert into the HTML string.
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+// Show markup as its HTML, such as in a template.
+impl std::fmt::Display for Markup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// Markup is already safe, so a template shows it as is, without escaping
+// it again, such as for a page that shows a file of HTML that we wrote.
+impl askama::filters::HtmlSafe for Markup {}
+
+// Respond with markup, with an HTTP header content-type of `text/html`.
+impl IntoResponse for Markup {
+    fn into_response(self) -> Response {
+        axum::response::Html(self.0).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A value whose `Display` fails, so its template fails to render.
+    struct Broken;
+
+    impl std::fmt::Display for Broken {
+        fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            Err(std::fmt::Error)
+        }
+    }
+
+    #[derive(Template)]
+    #[template(ext = "html", source = "<p>{{ value }}</p>")]
+    struct Paragraph<T: std::fmt::Display> {
+        value: T,
+    }
+
+    #[test]
+    fn render_escapes_or_fails() {
+        let markup = Markup::render(&Paragraph { value: "<b>" }).unwrap();
+        assert_eq!(markup.as_str(), "<p>&#60;b&#62;</p>");
+        // A failure is a server error, rather than an empty page.
+        let err = Markup::render(&Paragraph { value: Broken }).unwrap_err();
+        assert_eq!(err.into_response().status(), axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
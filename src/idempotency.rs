@@ -0,0 +1,358 @@
+// Use axum capabilities for requests, responses, and middleware.
+use axum::response::{IntoResponse, Response};
+
+// Use HashMap for idempotency records, keyed by client and key.
+use std::collections::HashMap;
+
+// Use Hash to fingerprint a request, so a retry must be identical.
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// Use Mutex for thread-safe access to the records. We never hold the lock
+// across an await, so a std lock is fine, and the records have a sync API.
+use std::sync::{Mutex, PoisonError};
+
+// Use Instant and Duration for each record's time to live.
+use std::time::{Duration, Instant};
+
+// Use the AppState struct, which has our idempotency records.
+use crate::app::AppState;
+
+// Use the AppError type.
+use crate::error::AppError;
+
+// The request header that a client sends with a unique key per operation,
+// such as a UUID, per the IETF draft "The Idempotency-Key HTTP Header Field".
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+// The response header that marks a replay of a first response.
+pub const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+
+// How long we keep a first response, so a client can retry within that time.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// The maximum length of a key, so a client can't use us as storage.
+const MAX_KEY_LEN: usize = 255;
+
+// The maximum size of a request body that we fingerprint.
+const MAX_BODY_LEN: usize = 2 * 1024 * 1024;
+
+// The request headers that change what a request means, so we fingerprint
+// them too. A retry with another `If-Match` has another precondition, and
+// a retry with another `Accept` asks for another response format.
+const FINGERPRINT_HEADERS: [axum::http::HeaderName; 4] = [
+    axum::http::header::IF_MATCH,
+    axum::http::header::IF_NONE_MATCH,
+    axum::http::header::CONTENT_TYPE,
+    axum::http::header::ACCEPT,
+];
+
+// Idempotency records, which let a client safely retry an unsafe request.
+//
+// A client sends an unsafe request, such as "PUT /books", with an
+// `Idempotency-Key` header. We run the first request with that key, then
+// keep its response. If the client retries with the same key and the same
+// request, then we replay the response, rather than create another book.
+//
+// Records are per client, so one client can't replay another's response,
+// and each record expires after its time to live.
+#[derive(Debug)]
+pub struct IdempotencyKeys {
+    ttl: Duration,
+    records: Mutex<HashMap<(String, String), Record>>,
+}
+
+// One record: the fingerprint of the first request, and its response,
+// or `None` while the first request is still in progress.
+#[derive(Debug, Clone)]
+struct Record {
+    fingerprint: u64,
+    response: Option<SavedResponse>,
+    expires: Instant,
+}
+
+// A response that we can replay: its status, headers, and body, and any
+// AppError, so middleware can still render the error in another format.
+#[derive(Debug, Clone)]
+struct SavedResponse {
+    status: axum::http::StatusCode,
+    headers: axum::http::HeaderMap,
+    body: axum::body::Bytes,
+    error: Option<AppError>,
+}
+
+impl IntoResponse for SavedResponse {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, self.headers, self.body).into_response();
+        if let Some(err) = self.error {
+            response.extensions_mut().insert(err);
+        }
+        response
+    }
+}
+
+// What to do with a request, given any record for its key.
+#[derive(Debug)]
+enum Begin {
+    // There's no record, so run the request; we've recorded it as in progress.
+    Run,
+    // The same request finished before, so replay its response.
+    Replay(SavedResponse),
+    // The same request is still in progress.
+    InProgress,
+    // A different request used this key.
+    Mismatch,
+}
+
+impl IdempotencyKeys {
+    // Create empty records, with a time to live for each record.
+    pub fn new(ttl: Duration) -> Self {
+        IdempotencyKeys { ttl, records: Mutex::new(HashMap::new()) }
+    }
+
+    // Find any record for a client and key, else record a new request.
+    // This also drops expired records, so the records don't grow forever.
+    fn begin(&self, client: &str, key: &str, fingerprint: u64) -> Begin {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        records.retain(|_, record| record.expires > now);
+        match records.get(&(client.to_string(), key.to_string())) {
+            Some(record) if record.fingerprint != fingerprint => Begin::Mismatch,
+            Some(Record { response: Some(response), .. }) => Begin::Replay(response.clone()),
+            Some(Record { response: None, .. }) => Begin::InProgress,
+            None => {
+                let record = Record { fingerprint, response: None, expires: now + self.ttl };
+                records.insert((client.to_string(), key.to_string()), record);
+                Begin::Run
+            }
+        }
+    }
+
+    // Keep the response of a request that we ran, or drop the record if there's
+    // no response to keep, such as for a server error, so the client can retry.
+    fn finish(&self, client: &str, key: &str, response: Option<SavedResponse>) {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        let id = (client.to_string(), key.to_string());
+        match response {
+            Some(response) => {
+                if let Some(record) = records.get_mut(&id) {
+                    record.response = Some(response);
+                }
+            }
+            None => {
+                records.remove(&id);
+            }
+        }
+    }
+}
+
+// A request that we run with a key, which must finish its record.
+//
+// If the request never finishes, such as when a client disconnects, so
+// axum drops the request future, or when the handler panics, then the guard
+// drops the record, so a retry can run, rather than get Conflict (409)
+// until the record expires.
+struct Pending<'a> {
+    keys: &'a IdempotencyKeys,
+    client: &'a str,
+    key: &'a str,
+    finished: bool,
+}
+
+impl<'a> Pending<'a> {
+    fn new(keys: &'a IdempotencyKeys, client: &'a str, key: &'a str) -> Self {
+        Pending { keys, client, key, finished: false }
+    }
+
+    // Finish the record, with the response to keep, if any.
+    fn finish(mut self, response: Option<SavedResponse>) {
+        self.finished = true;
+        self.keys.finish(self.client, self.key, response);
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.keys.finish(self.client, self.key, None);
+        }
+    }
+}
+
+impl Default for IdempotencyKeys {
+    fn default() -> Self {
+        IdempotencyKeys::new(DEFAULT_TTL)
+    }
+}
+
+// axum middleware that honors an `Idempotency-Key` header on an unsafe request.
+//
+// - A request without the header, or with a safe method such as GET, runs as usual.
+// - A first request runs, and we keep its response, unless it's a server error.
+// - A retry with the same method, path, and body replays the first response,
+//   with the header `Idempotent-Replayed: true`.
+// - A retry while the first request is still running gets Conflict (409).
+// - A retry after the first request failed to finish, such as when the
+//   client disconnected, runs again.
+// - A request that reuses a key with a different method, path, body, or
+//   precondition or format header, such as `If-Match`, gets Unprocessable
+//   Entity (422), because it's a client bug, not a retry.
+pub async fn idempotency(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    request: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> Result<Response, AppError> {
+    let key = match request.headers().get(IDEMPOTENCY_KEY) {
+        Some(key) if !request.method().is_safe() => key
+            .to_str()
+            .ok()
+            .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+            .ok_or_else(|| {
+                AppError::Validation(format!(
+                    "Idempotency-Key must be 1 to {} characters of visible ASCII",
+                    MAX_KEY_LEN
+                ))
+            })?
+            .to_string(),
+        _ => return Ok(next.run(request).await),
+    };
+    let client = client_id(&request);
+    let (parts, body) = request.into_parts();
+    let body = axum::body::to_bytes(body, MAX_BODY_LEN)
+        .await
+        .map_err(|err| AppError::Validation(format!("Request body is unreadable: {}", err)))?;
+    let fingerprint = fingerprint(&parts.method, &parts.uri, &parts.headers, &body);
+    match state.idempotency.begin(&client, &key, fingerprint) {
+        Begin::Run => {}
+        Begin::Replay(response) => {
+            let mut response = response.into_response();
+            response
+                .headers_mut()
+                .insert(IDEMPOTENT_REPLAYED, axum::http::HeaderValue::from_static("true"));
+            return Ok(response);
+        }
+        Begin::InProgress => {
+            return Err(AppError::Conflict(format!(
+                "A request with Idempotency-Key {} is still in progress",
+                key
+            )))
+        }
+        Begin::Mismatch => {
+            return Err(AppError::Validation(format!(
+                "Idempotency-Key {} was already used for a different request",
+                key
+            )))
+        }
+    }
+    let pending = Pending::new(&state.idempotency, &client, &key);
+    let response = next
+        .run(axum::extract::Request::from_parts(parts, axum::body::Body::from(body)))
+        .await;
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => {
+            pending.finish(None);
+            return Err(AppError::Internal(err.to_string()));
+        }
+    };
+    let saved = SavedResponse {
+        status: parts.status,
+        headers: parts.headers,
+        body,
+        error: parts.extensions.get::<AppError>().cloned(),
+    };
+    let keep = !saved.status.is_server_error();
+    pending.finish(keep.then(|| saved.clone()));
+    Ok(saved.into_response())
+}
+
+// Identify the client of a request, so each client has its own keys.
+//
+// A client with an `Authorization` header is that header, which we hash
+// so we don't keep a credential. Otherwise, a client is its IP address,
+// if the server provides it, else all clients share one identity.
+fn client_id(request: &axum::extract::Request) -> String {
+    if let Some(authorization) = request.headers().get(axum::http::header::AUTHORIZATION) {
+        let mut hasher = DefaultHasher::new();
+        authorization.as_bytes().hash(&mut hasher);
+        return format!("authorization:{:016x}", hasher.finish());
+    }
+    match request.extensions().get::<axum::extract::ConnectInfo<std::net::SocketAddr>>() {
+        Some(axum::extract::ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "anonymous".into(),
+    }
+}
+
+// Fingerprint a request by its method, path and query, the headers that
+// change what it means, and body.
+fn fingerprint(
+    method: &axum::http::Method,
+    uri: &axum::http::Uri,
+    headers: &axum::http::HeaderMap,
+    body: &[u8],
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    method.as_str().hash(&mut hasher);
+    uri.to_string().hash(&mut hasher);
+    for name in &FINGERPRINT_HEADERS {
+        let values = headers.get_all(name).iter().map(|value| value.as_bytes()).collect::<Vec<_>>();
+        values.hash(&mut hasher);
+    }
+    body.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::InMemoryBookStore;
+    use std::sync::Arc;
+
+    #[test]
+    fn records_expire() {
+        let keys = IdempotencyKeys::new(Duration::ZERO);
+        assert!(matches!(keys.begin("a", "k", 1), Begin::Run));
+        // The record has already expired, so the same key runs again.
+        assert!(matches!(keys.begin("a", "k", 2), Begin::Run));
+        let keys = IdempotencyKeys::default();
+        assert!(matches!(keys.begin("a", "k", 1), Begin::Run));
+        assert!(matches!(keys.begin("a", "k", 1), Begin::InProgress));
+        assert!(matches!(keys.begin("a", "k", 2), Begin::Mismatch));
+        // Another client has its own keys.
+        assert!(matches!(keys.begin("b", "k", 2), Begin::Run));
+        keys.finish("a", "k", None);
+        assert!(matches!(keys.begin("a", "k", 2), Begin::Run));
+    }
+
+    #[tokio::test]
+    async fn dropped_request_can_retry() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use tower::ServiceExt;
+        let state = AppState::with_books(Arc::new(InMemoryBookStore::new(vec![])), vec![]);
+        // The first request never finishes, such as for a slow network.
+        let first = Arc::new(AtomicBool::new(true));
+        let handler = async move || {
+            if first.swap(false, Ordering::SeqCst) {
+                std::future::pending::<()>().await;
+            }
+            axum::http::StatusCode::CREATED
+        };
+        let app = axum::Router::new()
+            .route("/books", axum::routing::put(handler))
+            .layer(axum::middleware::from_fn_with_state(state.clone(), idempotency))
+            .with_state(state);
+        let request = || {
+            axum::http::Request::put("/books")
+                .header(IDEMPOTENCY_KEY, "5f0c6a2e")
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+        // The client disconnects, so axum drops the request future.
+        let dropped = tokio::time::timeout(Duration::from_millis(50), app.clone().oneshot(request())).await;
+        assert!(dropped.is_err());
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::CREATED);
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.headers()[IDEMPOTENT_REPLAYED], "true");
+    }
+}
@@ -25,43 +25,206 @@ pub mod app;
 /// See file book.rs, which defines the `Book` struct.
 mod book;
 
-/// See file data.rs, which defines the DATA global variable.
+/// See file data.rs, which defines the `BookStore` data store.
 mod data;
 
+/// See file sqlite.rs, which defines the `SqliteBookStore` data store.
+mod sqlite;
+
+/// See file event_store.rs, which defines the `EventSourcedBookStore` data store.
+mod event_store;
+
+/// See file error.rs, which defines the `AppError` type.
+mod error;
+
+/// See file format.rs, which defines response formats: HTML, JSON, CSV.
+mod format;
+
+/// See file conditional.rs, which defines `ETag` and `If-Match` helpers.
+mod conditional;
+
+/// See file idempotency.rs, which defines `Idempotency-Key` handling.
+mod idempotency;
+
+/// See file book_query.rs, which defines the `BookQuery` parameters.
+mod book_query;
+
+/// See file search.rs, which defines the `SearchIndex` for books.
+mod search;
+
+/// See file trash.rs, which defines the trash retention policy.
+mod trash;
+
+/// See file revision.rs, which defines revision views and diffs.
+mod revision;
+
+/// See file changes.rs, which defines the `ChangeFeed` of book changes.
+mod changes;
+
+/// See file ws.rs, which defines WebSocket editing and `Presence`.
+mod ws;
+
+/// See file webhook.rs, which defines `Webhooks` and their deliveries.
+mod webhook;
+
+/// See file openapi.rs, which defines our OpenAPI document.
+mod openapi;
+
+/// See file schema.rs, which defines JSON Schemas and request validation.
+mod schema;
+
+/// See file html.rs, which defines `Markup`, which is HTML that is safe
+/// to send, because its values are escaped by default.
+mod html;
+
+/// See file page.rs, which defines the HTML pages, which use templates,
+/// with a base layout, and with partials such as for book rows and forms.
+mod page;
+
 /// Use tracing crates for application-level tracing output.
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+/// Command line arguments.
+///
+/// Usage: `demo-rust-axum [--database <path> | --events <path>] [--no-seed] [--trash-retention <duration>] [--allow-private-webhooks] [bind_address]`
+///
+/// - `--database <path>`: store books in a SQLite database file,
+///   which is created and migrated as needed at startup. Without
+///   this option, the app stores books in memory.
+/// - `--events <path>`: store books as events in a log file, which is
+///   replayed at startup, with a snapshot file beside it, such as
+///   "books.log.snapshot", to bound replay time.
+/// - `--no-seed`: skip the migration that seeds the demo books.
+/// - `--trash-retention <duration>`: how long a deleted book stays in
+///   the trash before it is purged, such as "7days"; defaults to 30 days.
+/// - `--allow-private-webhooks`: let a webhook deliver to a loopback or
+///   private address, such as for local development. Without this option,
+///   a webhook delivers to public addresses only.
+/// - `bind_address`: defaults to "0.0.0.0:3000".
+#[derive(Debug, PartialEq)]
+struct Args {
+    bind_address: String,
+    database: Option<String>,
+    events: Option<String>,
+    seed: bool,
+    trash_retention: std::time::Duration,
+    allow_private_webhooks: bool,
+}
+
+/// Parse command line arguments, or return an error message.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut bind_address = None;
+    let mut database = None;
+    let mut events = None;
+    let mut seed = true;
+    let mut trash_retention = crate::trash::DEFAULT_RETENTION;
+    let mut allow_private_webhooks = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--database" => match args.next() {
+                Some(path) => database = Some(path),
+                None => return Err("--database needs a path".into()),
+            },
+            "--events" => match args.next() {
+                Some(path) => events = Some(path),
+                None => return Err("--events needs a path".into()),
+            },
+            "--no-seed" => seed = false,
+            "--trash-retention" => match args.next().map(|s| humantime::parse_duration(&s)) {
+                Some(Ok(duration)) => trash_retention = duration,
+                Some(Err(err)) => return Err(format!("--trash-retention needs a duration: {}", err)),
+                None => return Err("--trash-retention needs a duration".into()),
+            },
+            "--allow-private-webhooks" => allow_private_webhooks = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option: {}", arg)),
+            _ if bind_address.is_none() => bind_address = Some(arg),
+            _ => return Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+    if database.is_some() && events.is_some() {
+        return Err("use either --database or --events, not both".into());
+    }
+    Ok(Args {
+        bind_address: bind_address.unwrap_or_else(|| "0.0.0.0:3000".into()),
+        database,
+        events,
+        seed,
+        trash_retention,
+        allow_private_webhooks,
+    })
+}
+
 /// The main function does these steps: 
 /// - Start tracing and emit a tracing event.
-/// - Get a command line argument as our bind address.
-/// - Create our application which is an axum router/.
-/// - Run our app using a hyper server.
+/// - Run our app, and report any startup error without a panic.
 #[tokio::main]  
-async fn main() {
+async fn main() -> std::process::ExitCode {
     // Start tracing and emit a tracing event.
     tracing_subscriber::registry()
         .with(tracing_subscriber::fmt::layer())
         .init();
     tracing::event!(tracing::Level::INFO, "main");
 
+    match run().await {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(err) => {
+            tracing::error!("{}", err);
+            std::process::ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run our app by doing these steps:
+/// - Get command line arguments for our bind address and data store.
+/// - Create our application which is an axum router.
+/// - Run our app using a hyper server.
+async fn run() -> Result<(), String> {
     // Get command line arguments.
-    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = parse_args(std::env::args().skip(1))?;
 
-    // Use the first arg for tokio::net::TcpListener::bind(…)  
-    let bind_address = match args.get(0) {
-        Some(x) => x.clone(),
-        None => "0.0.0.0:3000".into(),
+    // Create our data store, either SQLite, an event log, or in memory.
+    let store: std::sync::Arc<dyn crate::data::BookStore> = match (&args.database, &args.events) {
+        (Some(path), _) => std::sync::Arc::new(
+            crate::sqlite::SqliteBookStore::open(path, args.seed)
+                .map_err(|err| format!("failed to open database {}: {}", path, err))?,
+        ),
+        (_, Some(path)) => std::sync::Arc::new(
+            crate::event_store::EventSourcedBookStore::open(path, args.seed)
+                .map_err(|err| format!("failed to open event log {}: {}", path, err))?,
+        ),
+        (None, None) => std::sync::Arc::new(crate::data::InMemoryBookStore::new(
+            if args.seed { crate::data::demo_books() } else { vec![] },
+        )),
     };
 
+    // Purge books that have been in the trash for too long.
+    crate::trash::spawn_retention(store.clone(), args.trash_retention);
+
     // Create our application which is an axum router.
-    let app = crate::app::app();
+    let mut state = crate::app::AppState::new(store)
+        .await
+        .map_err(|err| format!("failed to index books: {}", err))?;
+    if args.allow_private_webhooks {
+        state.webhooks = std::sync::Arc::new(crate::webhook::Webhooks::new(crate::webhook::DEFAULT_BACKOFF, true));
+    }
+    let shutdown = state.shutdown.clone();
+    let app = crate::app::app_with_state(state);
 
-    // Run our app using a hyper server.
-    let listener = tokio::net::TcpListener::bind(bind_address).await.unwrap();
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+    // Run our app using a hyper server. The app gets each client's
+    // address, so it can tell clients apart, such as for idempotency keys.
+    // On shutdown, we tell the app, so it can close each WebSocket, which
+    // the server would otherwise wait for.
+    let listener = tokio::net::TcpListener::bind(&args.bind_address)
+        .await
+        .map_err(|err| format!("failed to bind {}: {}", args.bind_address, err))?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            shutdown.send_replace(true);
+        })
         .await
-        .unwrap();
+        .map_err(|err| format!("failed to serve: {}", err))
 }
 
 /// Shutdown signal to run axum with graceful shutdown when
@@ -89,3 +252,29 @@ pub async fn shutdown_signal() {
         _ = terminate => {},
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_args_with_database() {
+        let args = parse_args(["--database", "books.db", "--no-seed", "127.0.0.1:8080"].map(String::from)).unwrap();
+        assert_eq!(args, Args {
+            bind_address: "127.0.0.1:8080".into(),
+            database: Some("books.db".into()),
+            events: None,
+            seed: false,
+            trash_retention: crate::trash::DEFAULT_RETENTION,
+            allow_private_webhooks: false,
+        });
+        assert!(parse_args(["--database"].map(String::from)).is_err());
+        let args = parse_args(["--events", "books.log"].map(String::from)).unwrap();
+        assert_eq!(args.events, Some("books.log".into()));
+        assert!(parse_args(["--database", "books.db", "--events", "books.log"].map(String::from)).is_err());
+        let args = parse_args(["--trash-retention", "7days"].map(String::from)).unwrap();
+        assert_eq!(args.trash_retention, std::time::Duration::from_secs(7 * 24 * 60 * 60));
+        assert!(parse_args(["--trash-retention", "soon"].map(String::from)).is_err());
+        assert!(parse_args(["--allow-private-webhooks"].map(String::from)).unwrap().allow_private_webhooks);
+    }
+}
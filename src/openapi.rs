@@ -0,0 +1,106 @@
+// Use the utoipa OpenApi derive, which generates an OpenAPI 3.1 document
+// from our handlers' `#[utoipa::path]` attributes and our types' schemas.
+use utoipa::OpenApi;
+
+// Use the app handlers and their request and response types.
+use crate::app::*;
+use crate::book::{Book, NewBook};
+use crate::changes::{Change, ChangeKind};
+use crate::data::{BatchOp, RevisionAction};
+use crate::error::{ErrorBody, FieldError};
+use crate::revision::{FieldChange, RevisionView};
+use crate::search::SearchHit;
+use crate::trash::TrashedBook;
+use crate::webhook::{Delivery, DeliveryState, NewWebhook, Webhook};
+
+// Our OpenAPI document, which the app serves at "GET /openapi.json",
+// and which the API explorer at "GET /docs/api" shows.
+//
+// Each route in file app.rs has a handler with a `#[utoipa::path]`
+// attribute, with its parameters, request body, and responses, and the
+// handler's doc comment is its description. When you add a route, add
+// its handler here too; the test `openapi_paths_are_routes` in file app.rs
+// checks that each path here is a route.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "demo-rust-axum",
+        description = "Demo of Rust and axum web framework, with RESTful routes for books."
+    ),
+    paths(
+        hello,
+        string_html,
+        file_html,
+        status,
+        epoch,
+        uptime,
+        count,
+        request_uri,
+        demo_html,
+        demo_png,
+        get_demo_json,
+        put_demo_json,
+        get_foo,
+        put_foo,
+        patch_foo,
+        post_foo,
+        delete_foo,
+        get_items,
+        get_items_id,
+        get_books,
+        put_books,
+        get_books_search,
+        get_books_events,
+        get_ws,
+        post_books_batch,
+        get_books_trash,
+        delete_books_trash,
+        delete_books_trash_id,
+        get_books_id,
+        put_books_id,
+        patch_books_id,
+        delete_books_id,
+        get_books_id_form,
+        post_books_id_form,
+        post_books_id_restore,
+        get_books_id_revisions,
+        get_books_id_revisions_diff,
+        get_books_id_revisions_n,
+        post_books_id_revisions_n_revert,
+        get_schemas_name,
+        post_admin_rebuild,
+        get_admin_webhooks,
+        post_admin_webhooks,
+        get_admin_webhooks_id,
+        delete_admin_webhooks_id,
+        get_admin_webhooks_id_deliveries,
+    ),
+    components(schemas(
+        Book,
+        NewBook,
+        BookForm,
+        BatchRequest,
+        BatchMode,
+        BatchOp,
+        TrashedBook,
+        SearchHit,
+        RevisionView,
+        RevisionAction,
+        FieldChange,
+        Change,
+        ChangeKind,
+        NewWebhook,
+        Webhook,
+        Delivery,
+        DeliveryState,
+        ErrorBody,
+        FieldError,
+    )),
+    tags(
+        (name = "demo", description = "Demo handlers that show axum capabilities"),
+        (name = "books", description = "Books, by using RESTful routes and a data store"),
+        (name = "revisions", description = "The revision history of each book"),
+        (name = "admin", description = "Administration: projections and webhooks"),
+    )
+)]
+pub struct ApiDoc;
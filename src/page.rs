@@ -0,0 +1,121 @@
+// Use askama templates, which are files in the directory `templates`.
+//
+// Each template is checked when we compile, such as for a field that
+// doesn't exist, and each template escapes each value by default, such
+// as "<" as "&lt;". Each page extends the base layout `layout.html`,
+// which has the shared head and navigation, then fills its content
+// block, such as by including a partial from `templates/partials`.
+use askama::Template;
+
+// Use the Book struct, which a page shows by using its Display.
+use crate::book::Book;
+
+// Use the FieldError struct, which a form shows next to its field.
+use crate::error::FieldError;
+
+// Use the Markup struct, which a page shows as is, because it's safe.
+use crate::html::Markup;
+
+// A page of books, one per row, and links to the previous and next pages.
+//
+// See file `templates/books.html` and `templates/partials/book.html`.
+#[derive(Template)]
+#[template(path = "books.html")]
+pub struct BooksPage<'a> {
+    pub title: &'a str,
+    pub books: &'a [Book],
+    pub prev: Option<String>,
+    pub next: Option<String>,
+}
+
+impl<'a> BooksPage<'a> {
+    // Create a page of books, without links to other pages.
+    pub fn new(title: &'a str, books: &'a [Book]) -> Self {
+        BooksPage { title, books, prev: None, next: None }
+    }
+}
+
+// A page with a form to edit a book.
+//
+// See file `templates/book_form.html` and `templates/partials/book_form.html`.
+#[derive(Template)]
+#[template(path = "book_form.html")]
+pub struct BookFormPage<'a> {
+    pub title: String,
+    pub id: u32,
+    pub form: FormView<'a>,
+}
+
+// The text of each form field, such as from the book, or as a user
+// submitted it, and the errors, if any, for the fields.
+pub struct FormView<'a> {
+    pub fields: &'a [(String, String)],
+    pub errors: &'a [FieldError],
+}
+
+impl FormView<'_> {
+    // The text of a field, or blank if the field is missing.
+    pub fn value(&self, name: &str) -> &str {
+        self.fields.iter().find(|(field, _)| field == name).map_or("", |(_, value)| value)
+    }
+
+    // The errors for a field, which the form shows next to the field.
+    pub fn errors(&self, name: &str) -> Vec<&FieldError> {
+        self.errors.iter().filter(|error| error.field == name).collect()
+    }
+}
+
+// A page with a table, such as the revisions of a book.
+//
+// See file `templates/table.html` and `templates/partials/table.html`.
+#[derive(Template)]
+#[template(path = "table.html")]
+pub struct TablePage {
+    pub title: String,
+    pub table: Table,
+}
+
+// A table, with a header row, and rows of text.
+pub struct Table {
+    pub head: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+// A page with one message, such as what a change did, or an error.
+//
+// See file `templates/message.html`.
+#[derive(Template)]
+#[template(path = "message.html")]
+pub struct MessagePage<'a> {
+    pub title: &'a str,
+    pub message: &'a str,
+}
+
+// A page with markup that we wrote, such as a file of HTML, which the
+// page shows as is, because markup is already safe; see file html.rs.
+//
+// See file `templates/markup.html`.
+#[derive(Template)]
+#[template(path = "markup.html")]
+pub struct MarkupPage<'a> {
+    pub title: &'a str,
+    pub content: Markup,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_escape_values() {
+        let books = [Book { id: 1, title: "<script>".into(), author: "\"O'Brien\" & co".into() }];
+        let html = BooksPage::new("<i>", &books).render().unwrap();
+        assert!(html.contains("<title>&#60;i&#62; - Demo Rust Axum</title>"), "{}", html);
+        assert!(html.contains("<main>\n<p>&#60;script&#62; by &#34;O&#39;Brien&#34; &#38; co</p>\n</main>"), "{}", html);
+        let html = MessagePage { title: "Error", message: "<b>" }.render().unwrap();
+        assert!(html.contains("<main>\n<p>&#60;b&#62;</p>\n</main>"), "{}", html);
+        let html = MarkupPage { title: "<i>", content: Markup::raw("<p>Hi</p>\n") }.render().unwrap();
+        assert!(html.contains("<title>&#60;i&#62; - Demo Rust Axum</title>"), "{}", html);
+        assert!(html.contains("<main>\n<p>Hi</p>\n</main>"), "{}", html);
+    }
+}
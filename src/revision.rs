@@ -0,0 +1,199 @@
+// Use Serialize to convert revisions and diffs into response JSON.
+use serde::Serialize;
+
+// Use ToSchema to describe revisions and diffs in our OpenAPI document.
+use utoipa::ToSchema;
+
+// Use the Book struct, and the Revision struct.
+use crate::book::Book;
+use crate::data::{Revision, RevisionAction};
+
+// Use the Table struct, which a table page renders.
+use crate::page::Table;
+
+// The maximum length of a `From` header that we record as who made a change.
+const MAX_WHO_LEN: usize = 255;
+
+// axum extractor for who makes a change, which we record in its revision.
+//
+// A client may say who its user is by using the HTTP `From` header,
+// which is the user's email address. This app has no authentication, so
+// the header is unverified and self-reported: any client can claim any
+// name. So we also record the client's IP address, if the server provides
+// it, such as "librarian@example.com via 203.0.113.7". Without the header,
+// who is the IP address, else "anonymous".
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Who(pub String);
+
+impl<S: Send + Sync> axum::extract::FromRequestParts<S> for Who {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let from = parts
+            .headers
+            .get(axum::http::header::FROM)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|from| !from.is_empty() && from.len() <= MAX_WHO_LEN);
+        let peer = parts
+            .extensions
+            .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+            .map(|axum::extract::ConnectInfo(addr)| addr.ip().to_string());
+        Ok(Who(match (from, peer) {
+            (Some(from), Some(peer)) => format!("{} via {}", from, peer),
+            (Some(from), None) => from.to_string(),
+            (None, Some(peer)) => peer,
+            (None, None) => "anonymous".into(),
+        }))
+    }
+}
+
+// One revision as response JSON, with its time in RFC 3339 format.
+#[derive(Debug, Serialize, ToSchema, Clone, Eq, PartialEq)]
+pub struct RevisionView {
+    pub number: u64,
+    pub action: RevisionAction,
+    pub who: String,
+    pub when: String,
+    pub old: Option<Book>,
+    pub new: Option<Book>,
+}
+
+impl From<Revision> for RevisionView {
+    fn from(revision: Revision) -> Self {
+        RevisionView {
+            number: revision.number,
+            action: revision.action,
+            who: revision.who,
+            when: humantime::format_rfc3339_seconds(revision.when).to_string(),
+            old: revision.old,
+            new: revision.new,
+        }
+    }
+}
+
+// One field that differs between two revisions. A field has no value
+// in a revision where the book is in the trash.
+#[derive(Debug, Serialize, ToSchema, Clone, Eq, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+// Compare two values of a book, field by field, and return each change.
+pub fn diff(old: Option<&Book>, new: Option<&Book>) -> Vec<FieldChange> {
+    let values = |book: Option<&Book>| book.map(|book| [book.title.clone(), book.author.clone()]);
+    let (old, new) = (values(old), values(new));
+    ["title", "author"]
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, field)| {
+            let old = old.as_ref().map(|values| values[i].clone());
+            let new = new.as_ref().map(|values| values[i].clone());
+            (old != new).then_some(FieldChange { field, old, new })
+        })
+        .collect()
+}
+
+// Show revisions as a table, with a header row.
+pub fn revisions_table(revisions: &[Revision]) -> Table {
+    let value = |book: &Option<Book>| book.as_ref().map(Book::to_string).unwrap_or_default();
+    let head = vec!["Number", "Action", "Who", "When", "Old", "New"];
+    let rows = revisions.iter().map(|revision| {
+        let view = RevisionView::from(revision.clone());
+        vec![
+            view.number.to_string(),
+            view.action.as_str().to_string(),
+            view.who,
+            view.when,
+            value(&view.old),
+            value(&view.new),
+        ]
+    });
+    Table { head, rows: rows.collect() }
+}
+
+// Show field changes as a table, with a header row.
+pub fn diff_table(changes: &[FieldChange]) -> Table {
+    let head = vec!["Field", "Old", "New"];
+    let rows = changes.iter().map(|change| {
+        vec![
+            change.field.to_string(),
+            change.old.clone().unwrap_or_default(),
+            change.new.clone().unwrap_or_default(),
+        ]
+    });
+    Table { head, rows: rows.collect() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_fields() {
+        let antigone = Book { id: 1, title: "Antigone".into(), author: "Sophocles".into() };
+        let elektra = Book { title: "Elektra".into(), ..antigone.clone() };
+        assert_eq!(diff(Some(&antigone), Some(&antigone)), vec![]);
+        assert_eq!(
+            diff(Some(&antigone), Some(&elektra)),
+            vec![FieldChange { field: "title", old: Some("Antigone".into()), new: Some("Elektra".into()) }]
+        );
+        // A book in the trash has no values.
+        let changes = diff(Some(&antigone), None);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1], FieldChange { field: "author", old: Some("Sophocles".into()), new: None });
+    }
+
+    #[test]
+    fn tables() {
+        let antigone = Book { id: 1, title: "Antigone".into(), author: "Sophocles".into() };
+        let revision = Revision {
+            book_id: 1,
+            number: 2,
+            action: RevisionAction::Delete,
+            who: "librarian@example.com".into(),
+            when: std::time::SystemTime::UNIX_EPOCH,
+            old: Some(antigone.clone()),
+            new: None,
+        };
+        let table = revisions_table(&[revision]);
+        assert_eq!(table.head, ["Number", "Action", "Who", "When", "Old", "New"]);
+        assert_eq!(
+            table.rows,
+            [["2", "delete", "librarian@example.com", "1970-01-01T00:00:00Z", "Antigone by Sophocles", ""]]
+        );
+        let table = diff_table(&diff(Some(&antigone), None));
+        assert_eq!(table.head, ["Field", "Old", "New"]);
+        assert_eq!(table.rows, [["title", "Antigone", ""], ["author", "Sophocles", ""]]);
+    }
+
+    #[tokio::test]
+    async fn who_is_from_header() {
+        use axum::extract::FromRequestParts;
+        let who = async |from: Option<&str>, peer: Option<&str>| {
+            let mut request = axum::http::Request::builder();
+            if let Some(from) = from {
+                request = request.header(axum::http::header::FROM, from);
+            }
+            if let Some(peer) = peer {
+                let addr: std::net::SocketAddr = peer.parse().unwrap();
+                request = request.extension(axum::extract::ConnectInfo(addr));
+            }
+            let (mut parts, _) = request.body(()).unwrap().into_parts();
+            Who::from_request_parts(&mut parts, &()).await.unwrap().0
+        };
+        assert_eq!(who(Some(" librarian@example.com "), None).await, "librarian@example.com");
+        assert_eq!(who(None, None).await, "anonymous");
+        assert_eq!(who(Some(" "), None).await, "anonymous");
+        assert_eq!(who(Some(&"x".repeat(MAX_WHO_LEN + 1)), None).await, "anonymous");
+        // The header is self-reported, so we record the client's address too.
+        let peer = Some("203.0.113.7:4321");
+        assert_eq!(who(Some("librarian@example.com"), peer).await, "librarian@example.com via 203.0.113.7");
+        assert_eq!(who(None, peer).await, "203.0.113.7");
+    }
+}
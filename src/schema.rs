@@ -0,0 +1,421 @@
+// Use Cow for a schema that we either have already, or create as needed.
+use std::borrow::Cow;
+
+// Use HashMap for our published schemas, by name.
+use std::collections::HashMap;
+
+// Use LazyLock to create our published schemas once.
+use std::sync::LazyLock;
+
+// Use axum capabilities for extractors.
+use axum::response::{IntoResponse, Response};
+
+// Use DeserializeOwned to convert a valid request body into its type.
+use serde::de::DeserializeOwned;
+
+// Use Regex to check a string against a schema's "pattern".
+use regex::Regex;
+
+// Use Serde JSON for schemas, and for request bodies as we validate them.
+use serde_json::{Map, Value};
+
+// Use ToSchema to get the OpenAPI schema of a type, which is JSON Schema.
+use utoipa::ToSchema;
+
+// Use the request body types that we publish.
+use crate::app::{BatchRequest, BookForm};
+use crate::book::{Book, NewBook};
+
+// Use the AppError type, for a request body with invalid fields.
+use crate::error::{AppError, FieldError};
+
+// The JSON Schema dialect of our schemas, which is the same as OpenAPI 3.1.
+pub const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+// The JSON Schema of each request body type, by name, such as "Book".
+// The route "GET /schemas/{name}.json" publishes these, and the extractors
+// `ValidJson` and the function `form_value` help validate request bodies
+// against them.
+//
+// Each schema comes from its type's `#[schema(...)]` attributes, such as
+// `min_length`, so the constraints that we publish are the constraints
+// that we check, and the OpenAPI document has them too.
+static SCHEMAS: LazyLock<HashMap<String, Value>> = LazyLock::new(|| {
+    [document::<Book>(), document::<NewBook>(), document::<BookForm>(), document::<BatchRequest>()]
+        .into_iter()
+        .map(|document| (document["title"].as_str().unwrap_or_default().to_string(), document))
+        .collect()
+});
+
+// The regular expression of each "pattern" in our published schemas, by
+// pattern, so we compile each pattern once, rather than per validation.
+// An invalid pattern is a bug in a type's attributes, so it panics here,
+// and a test forces this, rather than letting every string pass.
+static PATTERNS: LazyLock<HashMap<String, Regex>> = LazyLock::new(|| {
+    let mut patterns = HashMap::new();
+    SCHEMAS.values().for_each(|document| collect_patterns(document, &mut patterns));
+    patterns
+});
+
+// Compile each "pattern" in a schema, at any depth.
+fn collect_patterns(schema: &Value, patterns: &mut HashMap<String, Regex>) {
+    match schema {
+        Value::Object(object) => {
+            if let Some(Value::String(pattern)) = object.get("pattern") {
+                patterns.entry(pattern.clone()).or_insert_with(|| compile(pattern));
+            }
+            object.values().for_each(|schema| collect_patterns(schema, patterns));
+        }
+        Value::Array(array) => array.iter().for_each(|schema| collect_patterns(schema, patterns)),
+        _ => {}
+    }
+}
+
+// Compile a pattern, or panic, because an invalid pattern is a bug.
+fn compile(pattern: &str) -> Regex {
+    Regex::new(pattern).unwrap_or_else(|err| panic!("invalid schema pattern \"{}\": {}", pattern, err))
+}
+
+// Get the regular expression of a pattern: the compiled one, if a published
+// schema has the pattern, or else compile it now, such as for a type that
+// we don't publish.
+fn regex(pattern: &str) -> Cow<'static, Regex> {
+    match PATTERNS.get(pattern) {
+        Some(regex) => Cow::Borrowed(regex),
+        None => Cow::Owned(compile(pattern)),
+    }
+}
+
+// Get the JSON Schema of a request body type by name, such as "Book".
+pub fn schema(name: &str) -> Option<&'static Value> {
+    SCHEMAS.get(name)
+}
+
+// The names of our published schemas, in order.
+pub fn schema_names() -> Vec<&'static str> {
+    let mut names: Vec<&str> = SCHEMAS.keys().map(String::as_str).collect();
+    names.sort();
+    names
+}
+
+// Create the JSON Schema document of a type from its OpenAPI schema, with
+// each schema that it refers to in "$defs", so the document stands alone.
+fn document<T: ToSchema>() -> Value {
+    let mut defs = Vec::new();
+    T::schemas(&mut defs);
+    let defs: Map<String, Value> = defs
+        .into_iter()
+        .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap_or_default()))
+        .collect();
+    let mut document = Map::new();
+    document.insert("$schema".into(), DIALECT.into());
+    document.insert("$id".into(), format!("/schemas/{}.json", T::name()).into());
+    document.insert("title".into(), T::name().into());
+    if let Value::Object(schema) = serde_json::to_value(T::schema()).unwrap_or_default() {
+        document.extend(schema);
+    }
+    if !defs.is_empty() {
+        document.insert("$defs".into(), Value::Object(defs));
+    }
+    let mut document = Value::Object(document);
+    rewrite_refs(&mut document);
+    document
+}
+
+// Rewrite each OpenAPI reference, such as "#/components/schemas/Book",
+// into a reference within the document, such as "#/$defs/Book".
+fn rewrite_refs(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::String(reference)) = object.get_mut("$ref")
+                && let Some(name) = reference.strip_prefix("#/components/schemas/")
+            {
+                *reference = format!("#/$defs/{}", name);
+            }
+            object.values_mut().for_each(rewrite_refs);
+        }
+        Value::Array(array) => array.iter_mut().for_each(rewrite_refs),
+        _ => {}
+    }
+}
+
+// Validate a value against the schema of a type, and return every field
+// error at once, rather than only the first.
+pub fn validate<T: ToSchema>(value: &Value) -> Result<(), AppError> {
+    let errors = field_errors::<T>(value);
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(AppError::invalid_fields(errors)),
+    }
+}
+
+// Check a value against the schema of a type, and return each field error.
+pub fn field_errors<T: ToSchema>(value: &Value) -> Vec<FieldError> {
+    let document = match SCHEMAS.get(T::name().as_ref()) {
+        Some(document) => Cow::Borrowed(document),
+        None => Cow::Owned(document::<T>()),
+    };
+    let mut errors = Vec::new();
+    check(&document, &document, value, "", &mut errors);
+    errors
+}
+
+// Validate a value, then convert it into its type.
+fn from_value<T: ToSchema + DeserializeOwned>(value: Value) -> Result<T, AppError> {
+    validate::<T>(&value)?;
+    serde_json::from_value(value).map_err(|err| AppError::Validation(err.to_string()))
+}
+
+// Check a value against a schema, and add each error, by using a subset
+// of JSON Schema: "$ref", "allOf", "oneOf", "type", "enum", "required",
+// "properties", "items", "minItems", "maxItems", "minLength", "maxLength",
+// "pattern", "minimum", and "maximum". These are what our types use.
+fn check(root: &Value, schema: &Value, value: &Value, path: &str, errors: &mut Vec<FieldError>) {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        if let Some(schema) = reference.strip_prefix("#/$defs/").and_then(|name| root["$defs"].get(name)) {
+            check(root, schema, value, path, errors);
+        }
+        return;
+    }
+    for schema in schema["allOf"].as_array().into_iter().flatten() {
+        check(root, schema, value, path, errors);
+    }
+    // A value that matches no alternative gets the errors of the closest
+    // one, preferring an alternative with the value's tag, if any.
+    if let Some(alternatives) = schema["oneOf"].as_array() {
+        let tagged: Vec<&Value> = alternatives.iter().filter(|schema| has_tag(schema, value)).collect();
+        let candidates = match tagged.is_empty() {
+            true => alternatives.iter().collect(),
+            false => tagged,
+        };
+        let closest = candidates
+            .into_iter()
+            .map(|schema| {
+                let mut errors = Vec::new();
+                check(root, schema, value, path, &mut errors);
+                errors
+            })
+            .min_by_key(Vec::len);
+        errors.extend(closest.into_iter().flatten());
+    }
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(name) => vec![name],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    };
+    if !types.is_empty() && !types.iter().any(|name| is_type(value, name)) {
+        let names = types.iter().map(|name| article(name)).collect::<Vec<_>>().join(" or ");
+        errors.push(field_error(path, format!("must be {}", names)));
+        return;
+    }
+    if let Some(values) = schema["enum"].as_array()
+        && !values.contains(value)
+    {
+        let names = values.iter().map(Value::to_string).collect::<Vec<_>>().join(", ");
+        errors.push(field_error(path, format!("must be one of: {}", names)));
+    }
+    match value {
+        // A string with the wrong length gets only that error, because an
+        // empty string, for one, would fail most patterns too.
+        Value::String(text) => {
+            let len = text.chars().count() as u64;
+            match (schema["minLength"].as_u64(), schema["maxLength"].as_u64()) {
+                (Some(1), _) if len == 0 => errors.push(field_error(path, "must not be empty".into())),
+                (Some(min), _) if len < min => {
+                    errors.push(field_error(path, format!("must have at least {} characters", min)))
+                }
+                (_, Some(max)) if len > max => {
+                    errors.push(field_error(path, format!("must have at most {} characters", max)))
+                }
+                // A pattern is hard to read, so a blank string that fails its
+                // pattern gets a plain message.
+                _ => {
+                    if let Some(pattern) = schema["pattern"].as_str()
+                        && !regex(pattern).is_match(text)
+                    {
+                        let message = match text.trim().is_empty() {
+                            true => "must not be blank".into(),
+                            false => format!("must match pattern \"{}\"", pattern),
+                        };
+                        errors.push(field_error(path, message));
+                    }
+                }
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            if let Some(min) = schema["minimum"].as_f64()
+                && number < min
+            {
+                errors.push(field_error(path, format!("must be at least {}", min)));
+            }
+            if let Some(max) = schema["maximum"].as_f64()
+                && number > max
+            {
+                errors.push(field_error(path, format!("must be at most {}", max)));
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema["minItems"].as_u64()
+                && (items.len() as u64) < min
+            {
+                errors.push(field_error(path, format!("must have at least {} items", min)));
+            }
+            if let Some(max) = schema["maxItems"].as_u64()
+                && (items.len() as u64) > max
+            {
+                errors.push(field_error(path, format!("must have at most {} items", max)));
+            }
+            if let Some(schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(root, schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        Value::Object(object) => {
+            for name in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    errors.push(field_error(&join(path, name), "is required".into()));
+                }
+            }
+            for (name, schema) in schema["properties"].as_object().into_iter().flatten() {
+                if let Some(value) = object.get(name) {
+                    check(root, schema, value, &join(path, name), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Does a value have the tag of a tagged alternative, such as "op" for a
+// batch operation? A tag is a property that allows only one value, such
+// as `{"op": {"enum": ["create"]}}`.
+fn has_tag(schema: &Value, value: &Value) -> bool {
+    schema["properties"].as_object().into_iter().flatten().any(|(name, property)| {
+        matches!(property["enum"].as_array(), Some(values) if values.len() == 1 && value.get(name) == values.first())
+    })
+}
+
+// Is a value of a JSON Schema type, such as "string"?
+fn is_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+// A JSON Schema type name with its article, such as "a string".
+fn article(name: &str) -> String {
+    match name {
+        "null" => name.into(),
+        "integer" | "object" | "array" => format!("an {}", name),
+        _ => format!("a {}", name),
+    }
+}
+
+// Join a field path and a field name, such as "operations[0].book".
+fn join(path: &str, name: &str) -> String {
+    match path {
+        "" => name.into(),
+        _ => format!("{}.{}", path, name),
+    }
+}
+
+// Create a field error. An error for the whole body has the field "body".
+fn field_error(path: &str, message: String) -> FieldError {
+    let field = match path {
+        "" => "body".into(),
+        _ => path.into(),
+    };
+    FieldError { field, message }
+}
+
+// axum extractor for a JSON request body that is valid for its type's
+// schema. A body that isn't JSON gets the same rejection as from
+// `axum::extract::Json`, and a body with invalid fields gets an error
+// with every field error.
+#[derive(Debug)]
+pub struct ValidJson<T>(pub T);
+
+impl<S: Send + Sync, T: ToSchema + DeserializeOwned> axum::extract::FromRequest<S> for ValidJson<T> {
+    type Rejection = Response;
+
+    async fn from_request(request: axum::extract::Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Json(value) = axum::extract::Json::<Value>::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        from_value(value).map(ValidJson).map_err(IntoResponse::into_response)
+    }
+}
+
+// Convert the fields of a form into a value that we can validate for a
+// type's schema. A form field is text, so a field whose schema is an
+// integer becomes a number, if it is one, and any other field is a string.
+pub fn form_value<T: ToSchema>(fields: &[(String, String)]) -> Value {
+    let schema = serde_json::to_value(T::schema()).unwrap_or_default();
+    let value = fields
+        .iter()
+        .map(|(name, text)| {
+            let integer = match &schema["properties"][name]["type"] {
+                Value::String(name) => name == "integer",
+                Value::Array(names) => names.iter().any(|name| name == "integer"),
+                _ => false,
+            };
+            let value = match text.parse::<i64>() {
+                Ok(number) if integer => Value::from(number),
+                _ => Value::String(text.clone()),
+            };
+            (name.clone(), value)
+        })
+        .collect::<Map<String, Value>>();
+    Value::Object(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn validate_reports_every_field_error() {
+        validate::<Book>(&json!({"id": 1, "title": "Antigone", "author": "Sophocles"})).unwrap();
+        let err = validate::<Book>(&json!({"id": "1", "title": " ", "author": "a".repeat(256)})).unwrap_err();
+        let mut fields: Vec<&str> = err.field_errors().iter().map(|error| error.field.as_str()).collect();
+        fields.sort();
+        assert_eq!(fields, ["author", "id", "title"]);
+        let err = validate::<Book>(&json!({"title": "Line\nbreak"})).unwrap_err();
+        assert_eq!(
+            err.field_errors().iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "id is required".to_string(),
+                "author is required".to_string(),
+                format!("title must match pattern \"{}\"", schema("Book").unwrap()["properties"]["title"]["pattern"].as_str().unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn patterns_compile() {
+        let pattern = crate::book::TEXT_PATTERN;
+        assert_eq!(schema("Book").unwrap()["properties"]["title"]["pattern"], pattern);
+        assert!(PATTERNS.contains_key(pattern));
+        assert!(std::panic::catch_unwind(|| compile("[")).is_err());
+    }
+
+    #[test]
+    fn validate_checks_nested_fields() {
+        let batch = json!({"operations": [
+            {"op": "create", "book": {"title": "Elektra", "author": "Sophocles"}},
+            {"op": "update", "book": {"id": 1, "title": ""}},
+        ]});
+        let err = validate::<BatchRequest>(&batch).unwrap_err();
+        assert_eq!(err.message(), "Invalid fields: operations[1].book.author is required; operations[1].book.title must not be empty");
+    }
+}
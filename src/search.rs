@@ -0,0 +1,269 @@
+// Use HashMap for the inverted index, from term to book id to term counts.
+use std::collections::HashMap;
+
+// Use RwLock for thread-safe access to the index. We never hold the lock
+// across an await, so a std lock is fine, and the index has a sync API.
+use std::sync::{PoisonError, RwLock};
+
+// Use Serialize to convert a search hit into response JSON.
+use serde::Serialize;
+
+// Use ToSchema to describe a search hit in our OpenAPI document.
+use utoipa::ToSchema;
+
+// Use the Book struct.
+use crate::book::Book;
+
+// The fields that we index, with a weight for ranking. A match in the
+// title counts for more than a match in the author.
+const FIELDS: usize = 2;
+const FIELD_WEIGHTS: [f64; FIELDS] = [2.0, 1.0];
+
+// A fuzzy match, which tolerates a typo, counts for less than an exact match.
+const FUZZY_WEIGHT: f64 = 0.5;
+
+// A phrase match counts for more than its terms would count separately.
+const PHRASE_WEIGHT: f64 = 1.5;
+
+// Search index for books, which is an in-process inverted index.
+//
+// The index maps each term to the books that contain it, with counts per
+// field. A term is a lowercase word that we stem, so that "editions" and
+// "edition" are the same term. The index also keeps the terms of each
+// book in order, so it can match a phrase query.
+//
+// The index is separate from the data store, so a handler that changes
+// a book must update the index too; see function `index` and `remove`.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    inner: RwLock<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    // Book id => terms of each field, in order.
+    docs: HashMap<u32, [Vec<String>; FIELDS]>,
+    // Term => book id => count of the term in each field.
+    postings: HashMap<String, HashMap<u32, [u32; FIELDS]>>,
+}
+
+// One search result: a book and its relevance score.
+#[derive(Debug, Serialize, ToSchema, Clone, PartialEq)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub book: Book,
+    pub score: f64,
+}
+
+// A parsed query: loose terms, and phrases which are quoted terms.
+#[derive(Debug, Default, PartialEq)]
+struct Query {
+    terms: Vec<String>,
+    phrases: Vec<Vec<String>>,
+}
+
+impl SearchIndex {
+    // Create a search index that contains the given books.
+    pub fn from_books(books: impl IntoIterator<Item = Book>) -> Self {
+        let index = SearchIndex::default();
+        for book in books {
+            index.index(&book);
+        }
+        index
+    }
+
+    // Replace everything in the index with the given books, such as after
+    // the data store rebuilds its view of the books.
+    pub fn rebuild(&self, books: impl IntoIterator<Item = Book>) {
+        let index = SearchIndex::from_books(books);
+        let inner = index.inner.into_inner().unwrap_or_else(PoisonError::into_inner);
+        *self.inner.write().unwrap_or_else(PoisonError::into_inner) = inner;
+    }
+
+    // Add a book to the index, or replace it if it is already indexed.
+    pub fn index(&self, book: &Book) {
+        let mut inner = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        inner.remove(book.id);
+        let fields = [tokenize(&book.title), tokenize(&book.author)];
+        for (field, terms) in fields.iter().enumerate() {
+            for term in terms {
+                inner
+                    .postings
+                    .entry(term.clone())
+                    .or_default()
+                    .entry(book.id)
+                    .or_default()[field] += 1;
+            }
+        }
+        inner.docs.insert(book.id, fields);
+    }
+
+    // Remove a book from the index, if it is indexed.
+    pub fn remove(&self, id: u32) {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner).remove(id);
+    }
+
+    // Search for books, and return their ids and scores, best first.
+    //
+    // Every loose term must match, either exactly after stemming, or
+    // with a typo if no exact match exists. Every quoted phrase must
+    // match its terms in order, within one field.
+    pub fn search(&self, q: &str) -> Vec<(u32, f64)> {
+        let query = parse(q);
+        if query.terms.is_empty() && query.phrases.is_empty() {
+            return vec![];
+        }
+        let inner = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        let mut scores: Option<HashMap<u32, f64>> = None;
+        let mut intersect = |term_scores: HashMap<u32, f64>| {
+            scores = Some(match scores.take() {
+                None => term_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(id, score)| term_scores.get(&id).map(|s| (id, score + s)))
+                    .collect(),
+            });
+        };
+        for term in &query.terms {
+            intersect(inner.term_scores(term));
+        }
+        for phrase in &query.phrases {
+            intersect(inner.phrase_scores(phrase));
+        }
+        let mut hits: Vec<(u32, f64)> = scores.unwrap_or_default().into_iter().collect();
+        hits.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        hits
+    }
+}
+
+impl Inner {
+    fn remove(&mut self, id: u32) {
+        if let Some(fields) = self.docs.remove(&id) {
+            for term in fields.iter().flatten() {
+                if let Some(books) = self.postings.get_mut(term) {
+                    books.remove(&id);
+                    if books.is_empty() {
+                        self.postings.remove(term);
+                    }
+                }
+            }
+        }
+    }
+
+    // Inverse document frequency: a rare term counts for more.
+    fn idf(&self, term: &str) -> f64 {
+        let df = self.postings.get(term).map_or(0, HashMap::len) as f64;
+        (1.0 + self.docs.len() as f64 / df.max(1.0)).ln()
+    }
+
+    // Score each book that matches a term, exactly or else fuzzily.
+    fn term_scores(&self, term: &str) -> HashMap<u32, f64> {
+        let candidates: Vec<(&String, f64)> = match self.postings.get_key_value(term) {
+            Some((term, _)) => vec![(term, 1.0)],
+            None => self
+                .postings
+                .keys()
+                .filter(|candidate| is_typo(term, candidate))
+                .map(|candidate| (candidate, FUZZY_WEIGHT))
+                .collect(),
+        };
+        let mut scores = HashMap::new();
+        for (candidate, weight) in candidates {
+            let idf = self.idf(candidate);
+            for (id, counts) in &self.postings[candidate] {
+                let tf: f64 = counts.iter().zip(FIELD_WEIGHTS).map(|(&n, w)| n as f64 * w).sum();
+                *scores.entry(*id).or_insert(0.0) += weight * idf * tf;
+            }
+        }
+        scores
+    }
+
+    // Score each book that matches a phrase, in order, within one field.
+    fn phrase_scores(&self, phrase: &[String]) -> HashMap<u32, f64> {
+        let idf: f64 = phrase.iter().map(|term| self.idf(term)).sum();
+        let Some(first) = self.postings.get(&phrase[0]) else {
+            return HashMap::new();
+        };
+        first
+            .keys()
+            .filter_map(|id| {
+                let fields = &self.docs[id];
+                let weight: f64 = fields
+                    .iter()
+                    .zip(FIELD_WEIGHTS)
+                    .filter(|(terms, _)| terms.windows(phrase.len()).any(|w| w == phrase))
+                    .map(|(_, w)| w)
+                    .sum();
+                (weight > 0.0).then_some((*id, PHRASE_WEIGHT * idf * weight))
+            })
+            .collect()
+    }
+}
+
+// Split text into lowercase words, then stem each word.
+fn tokenize(text: &str) -> Vec<String> {
+    let stemmer = rust_stemmers::Stemmer::create(rust_stemmers::Algorithm::English);
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| stemmer.stem(&word.to_lowercase()).into_owned())
+        .collect()
+}
+
+// Parse a query, where text in double quotes is a phrase.
+fn parse(q: &str) -> Query {
+    let mut query = Query::default();
+    for (i, part) in q.split('"').enumerate() {
+        let terms = tokenize(part);
+        if i % 2 == 1 && terms.len() > 1 {
+            query.phrases.push(terms);
+        } else {
+            query.terms.extend(terms);
+        }
+    }
+    query
+}
+
+// Is the candidate within a typo of the term? A longer term allows more.
+fn is_typo(term: &str, candidate: &str) -> bool {
+    let max = match term.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    max > 0 && strsim::damerau_levenshtein(term, candidate) <= max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::demo_books;
+
+    fn ids(index: &SearchIndex, q: &str) -> Vec<u32> {
+        index.search(q).into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn search_with_stemming_typos_and_phrases() {
+        let index = SearchIndex::from_books(demo_books());
+        index.index(&Book { id: 4, title: "Morrison Stories".into(), author: "Editor".into() });
+        // A title match ranks above an author match.
+        assert_eq!(ids(&index, "morrison"), [4, 2]);
+        // Stemming matches "story" with "stories".
+        assert_eq!(ids(&index, "story"), [4]);
+        // Typo tolerance matches "morison" with "morrison".
+        assert_eq!(ids(&index, "toni morison"), [2]);
+        // A phrase must match its terms in order.
+        assert_eq!(ids(&index, "\"toni morrison\""), [2]);
+        assert_eq!(ids(&index, "\"morrison toni\""), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn remove_and_reindex() {
+        let index = SearchIndex::from_books(demo_books());
+        index.remove(1);
+        assert!(ids(&index, "antigone").is_empty());
+        index.index(&Book { id: 3, title: "Elektra".into(), author: "Sophocles".into() });
+        assert_eq!(ids(&index, "elektra"), [3]);
+        assert!(ids(&index, "candide").is_empty());
+    }
+}
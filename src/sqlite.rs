@@ -0,0 +1,609 @@
+// Use Arc and Mutex to share one SQLite connection among many axum handlers.
+use std::sync::{Arc, Mutex};
+
+// Use SystemTime for modified times, which we store as Unix seconds.
+use std::time::{Duration, SystemTime};
+
+// Use rusqlite for SQLite, which is bundled, so there's no external service.
+use rusqlite::{params, Connection, OptionalExtension};
+
+// Use async_trait so a trait with async functions can be a `dyn` object.
+use async_trait::async_trait;
+
+// Use the Book and NewBook structs, and the BookStore trait.
+use crate::book::{Book, NewBook};
+use crate::data::{
+    check_version, demo_books, BatchOp, BatchOutcome, BookStore, Revision, RevisionAction, StoreError, StoredBook,
+};
+
+// Convert a SQLite error into a data store error.
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        StoreError::Failed(err.to_string())
+    }
+}
+
+// Store a revision action as text, such as "update".
+impl rusqlite::types::ToSql for RevisionAction {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+// Read a revision action from text.
+impl rusqlite::types::FromSql for RevisionAction {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        match value.as_str()? {
+            "create" => Ok(RevisionAction::Create),
+            "update" => Ok(RevisionAction::Update),
+            "delete" => Ok(RevisionAction::Delete),
+            "restore" => Ok(RevisionAction::Restore),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+// One versioned migration.
+//
+// A seed migration inserts our demo books rather than running SQL;
+// when seeding is turned off, we record a seed migration as applied
+// without running it, so a later startup with seeding never adds it.
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+    pub seed: bool,
+}
+
+// All migrations, in order. Never edit a migration after it ships;
+// instead, append a new migration with the next version number.
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create books table",
+        sql: "CREATE TABLE books (
+            id INTEGER PRIMARY KEY NOT NULL,
+            title TEXT NOT NULL,
+            author TEXT NOT NULL
+        );",
+        seed: false,
+    },
+    Migration {
+        version: 2,
+        name: "seed demo books",
+        sql: "",
+        seed: true,
+    },
+    Migration {
+        version: 3,
+        name: "add book versions",
+        sql: "ALTER TABLE books ADD COLUMN version INTEGER NOT NULL DEFAULT 1;",
+        seed: false,
+    },
+    Migration {
+        version: 4,
+        name: "add modified times",
+        sql: "ALTER TABLE books ADD COLUMN modified INTEGER NOT NULL DEFAULT 0;
+            UPDATE books SET modified = unixepoch();
+            CREATE TABLE books_modified (modified INTEGER NOT NULL);
+            INSERT INTO books_modified (modified) VALUES (unixepoch());",
+        seed: false,
+    },
+    Migration {
+        version: 5,
+        name: "add book id allocator",
+        sql: "CREATE TABLE book_ids (next_id INTEGER NOT NULL);
+            INSERT INTO book_ids (next_id) SELECT COALESCE(MAX(id), 0) + 1 FROM books;",
+        seed: false,
+    },
+    Migration {
+        version: 6,
+        name: "add trash",
+        sql: "ALTER TABLE books ADD COLUMN deleted_at INTEGER;",
+        seed: false,
+    },
+    Migration {
+        version: 7,
+        name: "add revisions",
+        sql: "CREATE TABLE revisions (
+                book_id INTEGER NOT NULL,
+                number INTEGER NOT NULL,
+                action TEXT NOT NULL,
+                who TEXT NOT NULL,
+                at INTEGER NOT NULL,
+                old_title TEXT,
+                old_author TEXT,
+                new_title TEXT,
+                new_author TEXT,
+                PRIMARY KEY (book_id, number)
+            );
+            INSERT INTO revisions (book_id, number, action, who, at, new_title, new_author)
+                SELECT id, version, 'create', 'system', modified, title, author
+                FROM books WHERE deleted_at IS NULL;
+            INSERT INTO revisions (book_id, number, action, who, at, old_title, old_author)
+                SELECT id, version, 'delete', 'system', modified, title, author
+                FROM books WHERE deleted_at IS NOT NULL;",
+        seed: false,
+    },
+];
+
+// Run every migration that the database has not yet applied.
+//
+// The table `schema_migrations` records each applied version.
+// Each migration runs in its own transaction with its record,
+// so a failed migration leaves the database at the prior version.
+pub fn migrate(conn: &mut Connection, seed: bool) -> Result<(), StoreError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL
+        )",
+        [],
+    )?;
+    for migration in MIGRATIONS {
+        let tx = conn.transaction()?;
+        let applied = tx
+            .query_row(
+                "SELECT 1 FROM schema_migrations WHERE version = ?1",
+                params![migration.version],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if applied {
+            continue;
+        }
+        if migration.seed {
+            if seed {
+                for book in demo_books() {
+                    tx.execute(
+                        "INSERT OR IGNORE INTO books (id, title, author) VALUES (?1, ?2, ?3)",
+                        params![book.id, book.title, book.author],
+                    )?;
+                }
+            }
+        } else {
+            tx.execute_batch(migration.sql)?;
+        }
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)",
+            params![migration.version, migration.name],
+        )?;
+        tx.commit()?;
+        tracing::info!("applied migration {}: {}", migration.version, migration.name);
+    }
+    Ok(())
+}
+
+// Create a data store that keeps books in a SQLite database.
+//
+// rusqlite is synchronous, so each operation runs on tokio's
+// blocking thread pool, and a mutex serializes connection access.
+#[derive(Debug, Clone)]
+pub struct SqliteBookStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteBookStore {
+    // Open a SQLite database file, creating it if needed, then migrate it.
+    // The path ":memory:" opens a private database that lives in memory.
+    pub fn open(path: impl AsRef<std::path::Path>, seed: bool) -> Result<Self, StoreError> {
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn, seed)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    // Run a closure with the connection, on the blocking thread pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, StoreError> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().map_err(|e| StoreError::Failed(e.to_string()))?;
+            f(&conn)
+        })
+        .await
+        .map_err(|e| StoreError::Failed(e.to_string()))?
+    }
+
+    // Run a closure in a transaction, which commits only if the closure
+    // succeeds, so a change and its revision are written together.
+    async fn with_tx<T, F>(&self, f: F) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, StoreError> + Send + 'static,
+    {
+        self.with_conn(|conn| {
+            let tx = conn.unchecked_transaction()?;
+            let value = f(&tx)?;
+            tx.commit()?;
+            Ok(value)
+        })
+        .await
+    }
+}
+
+// Read one book from a SQLite row with columns id, title, author.
+fn book_from_row(row: &rusqlite::Row) -> Result<Book, rusqlite::Error> {
+    Ok(Book {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        author: row.get(2)?,
+    })
+}
+
+// Read one stored book from a SQLite row with columns
+// id, title, author, version, modified, deleted_at.
+fn stored_book_from_row(row: &rusqlite::Row) -> Result<StoredBook, rusqlite::Error> {
+    Ok(StoredBook {
+        book: book_from_row(row)?,
+        version: row.get(3)?,
+        modified: from_unix(row.get(4)?),
+        deleted_at: row.get::<_, Option<i64>>(5)?.map(from_unix),
+    })
+}
+
+// Get one book by id, with its version and modified time, including
+// a book in the trash, using a connection or a transaction.
+fn select_book(conn: &Connection, id: u32) -> Result<Option<StoredBook>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT id, title, author, version, modified, deleted_at FROM books WHERE id = ?1",
+        params![id],
+        stored_book_from_row,
+    )
+    .optional()
+}
+
+// Get one book by id, unless it is in the trash.
+fn select_live_book(conn: &Connection, id: u32) -> Result<Option<StoredBook>, rusqlite::Error> {
+    Ok(select_book(conn, id)?.filter(|stored| stored.deleted_at.is_none()))
+}
+
+// Read one revision from a SQLite row with columns book_id, number,
+// action, who, at, old_title, old_author, new_title, new_author.
+fn revision_from_row(row: &rusqlite::Row) -> Result<Revision, rusqlite::Error> {
+    let book_id: u32 = row.get(0)?;
+    let book = |title: Option<String>, author: Option<String>| match (title, author) {
+        (Some(title), Some(author)) => Some(Book { id: book_id, title, author }),
+        _ => None,
+    };
+    Ok(Revision {
+        book_id,
+        number: row.get(1)?,
+        action: row.get(2)?,
+        who: row.get(3)?,
+        when: from_unix(row.get(4)?),
+        old: book(row.get(5)?, row.get(6)?),
+        new: book(row.get(7)?, row.get(8)?),
+    })
+}
+
+// Record a revision, using a connection or a transaction.
+fn insert_revision(conn: &Connection, revision: &Revision) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO revisions (book_id, number, action, who, at, old_title, old_author, new_title, new_author)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            revision.book_id,
+            revision.number,
+            revision.action,
+            revision.who,
+            to_unix(revision.when),
+            revision.old.as_ref().map(|book| &book.title),
+            revision.old.as_ref().map(|book| &book.author),
+            revision.new.as_ref().map(|book| &book.title),
+            revision.new.as_ref().map(|book| &book.author),
+        ],
+    )?;
+    Ok(())
+}
+
+// Convert a time to Unix seconds, which is the precision of HTTP dates.
+fn to_unix(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+}
+
+// Convert Unix seconds to a time.
+fn from_unix(secs: i64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs.max(0) as u64)
+}
+
+// Record that a book changed now, and return the time in Unix seconds.
+fn touch(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    let now = to_unix(SystemTime::now());
+    conn.execute("UPDATE books_modified SET modified = ?1", params![now])?;
+    Ok(now)
+}
+
+#[async_trait]
+impl BookStore for SqliteBookStore {
+    async fn get(&self, id: u32) -> Result<Option<StoredBook>, StoreError> {
+        self.with_conn(move |conn| Ok(select_live_book(conn, id)?)).await
+    }
+
+    async fn list(&self) -> Result<Vec<Book>, StoreError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT id, title, author FROM books WHERE deleted_at IS NULL")?;
+            let books = stmt.query_map([], book_from_row)?;
+            Ok(books.collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+
+    async fn last_modified(&self) -> Result<SystemTime, StoreError> {
+        self.with_conn(|conn| {
+            let secs = conn.query_row("SELECT modified FROM books_modified", [], |row| row.get(0))?;
+            Ok(from_unix(secs))
+        })
+        .await
+    }
+
+    async fn create(&self, new_book: NewBook, who: &str) -> Result<StoredBook, StoreError> {
+        let who = who.to_string();
+        self.with_tx(move |conn| create_book(conn, new_book, &who)).await
+    }
+
+    async fn update(&self, book: Book, expected: Option<&[u64]>, who: &str) -> Result<Option<StoredBook>, StoreError> {
+        let expected = expected.map(<[u64]>::to_vec);
+        let who = who.to_string();
+        self.with_tx(move |conn| update_book(conn, book, expected.as_deref(), &who)).await
+    }
+
+    async fn delete(&self, id: u32, expected: Option<&[u64]>, who: &str) -> Result<Option<Book>, StoreError> {
+        let expected = expected.map(<[u64]>::to_vec);
+        let who = who.to_string();
+        self.with_tx(move |conn| delete_book(conn, id, expected.as_deref(), &who)).await
+    }
+
+    async fn trash(&self) -> Result<Vec<StoredBook>, StoreError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT id, title, author, version, modified, deleted_at FROM books WHERE deleted_at IS NOT NULL",
+            )?;
+            let books = stmt.query_map([], stored_book_from_row)?;
+            Ok(books.collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+
+    async fn restore(&self, id: u32, who: &str) -> Result<Option<StoredBook>, StoreError> {
+        let who = who.to_string();
+        self.with_tx(move |conn| {
+            let Some(previous) = select_book(conn, id)?.filter(|stored| stored.deleted_at.is_some()) else {
+                return Ok(None);
+            };
+            let now = touch(conn)?;
+            conn.execute(
+                "UPDATE books SET deleted_at = NULL, version = version + 1, modified = ?2 WHERE id = ?1",
+                params![id, now],
+            )?;
+            let stored = StoredBook {
+                book: previous.book,
+                version: previous.version + 1,
+                modified: from_unix(now),
+                deleted_at: None,
+            };
+            insert_revision(conn, &Revision::of(RevisionAction::Restore, &who, &stored, None))?;
+            Ok(Some(stored))
+        })
+        .await
+    }
+
+    async fn purge(&self, id: Option<u32>, deleted_before: SystemTime) -> Result<Vec<Book>, StoreError> {
+        self.with_tx(move |conn| {
+            let mut stmt = conn.prepare(
+                "DELETE FROM books WHERE deleted_at <= ?1 AND (?2 IS NULL OR id = ?2) RETURNING id, title, author",
+            )?;
+            let books: Vec<Book> =
+                stmt.query_map(params![to_unix(deleted_before), id], book_from_row)?.collect::<Result<_, _>>()?;
+            for book in &books {
+                conn.execute("DELETE FROM revisions WHERE book_id = ?1", params![book.id])?;
+            }
+            Ok(books)
+        })
+        .await
+    }
+
+    // The batch runs in one transaction, which commits only if the batch
+    // may change the store. A failed operation writes nothing, because it
+    // fails its checks before it writes, so a best-effort batch can commit.
+    async fn batch(&self, ops: Vec<BatchOp>, atomic: bool, who: &str) -> Result<Vec<BatchOutcome>, StoreError> {
+        let who = who.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.unchecked_transaction()?;
+            let mut outcomes = Vec::with_capacity(ops.len());
+            for op in ops {
+                outcomes.push(match op {
+                    BatchOp::Create { book } => BatchOutcome::Created(create_book(&tx, book, &who)?),
+                    BatchOp::Update { book, version } => {
+                        let id = book.id;
+                        let result = update_book(&tx, book, BatchOp::expected(&version), &who);
+                        BatchOutcome::of(id, result, BatchOutcome::Updated)?
+                    }
+                    BatchOp::Delete { id, version } => {
+                        let result = delete_book(&tx, id, BatchOp::expected(&version), &who);
+                        BatchOutcome::of(id, result, BatchOutcome::Deleted)?
+                    }
+                });
+            }
+            if atomic && !outcomes.iter().all(BatchOutcome::is_done) {
+                // Dropping the transaction rolls it back.
+                return Ok(BatchOutcome::abort(outcomes));
+            }
+            tx.commit()?;
+            Ok(outcomes)
+        })
+        .await
+    }
+
+    async fn revisions(&self, id: u32) -> Result<Vec<Revision>, StoreError> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT book_id, number, action, who, at, old_title, old_author, new_title, new_author
+                    FROM revisions WHERE book_id = ?1 ORDER BY number",
+            )?;
+            let revisions = stmt.query_map(params![id], revision_from_row)?;
+            Ok(revisions.collect::<Result<_, _>>()?)
+        })
+        .await
+    }
+}
+
+// Create a book; see `BookStore::create`.
+//
+// The table `book_ids` has the next id, which only ever increases,
+// so the store never reuses an id, even after deleting the last book.
+fn create_book(conn: &Connection, new_book: NewBook, who: &str) -> Result<StoredBook, StoreError> {
+    let id: u32 = conn.query_row(
+        "UPDATE book_ids SET next_id = next_id + 1 RETURNING next_id - 1",
+        [],
+        |row| row.get(0),
+    )?;
+    let now = touch(conn)?;
+    let book = new_book.with_id(id);
+    conn.execute(
+        "INSERT INTO books (id, title, author, version, modified) VALUES (?1, ?2, ?3, 1, ?4)",
+        params![book.id, book.title, book.author, now],
+    )?;
+    let stored = StoredBook { book, version: 1, modified: from_unix(now), deleted_at: None };
+    insert_revision(conn, &Revision::of(RevisionAction::Create, who, &stored, None))?;
+    Ok(stored)
+}
+
+// Update a book; see `BookStore::update`.
+//
+// The connection mutex serializes operations, so the version that we
+// check is still the stored version when we write.
+fn update_book(
+    conn: &Connection,
+    book: Book,
+    expected: Option<&[u64]>,
+    who: &str,
+) -> Result<Option<StoredBook>, StoreError> {
+    let Some(previous) = select_live_book(conn, book.id)? else {
+        return Ok(None);
+    };
+    check_version(book.id, previous.version, expected)?;
+    let now = touch(conn)?;
+    conn.execute(
+        "UPDATE books SET title = ?2, author = ?3, version = version + 1, modified = ?4 WHERE id = ?1",
+        params![book.id, book.title, book.author, now],
+    )?;
+    let stored = StoredBook { book, version: previous.version + 1, modified: from_unix(now), deleted_at: None };
+    insert_revision(conn, &Revision::of(RevisionAction::Update, who, &stored, Some(previous.book)))?;
+    Ok(Some(stored))
+}
+
+// Move a book to the trash; see `BookStore::delete`.
+fn delete_book(conn: &Connection, id: u32, expected: Option<&[u64]>, who: &str) -> Result<Option<Book>, StoreError> {
+    let Some(previous) = select_live_book(conn, id)? else {
+        return Ok(None);
+    };
+    check_version(id, previous.version, expected)?;
+    let now = touch(conn)?;
+    conn.execute(
+        "UPDATE books SET deleted_at = ?2, version = version + 1, modified = ?2 WHERE id = ?1",
+        params![id, now],
+    )?;
+    let stored = StoredBook {
+        book: previous.book.clone(),
+        version: previous.version + 1,
+        modified: from_unix(now),
+        deleted_at: Some(from_unix(now)),
+    };
+    insert_revision(conn, &Revision::of(RevisionAction::Delete, who, &stored, Some(previous.book)))?;
+    Ok(Some(stored.book))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn seed_is_optional() {
+        let seeded = SqliteBookStore::open(":memory:", true).unwrap();
+        assert_eq!(seeded.list().await.unwrap().len(), 3);
+        let empty = SqliteBookStore::open(":memory:", false).unwrap();
+        assert_eq!(empty.list().await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn books_persist_across_restarts() {
+        let path = std::env::temp_dir().join(format!("demo-rust-axum-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let store = SqliteBookStore::open(&path, true).unwrap();
+            let new_book = NewBook { title: "Decameron".into(), author: "Giovanni Boccaccio".into() };
+            assert_eq!(store.create(new_book, "test").await.unwrap().book.id, 4);
+            store.delete(1, None, "test").await.unwrap();
+            store.delete(4, None, "test").await.unwrap();
+        }
+        // Reopen, which runs migrations again; the seed must not re-add book 1,
+        // and the next id must not reuse id 4.
+        let store = SqliteBookStore::open(&path, true).unwrap();
+        assert_eq!(store.get(1).await.unwrap(), None);
+        let new_book = NewBook { title: "Elektra".into(), author: "Sophocles".into() };
+        assert_eq!(store.create(new_book, "test").await.unwrap().book.id, 5);
+        assert_eq!(store.get(5).await.unwrap().unwrap().book.title, "Elektra");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn atomic_batch_rolls_back() {
+        let store = SqliteBookStore::open(":memory:", true).unwrap();
+        let ops = vec![
+            BatchOp::Create { book: NewBook { title: "Decameron".into(), author: "Giovanni Boccaccio".into() } },
+            BatchOp::Delete { id: 1, version: Some(2) },
+        ];
+        let outcomes = store.batch(ops.clone(), true, "test").await.unwrap();
+        assert_eq!(outcomes, [BatchOutcome::Aborted, BatchOutcome::VersionMismatch { id: 1, version: 1 }]);
+        assert_eq!(store.list().await.unwrap().len(), 3);
+        // The rollback also undoes the id allocation, so the next id is still 4.
+        let outcomes = store.batch(ops, false, "test").await.unwrap();
+        assert!(matches!(&outcomes[0], BatchOutcome::Created(stored) if stored.book.id == 4));
+        assert_eq!(store.list().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn trash_restore_and_purge() {
+        let store = SqliteBookStore::open(":memory:", true).unwrap();
+        store.delete(1, None, "test").await.unwrap();
+        assert_eq!(store.get(1).await.unwrap(), None);
+        assert_eq!(store.list().await.unwrap().len(), 2);
+        assert_eq!(store.trash().await.unwrap()[0].version, 2);
+        assert_eq!(store.restore(1, "test").await.unwrap().unwrap().version, 3);
+        assert_eq!(store.trash().await.unwrap(), vec![]);
+        store.delete(2, None, "test").await.unwrap();
+        assert_eq!(store.purge(None, SystemTime::UNIX_EPOCH).await.unwrap(), vec![]);
+        assert_eq!(store.purge(None, SystemTime::now()).await.unwrap()[0].id, 2);
+        assert_eq!(store.restore(2, "test").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn changes_record_revisions() {
+        let store = SqliteBookStore::open(":memory:", true).unwrap();
+        let book = Book { id: 1, title: "Elektra".into(), author: "Sophocles".into() };
+        store.update(book.clone(), None, "ann").await.unwrap();
+        store.delete(1, None, "bob").await.unwrap();
+        store.restore(1, "cy").await.unwrap();
+        let revisions = store.revisions(1).await.unwrap();
+        let summary: Vec<(u64, RevisionAction, &str)> =
+            revisions.iter().map(|r| (r.number, r.action, r.who.as_str())).collect();
+        assert_eq!(
+            summary,
+            [
+                (1, RevisionAction::Create, "system"),
+                (2, RevisionAction::Update, "ann"),
+                (3, RevisionAction::Delete, "bob"),
+                (4, RevisionAction::Restore, "cy"),
+            ]
+        );
+        assert_eq!(revisions[1].old.as_ref().map(|book| book.title.as_str()), Some("Antigone"));
+        assert_eq!((revisions[2].old.as_ref(), revisions[2].new.as_ref()), (Some(&book), None));
+        assert_eq!((revisions[3].old.as_ref(), revisions[3].new.as_ref()), (None, Some(&book)));
+    }
+}
@@ -0,0 +1,90 @@
+// Use Arc to share the data store with a background task.
+use std::sync::Arc;
+
+// Use Duration and SystemTime for deleted times and the retention policy.
+use std::time::{Duration, SystemTime};
+
+// Use Serialize to convert a trashed book into response JSON.
+use serde::Serialize;
+
+// Use ToSchema to describe a trashed book in our OpenAPI document.
+use utoipa::ToSchema;
+
+// Use the Book struct, and the BookStore trait.
+use crate::book::Book;
+use crate::data::{BookStore, StoreError, StoredBook};
+
+// How long a book stays in the trash, by default, before the retention
+// policy purges it. Until then, a librarian can restore it.
+pub const DEFAULT_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// How often the retention policy looks for books to purge.
+pub const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// One book in the trash, with its deleted time in RFC 3339 format,
+// such as "2026-10-17T09:30:00Z".
+#[derive(Debug, Serialize, ToSchema, Clone, Eq, PartialEq)]
+pub struct TrashedBook {
+    #[serde(flatten)]
+    pub book: Book,
+    pub deleted_at: String,
+}
+
+impl TrashedBook {
+    // Convert a stored book that is in the trash, else return `None`.
+    pub fn from_stored(stored: StoredBook) -> Option<Self> {
+        let deleted_at = stored.deleted_at?;
+        Some(TrashedBook {
+            book: stored.book,
+            deleted_at: humantime::format_rfc3339_seconds(deleted_at).to_string(),
+        })
+    }
+}
+
+// Purge each book that has been in the trash for longer than the retention.
+pub async fn purge_expired(store: &dyn BookStore, retention: Duration) -> Result<Vec<Book>, StoreError> {
+    let deleted_before = SystemTime::now().checked_sub(retention).unwrap_or(SystemTime::UNIX_EPOCH);
+    store.purge(None, deleted_before).await
+}
+
+// Run the retention policy in the background, once per interval,
+// for as long as the tokio runtime runs.
+pub fn spawn_retention(store: Arc<dyn BookStore>, retention: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge_expired(store.as_ref(), retention).await {
+                Ok(books) if !books.is_empty() => tracing::info!("purged {} books from the trash", books.len()),
+                Ok(_) => {}
+                Err(err) => tracing::error!("failed to purge the trash: {}", err),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{demo_books, InMemoryBookStore};
+
+    #[test]
+    fn from_stored() {
+        let book = demo_books().remove(0);
+        let stored = StoredBook { book, version: 2, modified: SystemTime::UNIX_EPOCH, deleted_at: None };
+        assert_eq!(TrashedBook::from_stored(stored.clone()), None);
+        let deleted_at = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(90));
+        let trashed = TrashedBook::from_stored(StoredBook { deleted_at, ..stored }).unwrap();
+        assert_eq!(trashed.deleted_at, "1970-01-01T00:01:30Z");
+    }
+
+    #[tokio::test]
+    async fn purge_expired_keeps_recent_books() {
+        let store = InMemoryBookStore::new(demo_books());
+        store.delete(1, None, "test").await.unwrap();
+        assert_eq!(purge_expired(&store, DEFAULT_RETENTION).await, Ok(vec![]));
+        assert_eq!(store.trash().await.unwrap().len(), 1);
+        assert_eq!(purge_expired(&store, Duration::ZERO).await.unwrap().len(), 1);
+        assert_eq!(store.trash().await.unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,516 @@
+// Use BTreeMap, HashMap, and VecDeque for webhooks and their deliveries.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+// Use Arc, Weak, Mutex, and OnceLock to share webhooks with the delivery
+// worker. We never hold the lock across an await, so a std lock is fine.
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, Weak};
+
+// Use IpAddr to tell a public address from a private one.
+use std::net::IpAddr;
+
+// Use Duration and SystemTime for retries and delivery times.
+use std::time::{Duration, SystemTime};
+
+// Use HMAC-SHA256 to sign each delivery with its webhook's secret.
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// Use Serialize and Deserialize for the admin API, which is JSON.
+use serde::{Deserialize, Serialize};
+
+// Use ToSchema to describe webhooks and deliveries in our OpenAPI document.
+use utoipa::ToSchema;
+
+// Use a tokio channel as the delivery queue, and a semaphore to bound
+// how many attempts we make at once.
+use tokio::sync::{mpsc, Semaphore};
+
+// Use the Change struct, which is the JSON body of each delivery.
+use crate::changes::{Change, ChangeKind};
+
+// Use the AppError type, for a webhook that is invalid.
+use crate::error::AppError;
+
+// The request header with the signature of a delivery, which is
+// "sha256=" then the hex HMAC-SHA256 of the body, keyed by the secret.
+pub const SIGNATURE_HEADER: &str = "webhook-signature";
+
+// The request header with the id of a delivery, which stays the same
+// for each retry, so a receiver can skip a delivery that it already has.
+pub const DELIVERY_HEADER: &str = "webhook-delivery";
+
+// How many times we try a delivery, at most, including the first time.
+pub const MAX_ATTEMPTS: u32 = 6;
+
+// How long we wait before the first retry; each retry waits twice as long.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+
+// How long a receiver has to respond to one attempt.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+// How many attempts we make at once, at most. Each attempt is its own
+// task, so one slow receiver doesn't hold up the other webhooks.
+pub const MAX_CONCURRENT_ATTEMPTS: usize = 16;
+
+// How many recent deliveries we keep for each webhook.
+const MAX_DELIVERIES: usize = 100;
+
+// The shortest secret that we accept, so a signature means something.
+const MIN_SECRET_LEN: usize = 16;
+
+// Request body to register a webhook, such as:
+// `{"url": "https://example.com/hooks", "events": ["created"], "secret": "…"}`
+#[derive(Debug, Deserialize, ToSchema, Clone)]
+pub struct NewWebhook {
+    pub url: String,
+    pub events: Vec<ChangeKind>,
+    pub secret: String,
+}
+
+// A webhook subscription. We never show its secret again after it's registered.
+#[derive(Debug, Serialize, ToSchema, Clone)]
+pub struct Webhook {
+    pub id: u32,
+    pub url: String,
+    pub events: Vec<ChangeKind>,
+    #[serde(skip)]
+    secret: String,
+}
+
+// The state of a delivery.
+#[derive(Debug, Serialize, ToSchema, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryState {
+    // We will try, or try again.
+    Pending,
+    // The receiver responded with a 2xx status.
+    Delivered,
+    // We tried the most times, and gave up.
+    Failed,
+}
+
+// One delivery of one change to one webhook, with its last attempt's
+// response status, or its error if there was no response.
+#[derive(Debug, Serialize, ToSchema, Clone, Eq, PartialEq)]
+pub struct Delivery {
+    pub id: u64,
+    pub webhook_id: u32,
+    pub change_id: u64,
+    #[serde(rename = "type")]
+    pub kind: ChangeKind,
+    pub state: DeliveryState,
+    pub attempts: u32,
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+    pub updated_at: String,
+}
+
+// One attempt to make, in the delivery queue.
+#[derive(Debug)]
+struct Job {
+    delivery_id: u64,
+    webhook_id: u32,
+    body: String,
+}
+
+// Webhook subscriptions, and their deliveries.
+//
+// When a handler changes a book, we queue a delivery of the change for
+// each webhook that wants that type of change. One worker takes each
+// delivery from the queue, and POSTs the change as JSON, signed with the
+// webhook's secret, in a task of its own, up to a most number at once.
+//
+// Anyone who can reach the admin API can register a webhook, so by
+// default we deliver only to public addresses, and never to a loopback,
+// private, or link-local one, such as a cloud metadata address. We check
+// a url when it's registered, and each address that its host resolves to
+// when we deliver, so a host can't later resolve to a private address.
+//
+// If the receiver fails, then we retry the delivery, with exponential
+// backoff, up to a most number of attempts.
+//
+// The worker starts with the first webhook, so an app without webhooks
+// has no worker.
+#[derive(Debug)]
+pub struct Webhooks {
+    backoff: Duration,
+    allow_private: bool,
+    inner: Mutex<Inner>,
+    queue: OnceLock<mpsc::UnboundedSender<Job>>,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: u32,
+    next_delivery: u64,
+    webhooks: BTreeMap<u32, Webhook>,
+    // Webhook id => its deliveries, oldest first.
+    deliveries: HashMap<u32, VecDeque<Delivery>>,
+}
+
+impl Webhooks {
+    // Create webhooks with the wait before the first retry, and whether
+    // to allow a private address, such as for local development.
+    //
+    // This panics if the HTTP client fails to build, such as when the TLS
+    // backend can't load, because a client without our resolver, redirect
+    // policy, and timeout would deliver to any address.
+    pub fn new(backoff: Duration, allow_private: bool) -> Self {
+        // A redirect could go to a private address, so we don't follow one.
+        let mut client = reqwest::Client::builder().timeout(TIMEOUT).redirect(reqwest::redirect::Policy::none());
+        if !allow_private {
+            client = client.dns_resolver(Arc::new(PublicResolver));
+        }
+        Webhooks {
+            backoff,
+            allow_private,
+            inner: Mutex::default(),
+            queue: OnceLock::new(),
+            client: client.build().expect("failed to build the webhook HTTP client"),
+        }
+    }
+
+    // Register a webhook, or fail if it's invalid. This starts the worker,
+    // if it's not already running, so it must run in a tokio runtime.
+    pub fn register(self: &Arc<Self>, new_webhook: NewWebhook) -> Result<Webhook, AppError> {
+        let NewWebhook { url, mut events, secret } = new_webhook;
+        let parsed = reqwest::Url::parse(&url)
+            .map_err(|err| AppError::Validation(format!("Webhook url {} is invalid: {}", url, err)))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(AppError::Validation(format!("Webhook url {} must be http or https", url)));
+        }
+        if !self.allow_private && !is_public_host(&parsed) {
+            return Err(AppError::Validation(format!("Webhook url {} must not be a private address", url)));
+        }
+        if events.is_empty() {
+            return Err(AppError::Validation("Webhook events must have created, updated, or deleted".into()));
+        }
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(AppError::Validation(format!(
+                "Webhook secret must be at least {} characters",
+                MIN_SECRET_LEN
+            )));
+        }
+        events.sort_by_key(ChangeKind::as_str);
+        events.dedup();
+        self.queue.get_or_init(|| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            tokio::spawn(worker(Arc::downgrade(self), receiver));
+            sender
+        });
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.next_id += 1;
+        let webhook = Webhook { id: inner.next_id, url, events, secret };
+        inner.webhooks.insert(webhook.id, webhook.clone());
+        Ok(webhook)
+    }
+
+    // List each webhook, in the order that they were registered.
+    pub fn list(&self) -> Vec<Webhook> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner).webhooks.values().cloned().collect()
+    }
+
+    // Get a webhook.
+    pub fn get(&self, id: u32) -> Option<Webhook> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner).webhooks.get(&id).cloned()
+    }
+
+    // Remove a webhook, with its deliveries. Any queued retry is dropped.
+    pub fn remove(&self, id: u32) -> Option<Webhook> {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.deliveries.remove(&id);
+        inner.webhooks.remove(&id)
+    }
+
+    // Get the recent deliveries of a webhook, newest first.
+    pub fn deliveries(&self, id: u32) -> Vec<Delivery> {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.deliveries.get(&id).map(|deliveries| deliveries.iter().rev().cloned().collect()).unwrap_or_default()
+    }
+
+    // Queue a delivery of a change for each webhook that wants it.
+    pub fn notify(&self, change: &Change) {
+        let Some(queue) = self.queue.get() else {
+            return;
+        };
+        let body = serde_json::to_string(change).unwrap_or_default();
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        let ids: Vec<u32> = inner
+            .webhooks
+            .values()
+            .filter(|webhook| webhook.events.contains(&change.kind))
+            .map(|webhook| webhook.id)
+            .collect();
+        for webhook_id in ids {
+            inner.next_delivery += 1;
+            let delivery = Delivery {
+                id: inner.next_delivery,
+                webhook_id,
+                change_id: change.id,
+                kind: change.kind,
+                state: DeliveryState::Pending,
+                attempts: 0,
+                response_status: None,
+                error: None,
+                updated_at: now(),
+            };
+            let deliveries = inner.deliveries.entry(webhook_id).or_default();
+            if deliveries.len() == MAX_DELIVERIES {
+                deliveries.pop_front();
+            }
+            deliveries.push_back(delivery);
+            let job = Job { delivery_id: inner.next_delivery, webhook_id, body: body.clone() };
+            // A send fails only if the worker has stopped, which it doesn't.
+            let _ = queue.send(job);
+        }
+    }
+
+    // Record the result of an attempt, and return the delivery, if the
+    // webhook still has it.
+    fn record(&self, job: &Job, result: Result<u16, String>) -> Option<Delivery> {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        let delivery = inner
+            .deliveries
+            .get_mut(&job.webhook_id)?
+            .iter_mut()
+            .find(|delivery| delivery.id == job.delivery_id)?;
+        delivery.attempts += 1;
+        delivery.updated_at = now();
+        let delivered = matches!(result, Ok(status) if (200..300).contains(&status));
+        (delivery.response_status, delivery.error) = match result {
+            Ok(status) => (Some(status), None),
+            Err(err) => (None, Some(err)),
+        };
+        delivery.state = if delivered {
+            DeliveryState::Delivered
+        } else if delivery.attempts >= MAX_ATTEMPTS {
+            DeliveryState::Failed
+        } else {
+            DeliveryState::Pending
+        };
+        Some(delivery.clone())
+    }
+
+    // Make one attempt: POST the body, signed, and return the response status.
+    async fn attempt(&self, webhook: &Webhook, job: &Job) -> Result<u16, String> {
+        let response = self
+            .client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, sign(&webhook.secret, job.body.as_bytes()))
+            .header(DELIVERY_HEADER, job.delivery_id.to_string())
+            .body(job.body.clone())
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        Ok(response.status().as_u16())
+    }
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Webhooks::new(DEFAULT_BACKOFF, false)
+    }
+}
+
+// Sign a body with a secret: "sha256=" then the hex HMAC-SHA256.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes a key of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+// Is a url's host public? A host name is, unless it's "localhost", because
+// we check the addresses of a host name when we deliver; see PublicResolver.
+fn is_public_host(url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    // An IPv6 host is in brackets, such as "[::1]".
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => is_public(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+    }
+}
+
+// Is an address public, rather than such as loopback, private, link-local,
+// or shared? An IPv4 address in IPv6 is whatever its IPv4 address is.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xC0) == 64;
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+// A DNS resolver that resolves a host name to its public addresses only,
+// and fails if it has none, so a delivery never goes to a private address.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<_> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            let public: Vec<_> = addrs.into_iter().filter(|addr| is_public(addr.ip())).collect();
+            if public.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(public.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+// The time now, in RFC 3339 format.
+fn now() -> String {
+    humantime::format_rfc3339_seconds(SystemTime::now()).to_string()
+}
+
+// Take each job from the queue, and deliver it in a task of its own, when
+// there are fewer than the most attempts at once. The worker stops when
+// the webhooks are gone.
+async fn worker(webhooks: Weak<Webhooks>, mut queue: mpsc::UnboundedReceiver<Job>) {
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_ATTEMPTS));
+    while let Some(job) = queue.recv().await {
+        // We never close the semaphore, so this never fails.
+        let Ok(permit) = permits.clone().acquire_owned().await else {
+            return;
+        };
+        let Some(webhooks) = webhooks.upgrade() else {
+            return;
+        };
+        tokio::spawn(async move {
+            deliver(&webhooks, job).await;
+            drop(permit);
+        });
+    }
+}
+
+// Attempt a job. If it fails, then wait, twice as long as the time before,
+// and queue it again.
+async fn deliver(webhooks: &Webhooks, job: Job) {
+    // A job of a removed webhook is dropped.
+    let Some(webhook) = webhooks.get(job.webhook_id) else {
+        return;
+    };
+    let result = webhooks.attempt(&webhook, &job).await;
+    let Some(delivery) = webhooks.record(&job, result) else {
+        return;
+    };
+    match delivery.state {
+        DeliveryState::Pending => {
+            let wait = webhooks.backoff * 2u32.pow(delivery.attempts - 1);
+            let queue = webhooks.queue.get().cloned();
+            tokio::spawn(async move {
+                tokio::time::sleep(wait).await;
+                if let Some(queue) = queue {
+                    let _ = queue.send(job);
+                }
+            });
+        }
+        DeliveryState::Failed => {
+            tracing::warn!("webhook {} delivery {} failed: {:?}", webhook.id, delivery.id, delivery.error);
+        }
+        DeliveryState::Delivered => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_body() {
+        // The example from RFC 4231, test case 2.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn register_checks_webhook() {
+        let webhooks = Arc::new(Webhooks::default());
+        let new_webhook = NewWebhook {
+            url: "https://example.com/hooks".into(),
+            events: vec![ChangeKind::Updated, ChangeKind::Created, ChangeKind::Updated],
+            secret: "0123456789abcdef".into(),
+        };
+        let webhook = webhooks.register(new_webhook.clone()).unwrap();
+        assert_eq!(webhook.events, [ChangeKind::Created, ChangeKind::Updated]);
+        assert!(webhooks.register(NewWebhook { url: "ftp://example.com".into(), ..new_webhook.clone() }).is_err());
+        for url in ["http://127.0.0.1/", "http://localhost:3000/", "http://169.254.169.254/", "http://[::ffff:10.0.0.1]/"] {
+            assert!(webhooks.register(NewWebhook { url: url.into(), ..new_webhook.clone() }).is_err(), "{}", url);
+        }
+        assert!(webhooks.register(NewWebhook { events: vec![], ..new_webhook.clone() }).is_err());
+        assert!(webhooks.register(NewWebhook { secret: "short".into(), ..new_webhook }).is_err());
+        assert_eq!(webhooks.list().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_receiver_does_not_hold_up_others() {
+        // A stand-in receiver, with one route that never responds in time.
+        let receiver = axum::Router::new()
+            .route("/slow", axum::routing::post(std::future::pending::<()>))
+            .route("/fast", axum::routing::post(|| async { axum::http::StatusCode::NO_CONTENT }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+        let webhooks = Arc::new(Webhooks::new(DEFAULT_BACKOFF, true));
+        for path in ["slow", "fast"] {
+            let url = format!("http://{}/{}", addr, path);
+            let new_webhook = NewWebhook { url, events: vec![ChangeKind::Created], secret: "0123456789abcdef".into() };
+            webhooks.register(new_webhook).unwrap();
+        }
+        let book = crate::book::Book { id: 1, title: "Antigone".into(), author: "Sophocles".into() };
+        webhooks.notify(&Change { id: 1, kind: ChangeKind::Created, book, version: Some(1) });
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while webhooks.deliveries(2)[0].state == DeliveryState::Pending && tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(webhooks.deliveries(2)[0].state, DeliveryState::Delivered);
+        assert_eq!(webhooks.deliveries(1)[0].attempts, 0);
+    }
+
+    #[test]
+    fn public_addresses() {
+        for ip in ["93.184.215.14", "2606:2800:21f:cb07:6820:80da:af6b:8b2c"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolver_skips_private_addresses() {
+        let resolved = reqwest::dns::Resolve::resolve(&PublicResolver, "localhost".parse().unwrap()).await;
+        assert!(resolved.is_err());
+    }
+}
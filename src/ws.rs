@@ -0,0 +1,285 @@
+// Use BTreeMap, BTreeSet, and HashMap for presence and subscriptions.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// Use an atomic counter to give each connection its own id.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Use Mutex for thread-safe access to presence. We never hold the lock
+// across an await, so a std lock is fine.
+use std::sync::{Mutex, PoisonError};
+
+// Use Serialize and Deserialize for WebSocket messages, which are JSON.
+use serde::{Deserialize, Serialize};
+
+// Use a tokio broadcast channel to send each presence update to every connection.
+use tokio::sync::broadcast;
+
+// Use axum WebSocket types.
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+
+// Use the Book struct, the app state, and the form that an edit saves.
+use crate::app::{book_not_found, save_book_form, AppState, BookForm};
+use crate::book::Book;
+
+// Use the AppError type, which an error reply has as JSON.
+use crate::error::AppError;
+
+// Use schema validation, for an edit.
+use crate::schema::validate;
+
+// The maximum number of books that one connection can subscribe to.
+pub const MAX_SUBSCRIPTIONS: usize = 100;
+
+// How many presence updates a slow connection can fall behind.
+const PRESENCE_BUFFER: usize = 256;
+
+// The next connection id.
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(1);
+
+// Who is viewing which book: book id => connection id => who.
+//
+// A person can view a book in many tabs, which are many connections, so
+// we keep each connection, and report each viewer once.
+#[derive(Debug)]
+pub struct Presence {
+    viewers: Mutex<HashMap<u32, BTreeMap<u64, String>>>,
+    sender: broadcast::Sender<PresenceUpdate>,
+}
+
+// Who is viewing one book now, after someone joined or left.
+#[derive(Debug, Serialize, Clone, Eq, PartialEq)]
+pub struct PresenceUpdate {
+    pub id: u32,
+    pub viewers: Vec<String>,
+}
+
+impl Presence {
+    // A connection starts viewing books.
+    fn join(&self, connection: u64, who: &str, ids: &[u32]) {
+        let mut viewers = self.viewers.lock().unwrap_or_else(PoisonError::into_inner);
+        for id in ids {
+            viewers.entry(*id).or_default().insert(connection, who.to_string());
+            self.send(&viewers, *id);
+        }
+    }
+
+    // A connection stops viewing books.
+    fn leave(&self, connection: u64, ids: &[u32]) {
+        let mut viewers = self.viewers.lock().unwrap_or_else(PoisonError::into_inner);
+        for id in ids {
+            if let Some(book_viewers) = viewers.get_mut(id)
+                && book_viewers.remove(&connection).is_some()
+            {
+                if book_viewers.is_empty() {
+                    viewers.remove(id);
+                }
+                self.send(&viewers, *id);
+            }
+        }
+    }
+
+    // Who is viewing a book, once each, in order.
+    fn viewers_of(viewers: &HashMap<u32, BTreeMap<u64, String>>, id: u32) -> Vec<String> {
+        let who: BTreeSet<&String> = viewers.get(&id).into_iter().flat_map(|viewers| viewers.values()).collect();
+        who.into_iter().cloned().collect()
+    }
+
+    // Send who is viewing a book. A send fails only if there are no
+    // connections, which is fine.
+    fn send(&self, viewers: &HashMap<u32, BTreeMap<u64, String>>, id: u32) {
+        let _ = self.sender.send(PresenceUpdate { id, viewers: Presence::viewers_of(viewers, id) });
+    }
+}
+
+impl Default for Presence {
+    fn default() -> Self {
+        Presence { viewers: Mutex::default(), sender: broadcast::channel(PRESENCE_BUFFER).0 }
+    }
+}
+
+// A command from a client, such as:
+//
+// - `{"type": "subscribe", "ids": [1, 2]}`
+// - `{"type": "unsubscribe", "ids": [2]}`
+// - `{"type": "edit", "id": 1, "version": 1, "title": "Elektra", "author": "Sophocles"}`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Command {
+    Subscribe { ids: Vec<u32> },
+    Unsubscribe { ids: Vec<u32> },
+    Edit(BookForm),
+}
+
+// A reply to a client, besides book changes, which are JSON as in
+// "GET /books/events", such as `{"id": 7, "type": "updated", ...}`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Reply {
+    // A subscribed book as it is now.
+    Book { book: Book, version: u64 },
+    // Who is viewing a subscribed book.
+    Presence(PresenceUpdate),
+    // An edit that we saved.
+    Saved { book: Book, version: u64 },
+    // A command that failed, with its HTTP status code.
+    Error { status: u16, message: String },
+    // The connection fell behind, so some changes are lost; the client
+    // should subscribe again to get each book as it is now.
+    Reset,
+}
+
+// Run one WebSocket connection for someone, until either side closes it,
+// or the server shuts down, which closes it with "going away" (1001).
+pub async fn run(mut socket: WebSocket, state: AppState, who: String) {
+    let connection = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
+    let mut changes = state.changes.subscribe(None).receiver;
+    let mut presence = state.presence.sender.subscribe();
+    let mut shutdown = state.shutdown.subscribe();
+    let mut subscribed = BTreeSet::new();
+    // The state holds the shutdown sender, so the flag can't close.
+    let mut going_away = *shutdown.borrow_and_update();
+    while !going_away {
+        let reply = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let replies = command(&state, connection, &who, &mut subscribed, &text).await;
+                    if !send_all(&mut socket, replies).await {
+                        break;
+                    }
+                    continue;
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // axum answers a ping with a pong for us.
+                Some(Ok(_)) => continue,
+            },
+            change = changes.recv() => match change {
+                Ok(change) if subscribed.contains(&change.book.id) => serde_json::to_string(&change),
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(_)) => serde_json::to_string(&Reply::Reset),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            update = presence.recv() => match update {
+                Ok(update) if subscribed.contains(&update.id) => serde_json::to_string(&Reply::Presence(update)),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = shutdown.changed() => {
+                going_away = *shutdown.borrow();
+                continue;
+            }
+        };
+        let Ok(text) = reply else { continue };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+    if going_away {
+        let frame = CloseFrame { code: close_code::AWAY, reason: "Server is shutting down".into() };
+        let _ = socket.send(Message::Close(Some(frame))).await;
+    }
+    let ids: Vec<u32> = subscribed.into_iter().collect();
+    state.presence.leave(connection, &ids);
+}
+
+// Send replies, or return false if the connection is gone.
+async fn send_all(socket: &mut WebSocket, replies: Vec<Reply>) -> bool {
+    for reply in replies {
+        let Ok(text) = serde_json::to_string(&reply) else { continue };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+// Run a command from a client, and return any replies to it.
+async fn command(
+    state: &AppState,
+    connection: u64,
+    who: &str,
+    subscribed: &mut BTreeSet<u32>,
+    text: &str,
+) -> Vec<Reply> {
+    let value = match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(value) => value,
+        Err(err) => return vec![error_reply(AppError::Validation(format!("Bad command: {}", err)))],
+    };
+    // An edit has the same fields as a form, so it must be valid for its schema.
+    if value["type"] == "edit"
+        && let Err(err) = validate::<BookForm>(&value)
+    {
+        return vec![error_reply(err)];
+    }
+    let command = match serde_json::from_value::<Command>(value) {
+        Ok(command) => command,
+        Err(err) => return vec![error_reply(AppError::Validation(format!("Bad command: {}", err)))],
+    };
+    match command {
+        Command::Subscribe { ids } => {
+            let mut replies = Vec::new();
+            let mut joined = Vec::new();
+            let ids: Vec<u32> = ids.into_iter().filter(|id| !subscribed.contains(id)).collect();
+            for id in ids {
+                if subscribed.len() >= MAX_SUBSCRIPTIONS {
+                    replies.push(error_reply(AppError::Validation(format!(
+                        "A connection can subscribe to at most {} books",
+                        MAX_SUBSCRIPTIONS
+                    ))));
+                    break;
+                }
+                match state.store.get(id).await {
+                    Ok(Some(stored)) => {
+                        subscribed.insert(id);
+                        joined.push(id);
+                        replies.push(Reply::Book { book: stored.book, version: stored.version });
+                    }
+                    Ok(None) => replies.push(error_reply(book_not_found(id))),
+                    Err(err) => replies.push(error_reply(err.into())),
+                }
+            }
+            // We join after the books, so a client sees each book, then who views it.
+            state.presence.join(connection, who, &joined);
+            replies
+        }
+        Command::Unsubscribe { ids } => {
+            let left: Vec<u32> = ids.into_iter().filter(|id| subscribed.remove(id)).collect();
+            state.presence.leave(connection, &left);
+            vec![]
+        }
+        Command::Edit(form) => match save_book_form(state, who, form.id, form).await {
+            Ok(stored) => vec![Reply::Saved { book: stored.book, version: stored.version }],
+            Err(err) => vec![error_reply(err)],
+        },
+    }
+}
+
+// Convert an app error into an error reply.
+fn error_reply(err: AppError) -> Reply {
+    if let AppError::Internal(detail) = &err {
+        tracing::error!("websocket command failed: {}", detail);
+    }
+    Reply::Error { status: err.status().as_u16(), message: err.message().to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presence_reports_each_viewer_once() {
+        let presence = Presence::default();
+        let mut updates = presence.sender.subscribe();
+        presence.join(1, "alice@example.com", &[7]);
+        presence.join(2, "alice@example.com", &[7]);
+        presence.join(3, "bob@example.com", &[7]);
+        let viewers = |update: PresenceUpdate| update.viewers;
+        assert_eq!(viewers(updates.try_recv().unwrap()), ["alice@example.com"]);
+        assert_eq!(viewers(updates.try_recv().unwrap()), ["alice@example.com"]);
+        assert_eq!(viewers(updates.try_recv().unwrap()), ["alice@example.com", "bob@example.com"]);
+        presence.leave(1, &[7]);
+        assert_eq!(viewers(updates.try_recv().unwrap()), ["alice@example.com", "bob@example.com"]);
+        presence.leave(2, &[7, 8]);
+        assert_eq!(viewers(updates.try_recv().unwrap()), ["bob@example.com"]);
+        assert!(updates.try_recv().is_err());
+    }
+}